@@ -1,5 +1,6 @@
 bitflags::bitflags! {
     /// Possible flags for a page table entry.
+    #[derive(Clone, Copy)]
     pub struct PTEFlags: usize {
         // Attribute fields in stage 1 VMSAv8-64 Block and Page descriptors:
         /// Whether the descriptor is valid.
@@ -91,6 +92,6 @@ impl PTE {
 
     #[inline]
     pub fn new_page(paddr: usize, flags: PTEFlags) -> Self {
-        Self(paddr | flags.bits() as usize)
+        Self(paddr | flags.bits())
     }
 }
@@ -0,0 +1,14 @@
+//! 内核使用的过程宏
+
+use proc_macro::TokenStream;
+
+/// 将函数放在 `.boot.text` 段中，对应 seL4 的 `BOOT_CODE`
+///
+/// `.boot` 段在启动完成后会被回收，因此只能标注仅在启动阶段调用的函数
+#[proc_macro_attribute]
+pub fn boot_code(attr: TokenStream, item: TokenStream) -> TokenStream {
+    assert!(attr.is_empty(), "boot_code does not take arguments");
+    let mut output: TokenStream = r#"#[unsafe(link_section = ".boot.text")]"#.parse().unwrap();
+    output.extend(item);
+    output
+}
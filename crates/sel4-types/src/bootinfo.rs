@@ -0,0 +1,144 @@
+//! root task 启动信息，与 libsel4 中的 `bootinfo_types.h` 保持一致
+
+/// 空 slot
+pub const CAP_NULL: usize = 0;
+/// root task 的 TCB
+pub const CAP_INIT_THREAD_TCB: usize = 1;
+/// root task 的 CNode
+pub const CAP_INIT_THREAD_CNODE: usize = 2;
+/// root task 的 VSpace
+pub const CAP_INIT_THREAD_VSPACE: usize = 3;
+/// 全局 IRQ 控制
+pub const CAP_IRQ_CONTROL: usize = 4;
+/// 全局 ASID 控制
+pub const CAP_ASID_CONTROL: usize = 5;
+/// root task 的 ASID pool
+pub const CAP_INIT_THREAD_ASID_POOL: usize = 6;
+/// 全局 IO Port 控制，仅 x86 使用
+pub const CAP_IO_PORT_CONTROL: usize = 7;
+/// 全局 IO Space，仅在开启 IOMMU 时使用
+pub const CAP_IO_SPACE: usize = 8;
+/// BootInfo 页
+pub const CAP_BOOT_INFO_FRAME: usize = 9;
+/// root task 的 IPC buffer
+pub const CAP_INIT_THREAD_IPC_BUFFER: usize = 10;
+/// 全局 domain 控制
+pub const CAP_DOMAIN: usize = 11;
+/// 全局 SMMU SID 控制，仅在开启 SMMU 时使用
+pub const CAP_SMMU_SID_CONTROL: usize = 12;
+/// 全局 SMMU CB 控制，仅在开启 SMMU 时使用
+pub const CAP_SMMU_CB_CONTROL: usize = 13;
+/// root task 的调度上下文，仅 MCS 使用
+pub const CAP_INIT_THREAD_SC: usize = 14;
+/// SMC 调用，仅在允许 SMC 调用时使用
+pub const CAP_SMC: usize = 15;
+/// 初始 capability 的数量
+pub const NUM_INITIAL_CAPS: usize = 16;
+
+/// BootInfo 中最多记录的 untyped 数量，对应 `CONFIG_MAX_NUM_BOOTINFO_UNTYPED_CAPS`
+pub const MAX_NUM_BOOTINFO_UNTYPED_CAPS: usize = 230;
+
+/// 额外启动信息中的填充
+pub const SEL4_BOOTINFO_HEADER_PADDING: usize = 0;
+// 以下类型仅 x86 使用
+pub const SEL4_BOOTINFO_HEADER_X86_VBE: usize = 1;
+pub const SEL4_BOOTINFO_HEADER_X86_MBMMAP: usize = 2;
+pub const SEL4_BOOTINFO_HEADER_X86_ACPI_RSDP: usize = 3;
+pub const SEL4_BOOTINFO_HEADER_X86_FRAMEBUFFER: usize = 4;
+pub const SEL4_BOOTINFO_HEADER_X86_TSC_FREQ: usize = 5;
+/// 额外启动信息中的 DTB
+pub const SEL4_BOOTINFO_HEADER_FDT: usize = 6;
+/// 额外启动信息类型的数量
+pub const SEL4_BOOTINFO_HEADER_NUM: usize = 7;
+
+/// root CNode 中 `[start, end)` 范围的 slot
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SlotRegion {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl SlotRegion {
+    pub const fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+}
+
+/// untyped 的描述，与 `untyped` 中的 slot 一一对应
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UntypedDesc {
+    /// untyped 的物理地址
+    pub paddr: usize,
+    /// untyped 的大小
+    pub size_bits: u8,
+    /// 是否为设备内存
+    pub is_device: u8,
+    pub padding: [u8; 6],
+}
+
+impl UntypedDesc {
+    pub const fn new(paddr: usize, size_bits: usize, is_device: bool) -> Self {
+        Self {
+            paddr,
+            size_bits: size_bits as u8,
+            is_device: is_device as u8,
+            padding: [0; 6],
+        }
+    }
+}
+
+/// root task 的启动信息，对应 `seL4_BootInfo`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BootInfo {
+    /// 额外启动信息的长度
+    pub extra_len: usize,
+    /// 当前节点的编号
+    pub node_id: usize,
+    /// 节点数量
+    pub num_nodes: usize,
+    /// IOMMU 页表的级数
+    pub num_iopt_levels: usize,
+    /// root task IPC buffer 的地址
+    pub ipc_buffer: usize,
+    /// root CNode 中的空 slot
+    pub empty: SlotRegion,
+    /// 共享页
+    pub shared_frames: SlotRegion,
+    /// 用户镜像的页
+    pub user_image_frames: SlotRegion,
+    /// 用户镜像的页表
+    pub user_image_paging: SlotRegion,
+    /// IO Space
+    pub io_space_caps: SlotRegion,
+    /// 额外启动信息的页
+    pub extra_bi_pages: SlotRegion,
+    /// root CNode 的大小
+    pub init_thread_cnode_size_bits: usize,
+    /// root task 所在的 domain
+    pub init_thread_domain: usize,
+    /// untyped
+    pub untyped: SlotRegion,
+    /// untyped 的描述，前 `untyped.end - untyped.start` 项有效
+    pub untyped_list: [UntypedDesc; MAX_NUM_BOOTINFO_UNTYPED_CAPS],
+}
+
+/// 额外启动信息的头部，后面紧跟 `len - size_of::<BootInfoHeader>()` 字节的内容
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BootInfoHeader {
+    /// 额外启动信息的类型，例如 [SEL4_BOOTINFO_HEADER_FDT]
+    pub id: usize,
+    /// 包括头部在内的长度
+    pub len: usize,
+}
+
+const fn _check_type_width() {
+    assert!(size_of::<SlotRegion>() == 2 * size_of::<usize>());
+    assert!(size_of::<UntypedDesc>() == 2 * size_of::<usize>());
+    assert!(size_of::<BootInfoHeader>() == 2 * size_of::<usize>());
+    assert!(core::mem::offset_of!(BootInfo, untyped_list) == 21 * size_of::<usize>());
+}
+const _: () = _check_type_width();
@@ -0,0 +1,17 @@
+//! 系统调用错误码，与 libsel4 中的 `seL4_Error` 保持一致
+
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sel4Error {
+    NoError = 0,
+    InvalidArgument,
+    InvalidCapability,
+    IllegalOperation,
+    RangeError,
+    AlignmentError,
+    FailedLookup,
+    TruncatedMessage,
+    DeleteFirst,
+    RevokeFirst,
+    NotEnoughMemory,
+}
@@ -0,0 +1,96 @@
+//! 内核对象的调用编号，与 libsel4 生成的 `invocation_label` 保持一致
+//!
+//! 编号对应非 MCS、未开启 SMP 和硬件调试接口的配置
+
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvocationLabel {
+    InvalidInvocation = 0,
+    UntypedRetype,
+    TCBReadRegisters,
+    TCBWriteRegisters,
+    TCBCopyRegisters,
+    TCBConfigure,
+    TCBSetPriority,
+    TCBSetMCPriority,
+    TCBSetSchedParams,
+    TCBSetIPCBuffer,
+    TCBSetSpace,
+    TCBSuspend,
+    TCBResume,
+    TCBBindNotification,
+    TCBUnbindNotification,
+    TCBSetTLSBase,
+    CNodeRevoke,
+    CNodeDelete,
+    CNodeCancelBadgedSends,
+    CNodeCopy,
+    CNodeMint,
+    CNodeMove,
+    CNodeMutate,
+    CNodeRotate,
+    CNodeSaveCaller,
+    IRQIssueIRQHandler,
+    IRQAckIRQ,
+    IRQSetIRQHandler,
+    IRQClearIRQHandler,
+    DomainSetSet,
+}
+
+/// 通用调用编号的数量，架构相关的调用编号从这里开始
+pub const N_INVOCATION_LABELS: usize = InvocationLabel::DomainSetSet as usize + 1;
+
+impl InvocationLabel {
+    /// 从消息标签中的 label 转换，超出范围时返回 [InvocationLabel::InvalidInvocation]
+    pub const fn from_raw(raw: usize) -> Self {
+        const LABELS: [InvocationLabel; N_INVOCATION_LABELS] = {
+            use InvocationLabel::*;
+            [
+                InvalidInvocation,
+                UntypedRetype,
+                TCBReadRegisters,
+                TCBWriteRegisters,
+                TCBCopyRegisters,
+                TCBConfigure,
+                TCBSetPriority,
+                TCBSetMCPriority,
+                TCBSetSchedParams,
+                TCBSetIPCBuffer,
+                TCBSetSpace,
+                TCBSuspend,
+                TCBResume,
+                TCBBindNotification,
+                TCBUnbindNotification,
+                TCBSetTLSBase,
+                CNodeRevoke,
+                CNodeDelete,
+                CNodeCancelBadgedSends,
+                CNodeCopy,
+                CNodeMint,
+                CNodeMove,
+                CNodeMutate,
+                CNodeRotate,
+                CNodeSaveCaller,
+                IRQIssueIRQHandler,
+                IRQAckIRQ,
+                IRQSetIRQHandler,
+                IRQClearIRQHandler,
+                DomainSetSet,
+            ]
+        };
+        if raw < N_INVOCATION_LABELS {
+            LABELS[raw]
+        } else {
+            Self::InvalidInvocation
+        }
+    }
+}
+
+const fn _check_labels() {
+    let mut i = 0;
+    while i < N_INVOCATION_LABELS {
+        assert!(InvocationLabel::from_raw(i) as usize == i);
+        i += 1;
+    }
+}
+const _: () = _check_labels();
@@ -1 +1,9 @@
 #![no_std]
+
+pub mod bootinfo;
+pub mod error;
+pub mod invocation;
+pub mod message;
+pub mod object;
+pub mod rights;
+pub mod syscall;
@@ -0,0 +1,55 @@
+//! 消息标签，与 libsel4 中的 `seL4_MessageInfo_t` 保持一致
+
+/// 消息的最大长度，对应 seL4 的 `seL4_MsgMaxLength`
+pub const MSG_MAX_LENGTH: usize = 120;
+/// 消息中附带的 capability 的最大数量，对应 seL4 的 `seL4_MsgMaxExtraCaps`
+pub const MSG_MAX_EXTRA_CAPS: usize = 3;
+
+/// 通过 x1 传递的消息标签，从高位到低位依次为 52 位的 label、3 位的 `capsUnwrapped`、
+/// 2 位的 `extraCaps` 和 7 位的 `length`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageInfo {
+    pub label: usize,
+    pub caps_unwrapped: usize,
+    pub extra_caps: usize,
+    pub length: usize,
+}
+
+impl MessageInfo {
+    pub const fn new(
+        label: usize,
+        caps_unwrapped: usize,
+        extra_caps: usize,
+        length: usize,
+    ) -> Self {
+        Self {
+            label,
+            caps_unwrapped,
+            extra_caps,
+            length,
+        }
+    }
+
+    /// 从寄存器中的值转换，长度超过 [MSG_MAX_LENGTH] 时截断，对应 seL4 的 `messageInfoFromWord`
+    pub const fn from_word(word: usize) -> Self {
+        let length = word & 0x7f;
+        Self {
+            label: word >> 12,
+            caps_unwrapped: (word >> 9) & 0x7,
+            extra_caps: (word >> 7) & 0x3,
+            length: if length > MSG_MAX_LENGTH {
+                MSG_MAX_LENGTH
+            } else {
+                length
+            },
+        }
+    }
+
+    /// 对应 seL4 的 `wordFromMessageInfo`
+    pub const fn to_word(self) -> usize {
+        (self.label << 12)
+            | ((self.caps_unwrapped & 0x7) << 9)
+            | ((self.extra_caps & 0x3) << 7)
+            | (self.length & 0x7f)
+    }
+}
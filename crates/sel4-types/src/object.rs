@@ -0,0 +1,109 @@
+//! 内核对象的类型和大小，与 libsel4 中的 `seL4_ObjectType` 和 `seL4_*Bits` 保持一致
+//!
+//! 编号和大小对应 aarch64、未开启虚拟化和 SMMU 的配置
+
+/// CTE 大小，对应 `seL4_SlotBits`
+pub const SLOT_BITS: usize = 5;
+/// TCB 对象大小，对应 `seL4_TCBBits`
+pub const TCB_BITS: usize = 11;
+/// endpoint 对象大小，对应 `seL4_EndpointBits`
+pub const ENDPOINT_BITS: usize = 4;
+/// notification 对象大小，对应 `seL4_NotificationBits`
+pub const NOTIFICATION_BITS: usize = 5;
+/// 4K 页大小，对应 `seL4_PageBits`
+pub const PAGE_BITS: usize = 12;
+/// 2M 页大小，对应 `seL4_LargePageBits`
+pub const LARGE_PAGE_BITS: usize = 21;
+/// 1G 页大小，对应 `seL4_HugePageBits`
+pub const HUGE_PAGE_BITS: usize = 30;
+/// 页表大小，对应 `seL4_PageTableBits`
+pub const PAGE_TABLE_BITS: usize = 12;
+/// VSpace 大小，对应 `seL4_VSpaceBits`
+pub const VSPACE_BITS: usize = 12;
+/// untyped 最小的大小，对应 `seL4_MinUntypedBits`
+pub const MIN_UNTYPED_BITS: usize = 4;
+/// untyped 最大的大小，对应 `seL4_MaxUntypedBits`
+pub const MAX_UNTYPED_BITS: usize = 47;
+
+/// `Untyped_Retype` 可以创建的对象类型
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum ObjectType {
+    Untyped = 0,
+    TCB,
+    Endpoint,
+    Notification,
+    CapTable,
+    /// 以下为架构相关的对象，对应 `seL4_ModeObjectType` 和 `seL4_ArchObjectType`
+    HugePage,
+    VSpace,
+    SmallPage,
+    LargePage,
+    PageTable,
+}
+
+/// 通用对象类型的数量，对应 `seL4_NonArchObjectTypeCount`
+pub const NON_ARCH_OBJECT_TYPE_COUNT: usize = ObjectType::HugePage as usize;
+/// 对象类型的数量，对应 `seL4_ObjectTypeCount`
+pub const OBJECT_TYPE_COUNT: usize = ObjectType::PageTable as usize + 1;
+
+impl ObjectType {
+    /// 从调用参数转换，超出范围时返回 `None`
+    pub const fn from_raw(raw: usize) -> Option<Self> {
+        const TYPES: [ObjectType; OBJECT_TYPE_COUNT] = {
+            use ObjectType::*;
+            [
+                Untyped,
+                TCB,
+                Endpoint,
+                Notification,
+                CapTable,
+                HugePage,
+                VSpace,
+                SmallPage,
+                LargePage,
+                PageTable,
+            ]
+        };
+        if raw < OBJECT_TYPE_COUNT {
+            Some(TYPES[raw])
+        } else {
+            None
+        }
+    }
+
+    /// 是否为页，设备内存中只能创建页和 untyped
+    pub const fn is_frame(self) -> bool {
+        matches!(self, Self::SmallPage | Self::LargePage | Self::HugePage)
+    }
+
+    /// 对象的大小
+    ///
+    /// `user_size` 为 untyped 的大小或 CNode 的 radix，其他类型忽略
+    pub const fn object_size_bits(self, user_size: usize) -> usize {
+        match self {
+            Self::Untyped => user_size,
+            Self::TCB => TCB_BITS,
+            Self::Endpoint => ENDPOINT_BITS,
+            Self::Notification => NOTIFICATION_BITS,
+            Self::CapTable => SLOT_BITS + user_size,
+            Self::HugePage => HUGE_PAGE_BITS,
+            Self::VSpace => VSPACE_BITS,
+            Self::SmallPage => PAGE_BITS,
+            Self::LargePage => LARGE_PAGE_BITS,
+            Self::PageTable => PAGE_TABLE_BITS,
+        }
+    }
+}
+
+const fn _check_types() {
+    let mut i = 0;
+    while i < OBJECT_TYPE_COUNT {
+        assert!(matches!(ObjectType::from_raw(i), Some(t) if t as usize == i));
+        i += 1;
+    }
+    assert!(ObjectType::from_raw(OBJECT_TYPE_COUNT).is_none());
+}
+const _: () = _check_types();
+const _: () = assert!(NON_ARCH_OBJECT_TYPE_COUNT == 5);
@@ -0,0 +1,29 @@
+//! capability 的访问权限，与 libsel4 中的 `seL4_CapRights_t` 保持一致
+
+bitflags::bitflags! {
+    /// capability 的访问权限，作为 CNode_Mint 和 CNode_Copy 的参数，
+    /// 低 4 位依次为 `capAllowWrite`、`capAllowRead`、`capAllowGrant` 和 `capAllowGrantReply`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct CapRights: usize {
+        /// endpoint 和 notification 的发送权限，页的写权限
+        const WRITE = 1 << 0;
+        /// endpoint 和 notification 的接收权限，页的读权限
+        const READ = 1 << 1;
+        /// 通过 endpoint 传递任意 capability，reply 时同样可以传递 capability
+        const GRANT = 1 << 2;
+        /// 通过 endpoint Call 时允许接收方获得 reply capability
+        const GRANT_REPLY = 1 << 3;
+    }
+}
+
+impl CapRights {
+    /// 从消息中的权限字转换，忽略未定义的位，对应 seL4 的 `rightsFromWord`
+    pub const fn from_word(word: usize) -> Self {
+        Self::from_bits_truncate(word)
+    }
+
+    /// 对应 seL4 的 `wordFromRights`
+    pub const fn to_word(self) -> usize {
+        self.bits()
+    }
+}
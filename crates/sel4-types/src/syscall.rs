@@ -0,0 +1,49 @@
+//! 系统调用编号，与 libsel4 生成的 `seL4_Syscall_ID` 保持一致
+//!
+//! 编号对应非 MCS 的配置，系统调用编号通过 x7 传入内核
+
+#[repr(isize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syscall {
+    Call = -1,
+    ReplyRecv = -2,
+    Send = -3,
+    NBSend = -4,
+    Recv = -5,
+    Reply = -6,
+    Yield = -7,
+    NBRecv = -8,
+    DebugPutChar = -9,
+    DebugDumpScheduler = -10,
+    DebugHalt = -11,
+    DebugCapIdentify = -12,
+    DebugSnapshot = -13,
+    DebugNameThread = -14,
+    /// 检查内核中所有 MDB 的不变量，seL4 中没有对应的系统调用，编号不与 seL4 的调试和 benchmark 调用重叠
+    DebugCheckMdb = -32,
+}
+
+impl Syscall {
+    /// 从 x7 中的编号转换，不是系统调用编号时返回 `None`
+    pub const fn from_raw(raw: usize) -> Option<Self> {
+        use Syscall::*;
+        Some(match raw as isize {
+            -1 => Call,
+            -2 => ReplyRecv,
+            -3 => Send,
+            -4 => NBSend,
+            -5 => Recv,
+            -6 => Reply,
+            -7 => Yield,
+            -8 => NBRecv,
+            -9 => DebugPutChar,
+            -10 => DebugDumpScheduler,
+            -11 => DebugHalt,
+            -12 => DebugCapIdentify,
+            -13 => DebugSnapshot,
+            -14 => DebugNameThread,
+            -32 => DebugCheckMdb,
+            _ => return None,
+        })
+    }
+}
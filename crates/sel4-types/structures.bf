@@ -261,3 +261,79 @@ block notification {
 #endif
     field state 2
 }
+
+---- AArch64 specific caps
+
+block frame_cap {
+    field capFMappedASID             16
+    field_high capFBasePtr           48
+
+    field capType                    5
+    field capFSize                   2
+    field_high capFMappedAddress     48
+    field capFVMRights               2
+    field capFIsDevice               1
+    padding                          6
+}
+
+-- Page table caps
+block page_table_cap {
+    field capPTMappedASID            16
+    field_high capPTBasePtr          48
+
+    field capType                    5
+    padding                          10
+    field capPTIsMapped              1
+    field_high capPTMappedAddress    28
+    padding                          20
+}
+
+-- First-level page table (vspace_root)
+block vspace_cap {
+    field capVSMappedASID            16
+    field_high capVSBasePtr          48
+
+    field capType                    5
+    field capVSIsMapped              1
+    padding                          58
+}
+
+-- Cap to the table of 2^7 ASID pools
+block asid_control_cap {
+    padding                          64
+
+    field capType                    5
+    padding                          59
+}
+
+-- Cap to a pool of 2^9 ASIDs
+block asid_pool_cap {
+    padding                          64
+
+    field capType                    5
+    field capASIDBase                16
+    padding                          6
+    field_high capASIDPool           37
+}
+
+tagged_union cap capType {
+    -- 5-bit tag caps
+    tag null_cap                    0
+    tag untyped_cap                 2
+    tag endpoint_cap                4
+    tag notification_cap            6
+    tag reply_cap                   8
+    tag cnode_cap                   10
+    tag thread_cap                  12
+    tag irq_control_cap             14
+    tag irq_handler_cap             16
+    tag zombie_cap                  18
+    tag domain_cap                  20
+
+    -- 5-bit tag arch caps
+    tag frame_cap                   1
+    tag page_table_cap              3
+    tag vspace_cap                  9
+    tag asid_control_cap            11
+    tag asid_pool_cap               13
+}
@@ -11,8 +11,21 @@ fn main() {
     // write module configuration to OUT_PATH, then it will be included in the main.rs
     // let _platform = env::var("CARGO_CFG_BOARD").expect("can't find board");
     // TODO: using `_platform` isntead of `qemu` in the future
-    gen_linker_script("qemu").expect("can't generate linker script");
+    // host 上运行单元测试时使用默认的链接脚本
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("none") {
+        gen_linker_script("qemu").expect("can't generate linker script");
+    }
+    let smp = env::var("SMP").unwrap_or_else(|_| "1".into());
+    println!("cargo:rustc-env=MAX_NUM_NODES={}", smp);
+    println!("cargo:rerun-if-env-changed=SMP");
+    let num_domains = env::var("NUM_DOMAINS").unwrap_or_else(|_| "1".into());
+    println!("cargo:rustc-env=NUM_DOMAINS={}", num_domains);
+    println!("cargo:rerun-if-env-changed=NUM_DOMAINS");
+    let domain_schedule = env::var("DOMAIN_SCHEDULE").unwrap_or_else(|_| "0:1".into());
+    println!("cargo:rustc-env=DOMAIN_SCHEDULE={}", domain_schedule);
+    println!("cargo:rerun-if-env-changed=DOMAIN_SCHEDULE");
     println!("cargo:rerun-if-env-changed=CARGO_CFG_TARGET_ARCH");
+    println!("cargo:rerun-if-env-changed=CARGO_CFG_TARGET_OS");
     println!("cargo:rerun-if-env-changed=CARGO_CFG_BOARD");
     println!("cargo:rerun-if-changed=build.rs");
     println!("cargo:rerun-if-changed=linker.lds");
@@ -0,0 +1,74 @@
+//! 内核操作的失败类型，对应 seL4 `api/failures.h` 中的 `exception_t` 和 `syscall_error_t`
+
+use sel4_types::error::Sel4Error;
+
+use crate::object::fault::{Fault, LookupFault};
+
+/// 返回给用户的系统调用错误，附带 seL4 在 IPC buffer 中返回的额外信息
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum SyscallError {
+    InvalidArgument {
+        number: usize,
+    },
+    InvalidCapability {
+        number: usize,
+    },
+    IllegalOperation,
+    RangeError {
+        min: usize,
+        max: usize,
+    },
+    AlignmentError,
+    /// 查找 capability 失败，`was_source` 表示失败的是否为源 slot
+    FailedLookup {
+        was_source: bool,
+        fault: LookupFault,
+    },
+    TruncatedMessage,
+    DeleteFirst,
+    RevokeFirst,
+    NotEnoughMemory {
+        memory_left: usize,
+    },
+}
+
+impl SyscallError {
+    /// 对应的 `seL4_Error`
+    pub const fn code(&self) -> Sel4Error {
+        match self {
+            Self::InvalidArgument { .. } => Sel4Error::InvalidArgument,
+            Self::InvalidCapability { .. } => Sel4Error::InvalidCapability,
+            Self::IllegalOperation => Sel4Error::IllegalOperation,
+            Self::RangeError { .. } => Sel4Error::RangeError,
+            Self::AlignmentError => Sel4Error::AlignmentError,
+            Self::FailedLookup { .. } => Sel4Error::FailedLookup,
+            Self::TruncatedMessage => Sel4Error::TruncatedMessage,
+            Self::DeleteFirst => Sel4Error::DeleteFirst,
+            Self::RevokeFirst => Sel4Error::RevokeFirst,
+            Self::NotEnoughMemory { .. } => Sel4Error::NotEnoughMemory,
+        }
+    }
+}
+
+/// 对应 seL4 的 `exception_t`，`EXCEPTION_NONE` 使用 [Ok] 表示
+#[derive(PartialEq, Debug)]
+pub enum Exception {
+    /// 需要向线程的 fault handler 发送 fault
+    Fault(Fault),
+    LookupFault(LookupFault),
+    SyscallError(SyscallError),
+    /// 长时间运行的操作被中断打断，重新执行系统调用时继续
+    Preempted,
+}
+
+impl From<SyscallError> for Exception {
+    fn from(err: SyscallError) -> Self {
+        Self::SyscallError(err)
+    }
+}
+
+impl From<LookupFault> for Exception {
+    fn from(fault: LookupFault) -> Self {
+        Self::LookupFault(fault)
+    }
+}
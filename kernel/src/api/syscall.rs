@@ -0,0 +1,212 @@
+//! 系统调用的处理，对应 seL4 `api/syscall.c`
+//!
+//! Send、NBSend 和 Call 调用 x0 中的 capability，消息标签位于 x1，参数依次位于消息寄存器和
+//! IPC buffer 中，附带的 capability 的 CPtr 位于 IPC buffer 中的 `caps_or_badges`。
+
+use sel4_types::{
+    invocation::InvocationLabel,
+    message::{MessageInfo, MSG_MAX_EXTRA_CAPS, MSG_MAX_LENGTH},
+};
+
+use super::failures::Exception;
+use crate::{
+    arch::{lookup_ipc_buffer, CAP_REGISTER, MSG_INFO_REGISTER, MSG_REGISTERS},
+    kernel::thread::{
+        cur_thread, reply_from_kernel_error, reply_from_kernel_success_empty, set_thread_state,
+    },
+    object::{
+        cap::{Cap, Cte},
+        cspace::lookup_slot,
+        fault::{LookupFault, ThreadStateType},
+        objecttype::decode_invocation,
+        tcb::{TcbCnodeIndex, TCB},
+    },
+};
+
+/// IPC buffer 中 `caps_or_badges` 的位置，位于消息标签、消息和 `userData` 之后，
+/// 对应 seL4 的 `getExtraCPtr`
+const EXTRA_CAPS_OFFSET: usize = MSG_MAX_LENGTH + 2;
+
+/// 处理 Send、NBSend 和 Call 中对内核对象的调用，对应 seL4 的 `handleInvocation`
+///
+/// 调用出错时只有通过 Call 发起的调用会收到错误信息。被抢占时返回 [Exception::Preempted]，
+/// 当前线程保持 `Restart` 状态，返回用户态时重新执行系统调用
+pub fn handle_invocation(is_call: bool) -> Result<(), Exception> {
+    let thread = unsafe { &mut *cur_thread() };
+    let info = MessageInfo::from_word(thread.context().get_register(MSG_INFO_REGISTER));
+    let cptr = thread.context().get_register(CAP_REGISTER);
+    let cspace_root = **thread.cte(TcbCnodeIndex::CTable);
+
+    // TODO: 查找失败时产生 CapFault，阻塞的调用通过 handleFault 发送给 fault handler
+    let Ok(slot) = lookup_slot(cspace_root, cptr) else {
+        return Ok(());
+    };
+    let ipc_buffer = lookup_ipc_buffer(false, thread);
+    let mut extra_caps = [core::ptr::null_mut(); MSG_MAX_EXTRA_CAPS];
+    let Ok(extra_caps) = lookup_extra_caps(cspace_root, ipc_buffer, info, &mut extra_caps) else {
+        return Ok(());
+    };
+
+    let length = match ipc_buffer {
+        Some(_) => info.length,
+        None => info.length.min(MSG_REGISTERS.len()),
+    };
+    let mut args = [0; MSG_MAX_LENGTH];
+    for (i, arg) in args[..length].iter_mut().enumerate() {
+        *arg = get_syscall_arg(thread, ipc_buffer, i);
+    }
+
+    let label = InvocationLabel::from_raw(info.label);
+    let status = unsafe { decode_invocation(label, slot, &args[..length], extra_caps, is_call) };
+
+    // 调用的过程中可能修改了当前线程，重新获取引用
+    let thread = unsafe { &mut *cur_thread() };
+    match status {
+        Ok(()) => {}
+        Err(Exception::SyscallError(err)) => {
+            if is_call {
+                reply_from_kernel_error(thread, err);
+            }
+            return Ok(());
+        }
+        Err(err) => return Err(err),
+    }
+    if thread.state().ts_type() == ThreadStateType::Restart {
+        if is_call {
+            reply_from_kernel_success_empty(thread);
+        }
+        set_thread_state(thread, ThreadStateType::Running);
+    }
+    Ok(())
+}
+
+/// 查找消息中附带的 capability 所在的 slot，没有 IPC buffer 时不附带 capability，
+/// 对应 seL4 的 `lookupExtraCaps`
+fn lookup_extra_caps(
+    cspace_root: Cap,
+    ipc_buffer: Option<*mut usize>,
+    info: MessageInfo,
+    slots: &mut [*mut Cte; MSG_MAX_EXTRA_CAPS],
+) -> Result<&[*mut Cte], LookupFault> {
+    let Some(buffer) = ipc_buffer else {
+        return Ok(&[]);
+    };
+    for (i, slot) in slots[..info.extra_caps].iter_mut().enumerate() {
+        let cptr = unsafe { *buffer.add(EXTRA_CAPS_OFFSET + i) };
+        *slot = lookup_slot(cspace_root, cptr)?;
+    }
+    Ok(&slots[..info.extra_caps])
+}
+
+/// 第 `i` 个参数，超出消息寄存器的部分位于 IPC buffer 中，对应 seL4 的 `getSyscallArg`
+fn get_syscall_arg(thread: &mut TCB, ipc_buffer: Option<*mut usize>, i: usize) -> usize {
+    if i < MSG_REGISTERS.len() {
+        return thread.context().get_register(MSG_REGISTERS[i]);
+    }
+    match ipc_buffer {
+        Some(buffer) => unsafe { *buffer.add(i + 1) },
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sel4_types::error::Sel4Error;
+
+    use super::*;
+    use crate::{
+        arch::{set_irq_pending, FAULT_IP, NEXT_IP},
+        config::MAX_NUM_WORK_UNITS_PER_PREEMPTION,
+        kernel::thread::activate_thread,
+        model::{preemption::reset_work_units, statedata::node_state},
+        object::{
+            cnode::cte_insert,
+            structures::{EndpointCap, ThreadCap},
+            test_utils::{alloc_tcb, cnode_cap, slot, write},
+        },
+    };
+
+    /// 当前线程的 CSpace 为一级 radix 为 8 的 CNode，slot 1 中为该 CNode 自身
+    fn setup() -> (&'static mut TCB, Cap) {
+        let root = cnode_cap(8, 56, 0);
+        write(root, 1, root);
+        let thread = alloc_tcb();
+        thread.state_mut().set_ts_type(ThreadStateType::Running);
+        **thread.cte(TcbCnodeIndex::CTable) = root;
+        unsafe { (*node_state()).cur_thread = thread };
+        (thread, root)
+    }
+
+    fn set_message(thread: &mut TCB, cptr: usize, label: InvocationLabel, args: &[usize]) {
+        let context = thread.context();
+        context.set_register(CAP_REGISTER, cptr);
+        let info = MessageInfo::new(label as usize, 0, 0, args.len());
+        context.set_register(MSG_INFO_REGISTER, info.to_word());
+        for (&reg, &arg) in MSG_REGISTERS.iter().zip(args) {
+            context.set_register(reg, arg);
+        }
+    }
+
+    #[test]
+    fn call_replies_error() {
+        let (thread, _) = setup();
+        set_message(thread, 1, InvocationLabel::UntypedRetype, &[]);
+        handle_invocation(true).unwrap();
+
+        let info = MessageInfo::from_word(thread.context().get_register(MSG_INFO_REGISTER));
+        assert_eq!(info.label, Sel4Error::IllegalOperation as usize);
+        assert_eq!(info.length, 0);
+        assert_eq!(thread.state().ts_type(), ThreadStateType::Running);
+    }
+
+    #[test]
+    fn call_read_registers_keeps_reply() {
+        let (thread, root) = setup();
+        let target = alloc_tcb();
+        target.context().set_register(FAULT_IP, 0x4000);
+        write(root, 2, ThreadCap::new(target.ptr()).into());
+        set_message(thread, 2, InvocationLabel::TCBReadRegisters, &[0, 2]);
+        handle_invocation(true).unwrap();
+
+        let info = MessageInfo::from_word(thread.context().get_register(MSG_INFO_REGISTER));
+        assert_eq!(info, MessageInfo::new(0, 0, 0, 2));
+        assert_eq!(thread.context().get_register(MSG_REGISTERS[0]), 0x4000);
+        assert_eq!(thread.state().ts_type(), ThreadStateType::Running);
+    }
+
+    #[test]
+    fn preempted_revoke_resumes() {
+        let (thread, root) = setup();
+        let children = MAX_NUM_WORK_UNITS_PER_PREEMPTION + 50;
+        let ep: Cap = EndpointCap::new(0, true, true, true, true, 0x1000).into();
+        write(root, 2, ep);
+        unsafe { (*slot(root, 2)).set_revocable(true) };
+        for i in 0..children {
+            unsafe { cte_insert(ep, slot(root, 2), slot(root, 3 + i)) };
+        }
+        let remaining = || {
+            (3..3 + children)
+                .filter(|&i| unsafe { !(*slot(root, i)).is_null() })
+                .count()
+        };
+        thread.context().set_register(FAULT_IP, 0x1000);
+        thread.context().set_register(NEXT_IP, 0x1004);
+        set_message(thread, 1, InvocationLabel::CNodeRevoke, &[2, 64]);
+
+        reset_work_units();
+        set_irq_pending(true);
+        assert_eq!(handle_invocation(true), Err(Exception::Preempted));
+        assert_eq!(thread.state().ts_type(), ThreadStateType::Restart);
+        assert_eq!(remaining(), 50);
+
+        // 重新执行触发系统调用的指令
+        activate_thread();
+        assert_eq!(thread.context().get_register(NEXT_IP), 0x1000);
+        set_irq_pending(false);
+        handle_invocation(true).unwrap();
+        assert_eq!(thread.state().ts_type(), ThreadStateType::Running);
+        assert_eq!(remaining(), 0);
+        assert!(unsafe { !(*slot(root, 2)).is_null() });
+        assert_eq!(thread.context().get_register(MSG_INFO_REGISTER), 0);
+    }
+}
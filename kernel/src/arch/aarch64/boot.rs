@@ -1,31 +1,92 @@
 use core::arch::naked_asm;
 
+use macros::boot_code;
+
 use crate::{
-    arch::aarch64::{cpu, vspace::map_kernel_window},
-    driver::system_off,
+    arch::aarch64::{
+        cpu, restore_user_context,
+        smp::{release_secondary_cpus, start_secondary_cpus},
+        vspace::{map_kernel_devices, map_kernel_window, reclaim_boot_region},
+        PAGE_TABLE_BITS,
+    },
+    arch::RegionList,
+    boot::{
+        boot_mem_reuse_p_reg, create_idle_thread, freemem::MemoryMap, kernel_image_p_reg,
+        rootserver::RootServer, BootArgs, BootError,
+    },
+    config::{
+        AARCH64_USER_CACHE_ENABLE, ALLOW_UNALIGNED_ACCESS, DEBUG_DISABLE_L1_DCACHE,
+        DEBUG_DISABLE_L1_ICACHE,
+    },
+    kernel::thread::{activate_thread, schedule},
+    platform::{self, MAX_NUM_RESV_REGIONS, PLATFORM},
 };
 
+/// SCTLR_EL1 中的控制位
+const CONTROL_M: usize = 0;
+const CONTROL_A: usize = 1;
+const CONTROL_C: usize = 2;
+const CONTROL_SA0: usize = 4;
+const CONTROL_I: usize = 12;
+const CONTROL_UCT: usize = 15;
+const CONTROL_E0E: usize = 24;
+const CONTROL_EE: usize = 25;
+const CONTROL_UCI: usize = 26;
+
+/// 根据配置选择置位或清零 `bits`，返回 `(set, clear)`
+const fn cr_bits(enable: bool, bits: usize) -> (usize, usize) {
+    match enable {
+        true => (bits, 0),
+        false => (0, bits),
+    }
+}
+
+const CR_ALIGN: (usize, usize) = cr_bits(!ALLOW_UNALIGNED_ACCESS, bit!(CONTROL_A));
+const CR_USER_CACHE_OPS: (usize, usize) = cr_bits(
+    AARCH64_USER_CACHE_ENABLE,
+    bit!(CONTROL_UCT) | bit!(CONTROL_UCI),
+);
+const CR_L1_ICACHE: (usize, usize) = cr_bits(!DEBUG_DISABLE_L1_ICACHE, bit!(CONTROL_I));
+const CR_L1_DCACHE: (usize, usize) = cr_bits(!DEBUG_DISABLE_L1_DCACHE, bit!(CONTROL_C));
+
+/// `_start` 中需要置位的 SCTLR_EL1 位，始终开启 MMU
+const CR_BITS_SET: usize =
+    CR_ALIGN.0 | CR_L1_ICACHE.0 | CR_L1_DCACHE.0 | CR_USER_CACHE_OPS.0 | bit!(CONTROL_M);
+
+/// `_start` 中需要清零的 SCTLR_EL1 位，关闭栈对齐检查，EL1 和 EL0 均使用小端序
+const CR_BITS_CLEAR: usize = CR_ALIGN.1
+    | CR_L1_ICACHE.1
+    | CR_L1_DCACHE.1
+    | CR_USER_CACHE_OPS.1
+    | bit!(CONTROL_SA0)
+    | bit!(CONTROL_EE)
+    | bit!(CONTROL_E0E);
+
 #[unsafe(naked)]
 #[unsafe(no_mangle)]
 unsafe extern "C" fn _start() -> ! {
     naked_asm!(
         include_defines!(),"
         msr     daifset, DAIFSET_MASK
+        msr     tpidr_el1, xzr
 
         msr     spsel, #1
         mrs     x8, sctlr_el1
-        // ldr     x19, =CR_BITS_SET
-        // ldr     x20, =CR_BITS_CLEAR
-        // orr     x8, x8, x19
-        // bic     x8, x8, x20
+        ldr     x19, ={cr_bits_set}
+        ldr     x20, ={cr_bits_clear}
+        orr     x8, x8, x19
+        bic     x8, x8, x20
         msr     sctlr_el1, x8
+        isb
 
         ldr     x8, =kernel_boot_stack_top
         mov     sp, x8
 
-        bl      {main}
+        bl      {init_kernel}
         b       .",
-        main = sym main
+        cr_bits_set = const CR_BITS_SET,
+        cr_bits_clear = const CR_BITS_CLEAR,
+        init_kernel = sym init_kernel
     );
 }
 
@@ -39,24 +100,155 @@ unsafe extern "C" fn _start() -> ! {
     dtb_size,
 )
 */
-extern "C" fn main(
-    _phys_start: usize,
-    _phys_end: usize,
-    _phys_to_virt_offset: isize,
-    _virt_entry: usize,
-    _dtb_addr_p: usize,
-    _dtb_size: usize,
+extern "C" fn init_kernel(
+    ui_p_reg_start: usize,
+    ui_p_reg_end: usize,
+    pv_offset: isize,
+    v_entry: usize,
+    dtb_addr_p: usize,
+    dtb_size: usize,
 ) -> ! {
+    let result = try_init_kernel(
+        ui_p_reg_start,
+        ui_p_reg_end,
+        pv_offset,
+        v_entry,
+        dtb_addr_p,
+        dtb_size,
+    );
+    if let Err(err) = result {
+        panic!("kernel init failed: {:?}", err);
+    }
+
+    release_secondary_cpus();
+    reclaim_boot_region();
+
+    schedule();
+    activate_thread();
+    restore_user_context()
+}
+
+#[boot_code]
+fn try_init_kernel(
+    ui_p_reg_start: usize,
+    ui_p_reg_end: usize,
+    pv_offset: isize,
+    v_entry: usize,
+    dtb_addr_p: usize,
+    dtb_size: usize,
+) -> Result<(), BootError> {
     map_kernel_window();
+    let args = BootArgs::new(
+        ui_p_reg_start,
+        ui_p_reg_end,
+        pv_offset,
+        v_entry,
+        dtb_addr_p,
+        dtb_size,
+    )?;
+    platform::init(args.dtb_p_reg).map_err(BootError::InvalidDtb)?;
+    map_kernel_devices();
+
     cpu::init_cpu();
     cpu::init_plat();
     crate::console::init();
-    log::debug!("phys_start: {:#x}", _phys_start);
-    log::debug!("phys_end: {:#x}", _phys_end);
-    log::debug!("phys_to_virt_offset: {:#x}", _phys_to_virt_offset);
-    log::debug!("virt_entry: {:#x}", _virt_entry);
-    log::debug!("dtb_addr_p: {:#x}", _dtb_addr_p);
-    log::debug!("dtb_size: {:#x}", _dtb_size);
-
-    system_off();
+
+    print_platform_info();
+    let num_nodes = start_secondary_cpus();
+
+    let mut mem_map = init_freemem(&args)?;
+    log::debug!(
+        "free memory: {:#x} bytes in {} regions, {} untypeds",
+        mem_map.free_size(),
+        mem_map.free.len(),
+        mem_map.untypeds(boot_mem_reuse_p_reg()).count()
+    );
+    log::debug!(
+        "user image: [{:#x}, {:#x}) -> [{:#x}, {:#x}), entry: {:#x}",
+        args.ui_p_reg.start.raw(),
+        args.ui_p_reg.end.raw(),
+        args.ui_v_reg().start.raw(),
+        args.ui_v_reg().end.raw(),
+        args.v_entry.raw()
+    );
+    if let Some(dtb) = args.dtb_p_reg {
+        log::debug!("dtb: [{:#x}, {:#x})", dtb.start.raw(), dtb.end.raw());
+    }
+    log::debug!(
+        "ipc buffer: {:#x}, boot info: {:#x}, extra boot info: {:#x}",
+        args.ipc_buf_vptr().raw(),
+        args.bi_frame_vptr().raw(),
+        args.extra_bi_frame_vptr().raw()
+    );
+
+    create_idle_thread();
+    let rootserver = create_rootserver(&mut mem_map, &args, num_nodes)?;
+    log::debug!(
+        "root cnode: {:#x}, vspace: {:#x}, tcb: {:#x}, {} page tables, first free slot: {}",
+        rootserver.cnode.raw(),
+        rootserver.vspace.raw(),
+        rootserver.tcb.raw(),
+        rootserver.paging.size() >> PAGE_TABLE_BITS,
+        rootserver.slot_pos_cur
+    );
+
+    Ok(())
+}
+
+/// 创建 root task 的内核对象，并在 root CNode 中写入初始 capability
+#[boot_code]
+fn create_rootserver(
+    mem_map: &mut MemoryMap,
+    args: &BootArgs,
+    num_nodes: usize,
+) -> Result<RootServer, BootError> {
+    let extra_bi_size = args.extra_bi_size();
+    let mut rootserver = RootServer::new(mem_map, args.it_v_reg(), args.extra_bi_size_bits())?;
+    rootserver.create_root_cnode();
+    rootserver.create_control_caps();
+    rootserver.populate_bi_frame(0, num_nodes, args.ipc_buf_vptr(), extra_bi_size);
+    rootserver.create_it_address_space(args.it_v_reg())?;
+    rootserver.create_frame_caps(args.ipc_buf_vptr(), args.bi_frame_vptr());
+    rootserver.create_extra_bi(args.dtb_p_reg, extra_bi_size, args.extra_bi_frame_vptr())?;
+    rootserver.create_user_image_frames(args.ui_p_reg, args.pv_offset)?;
+    rootserver.create_initial_thread(args.v_entry, args.bi_frame_vptr(), args.ipc_buf_vptr());
+    rootserver.create_untypeds(mem_map)?;
+    rootserver.set_empty_slots();
+    Ok(rootserver)
+}
+
+/// 计算空闲内存，内核镜像、用户镜像、DTB 和平台保留内存都不能作为空闲内存
+#[boot_code]
+fn init_freemem(args: &BootArgs) -> Result<MemoryMap, BootError> {
+    let platform = PLATFORM.lock();
+    let mut reserved = RegionList::<{ MAX_NUM_RESV_REGIONS + 3 }>::new();
+    for resv in platform.reserved.iter() {
+        reserved.push(*resv);
+    }
+    reserved.push(kernel_image_p_reg());
+    reserved.push(args.ui_p_reg);
+    if let Some(dtb) = args.dtb_p_reg {
+        reserved.push(dtb);
+    }
+    MemoryMap::new(platform.memory.as_slice(), reserved.as_slice())
+}
+
+#[boot_code]
+fn print_platform_info() {
+    let platform = PLATFORM.lock();
+    for mem in platform.memory.iter() {
+        log::debug!("memory: [{:#x}, {:#x})", mem.start.raw(), mem.end.raw());
+    }
+    for resv in platform.reserved.iter() {
+        log::debug!("reserved: [{:#x}, {:#x})", resv.start.raw(), resv.end.raw());
+    }
+    log::debug!(
+        "uart: {:#x}, gic: {:#x}/{:#x}, timer irq: {}, psci: {:?}",
+        platform.uart.raw(),
+        platform.gic_dist.raw(),
+        platform.gic_cpu.raw(),
+        platform.kernel_timer_irq(),
+        platform.psci_method
+    );
+    log::debug!("cpus: {:#x?}", &platform.cpus[..platform.num_cpus]);
 }
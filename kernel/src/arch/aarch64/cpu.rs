@@ -1,23 +1,32 @@
-use super::vspace::activate_kernel_vspace;
+use super::{vspace::activate_kernel_vspace, ELR_EL1, SPSR_EL1};
 use crate::{
-    arch::{generic::KERNEL_STACK_ALLOC, PhysAddr, PPTR_BASE},
+    arch::{generic::KERNEL_STACK_ALLOC, VirtAddr},
     config::KERNEL_STACK_BITS,
     driver::{init_irq_controller, init_timer},
+    object::tcb::TCB,
 };
 use aarch64_cpu::{
     asm::barrier::{self, dsb, isb},
-    registers::{Writeable, CNTKCTL_EL1, TPIDR_EL1, VBAR_EL1},
+    registers::{Readable, Writeable, CNTKCTL_EL1, TPIDR_EL1, VBAR_EL1},
 };
 
+/// 当前核的逻辑编号，保存在 TPIDR_EL1 的低位，对应 seL4 的 `getCurrentCPUIndex`
+///
+/// 主核在 `_start` 中将 TPIDR_EL1 清零，次核在入口处写入编号，[init_cpu] 之后高位为内核栈顶
+pub fn get_current_cpu_index() -> usize {
+    TPIDR_EL1.get() as usize & (bit!(KERNEL_STACK_BITS) - 1)
+}
+
 pub fn init_cpu() {
     activate_kernel_vspace();
     unsafe {
-        let stack_top = (&raw mut KERNEL_STACK_ALLOC[0]) as usize + bit!(KERNEL_STACK_BITS);
-        TPIDR_EL1.set(stack_top as _);
+        let index = get_current_cpu_index();
+        let stack_top = (&raw mut KERNEL_STACK_ALLOC[index]) as usize + bit!(KERNEL_STACK_BITS);
+        TPIDR_EL1.set((stack_top | index) as _);
         extern "C" {
             fn arm_vector_table();
         }
-        set_vtable(pa!(arm_vector_table as usize - PPTR_BASE));
+        set_vtable(va!(arm_vector_table as *const () as usize));
     }
     crate::driver::cpu_init_local_irq_controller();
     armv_init_user_access();
@@ -28,10 +37,11 @@ pub fn init_plat() {
     init_irq_controller();
 }
 
-pub fn set_vtable(paddr: PhysAddr) {
-    assert!(paddr.raw() % 4 == 0);
+/// 设置异常向量表，返回用户态后 TTBR0 指向用户地址空间，因此需要使用内核的虚拟地址
+pub fn set_vtable(vaddr: VirtAddr) {
+    assert!(vaddr.raw() % 4 == 0);
     dsb(barrier::SY);
-    VBAR_EL1.set(paddr.raw() as _);
+    VBAR_EL1.set(vaddr.raw() as _);
     isb(barrier::SY);
 }
 
@@ -39,3 +49,42 @@ pub fn armv_init_user_access() {
     // 暴露 Physical Timer 给用户态
     CNTKCTL_EL1.write(CNTKCTL_EL1::EL0PCTEN::SET + CNTKCTL_EL1::EL0PTEN::SET);
 }
+
+/// 是否有等待处理的中断，对应 seL4 的 `isIRQPending`
+///
+/// 内核中屏蔽了中断，ISR_EL1 仍会反映处于 pending 状态的 IRQ 和 FIQ
+pub fn is_irq_pending() -> bool {
+    let isr: usize;
+    unsafe { core::arch::asm!("mrs {}, isr_el1", out(reg) isr) };
+    isr & (bit!(7) | bit!(6)) != 0
+}
+
+/// 将 `[start, end)` 的数据缓存写回到 PoC，使关闭缓存的核也能读到最新的数据
+pub fn clean_dcache_poc(start: VirtAddr, end: VirtAddr) {
+    let ctr: usize;
+    unsafe { core::arch::asm!("mrs {}, ctr_el0", out(reg) ctr) };
+    // CTR_EL0.DminLine 为最小数据缓存行包含的字数的 log2
+    let line = 4 << ((ctr >> 16) & 0xf);
+    for addr in (start.raw() & !(line - 1)..end.raw()).step_by(line) {
+        unsafe { core::arch::asm!("dc cvac, {}", in(reg) addr) };
+    }
+    dsb(barrier::SY);
+}
+
+/// idle 线程的 PSTATE，处于 EL1h 并打开 IRQ，对应 seL4 的 `PSTATE_IDLETHREAD`
+const PSTATE_IDLE_THREAD: usize = bit!(6) | bit!(8) | 0b0101;
+
+/// idle 线程，在 EL1 中等待中断
+///
+/// 返回 idle 线程时 SP 指向线程的寄存器，这里不能使用栈
+#[unsafe(naked)]
+unsafe extern "C" fn idle_thread() -> ! {
+    core::arch::naked_asm!("1: wfi", "b 1b");
+}
+
+/// 设置 idle 线程的入口和 PSTATE，对应 seL4 的 `Arch_configureIdleThread`
+pub fn arch_configure_idle_thread(tcb: &mut TCB) {
+    let context = tcb.context();
+    context.set_register(SPSR_EL1, PSTATE_IDLE_THREAD);
+    context.set_register(ELR_EL1, idle_thread as *const () as usize);
+}
@@ -1,13 +1,79 @@
+#[cfg(not(test))]
 #[macro_use]
 mod macros;
 
+#[cfg(not(test))]
 mod boot;
+#[cfg(not(test))]
 mod cpu;
 mod objects;
+#[cfg(not(test))]
+mod smp;
+#[cfg(not(test))]
 mod traps;
+#[cfg(not(test))]
 mod vspace;
 
-pub use objects::{ArchTCB, UserContext};
+#[cfg(not(test))]
+pub use cpu::{
+    arch_configure_idle_thread, clean_dcache_poc, get_current_cpu_index, is_irq_pending,
+};
+pub use objects::{
+    arch_cap_is_physical, arch_cap_ptr, arch_cap_size_bits, arch_create_object, arch_derive_cap,
+    arch_finalise_cap, arch_is_cap_revocable, arch_mask_cap_rights, arch_object_size_bits,
+    arch_same_object_as, arch_same_region_as, check_valid_ipc_buffer, is_valid_vtable_root,
+    lookup_ipc_buffer, mask_vm_rights, sanitise_register, ArchTCB, AsidPool, UserContext, VmRights,
+    BADGE_REGISTER, CAP_REGISTER, ELR_EL1, FAULT_IP, FRAME_REGISTERS, GP_REGISTERS,
+    IPC_BUFFER_SIZE_BITS, MSG_INFO_REGISTER, MSG_REGISTERS, NEXT_IP, SPSR_EL1, SP_EL0, TLS_BASE,
+    TPIDRRO_EL0, TPIDR_EL0,
+};
+#[cfg(not(test))]
+pub use traps::restore_user_context;
+#[cfg(not(test))]
+pub use vspace::{
+    delete_asid, delete_asid_pool, map_it_frame_cap, map_it_pt_cap, unmap_page, unmap_page_table,
+    write_it_asid_pool,
+};
+
+#[cfg(test)]
+std::thread_local! {
+    static IRQ_PENDING: core::cell::Cell<bool> = const { core::cell::Cell::new(false) };
+}
+
+/// 单元测试中没有中断，通过 [set_irq_pending] 模拟等待处理的中断
+#[cfg(test)]
+pub fn is_irq_pending() -> bool {
+    IRQ_PENDING.get()
+}
+
+/// 设置当前测试线程中是否有等待处理的中断
+#[cfg(test)]
+pub fn set_irq_pending(pending: bool) {
+    IRQ_PENDING.set(pending);
+}
+
+/// 单元测试中只有一个核
+#[cfg(test)]
+pub const fn get_current_cpu_index() -> usize {
+    0
+}
+
+/// 主机上的单元测试不需要维护缓存
+#[cfg(test)]
+pub fn clean_dcache_poc(_start: super::VirtAddr, _end: super::VirtAddr) {}
+
+/// 单元测试中没有用户页表和 ASID，解除映射时不需要处理
+#[cfg(test)]
+pub fn unmap_page(_size: usize, _asid: usize, _vaddr: usize, _pptr: usize) {}
+
+#[cfg(test)]
+pub fn unmap_page_table(_asid: usize, _vaddr: usize, _pt: usize) {}
+
+#[cfg(test)]
+pub fn delete_asid(_asid: usize, _vspace: usize) {}
+
+#[cfg(test)]
+pub fn delete_asid_pool(_asid_base: usize, _pool: usize) {}
 
 const CONTEXT_REGS_NUM: usize = 37;
 
@@ -19,7 +85,43 @@ pub const PPTR_TOP: usize = 0xFFFF_FFFF_C000_0000;
 pub const KERNEL_PT_BASE: usize = 0xFFFF_FFFF_FFE0_0000;
 /// 为 [KERNEL_PT_BASE] 设置的别名
 pub const KDEV_BASE: usize = KERNEL_PT_BASE;
+/// 页大小
+pub const PAGE_BITS: usize = 12;
+/// untyped 最小的大小
+pub const MIN_UNTYPED_BITS: usize = 4;
+/// untyped 最大的大小
+pub const MAX_UNTYPED_BITS: usize = 47;
 /// VSpace 大小
 pub const VSPACE_BITS: usize = 12;
 /// VSpace 索引大小
 pub const VSPACE_INDEX_BITS: usize = 9;
+/// 页表大小
+pub const PAGE_TABLE_BITS: usize = 12;
+/// 页表索引大小
+pub const PT_INDEX_BITS: usize = 9;
+/// TCB 对象大小
+pub const TCB_BITS: usize = 11;
+/// ASID pool 对象大小
+pub const ASID_POOL_BITS: usize = 12;
+/// ASID 高位，用于索引全局 ASID 表
+pub const ASID_HIGH_BITS: usize = 7;
+/// ASID 低位，用于索引 ASID pool
+pub const ASID_LOW_BITS: usize = 9;
+/// 4K 页，对应 seL4 的 `ARMSmallPage`
+pub const ARM_SMALL_PAGE: usize = 0;
+/// 2M 页，对应 seL4 的 `ARMLargePage`
+pub const ARM_LARGE_PAGE: usize = 1;
+/// 1G 页，对应 seL4 的 `ARMHugePage`
+pub const ARM_HUGE_PAGE: usize = 2;
+
+/// 页的大小，对应 seL4 的 `pageBitsForSize`
+pub const fn page_bits_for_size(size: usize) -> usize {
+    match size {
+        ARM_SMALL_PAGE => PAGE_BITS,
+        ARM_LARGE_PAGE => PAGE_BITS + PT_INDEX_BITS,
+        ARM_HUGE_PAGE => PAGE_BITS + 2 * PT_INDEX_BITS,
+        _ => panic!("invalid page size"),
+    }
+}
+/// root task 使用的 ASID
+pub const IT_ASID: usize = 1;
@@ -1,9 +1,393 @@
-use super::CONTEXT_REGS_NUM;
+use sel4_types::{object::ObjectType, rights::CapRights};
 
+use super::{
+    clean_dcache_poc, delete_asid, delete_asid_pool, page_bits_for_size, unmap_page,
+    unmap_page_table, ARM_HUGE_PAGE, ARM_LARGE_PAGE, ARM_SMALL_PAGE, ASID_LOW_BITS, ASID_POOL_BITS,
+    CONTEXT_REGS_NUM, PAGE_TABLE_BITS, VSPACE_BITS,
+};
+use crate::{
+    api::failures::SyscallError,
+    object::{
+        cap::{Cap, CapView},
+        objecttype::FinaliseCap,
+        structures::{FrameCap, PageTableCap, VspaceCap},
+        tcb::{TcbCnodeIndex, TCB},
+    },
+};
+
+/// x0，保存 capability 或 badge，root task 启动时保存 BootInfo 的地址
+pub const CAP_REGISTER: usize = 0;
+/// 对应 seL4 的 `badgeRegister`
+pub const BADGE_REGISTER: usize = CAP_REGISTER;
+/// x1，保存消息的 `seL4_MessageInfo`
+pub const MSG_INFO_REGISTER: usize = 1;
+/// 用户态栈指针
+pub const SP_EL0: usize = 31;
+/// 异常返回地址，即下一条要执行的指令
+pub const ELR_EL1: usize = 32;
+/// 对应 seL4 的 `NextIP`
+pub const NEXT_IP: usize = ELR_EL1;
+/// 异常返回时恢复的 PSTATE
+pub const SPSR_EL1: usize = 33;
+/// 触发异常的指令地址
+pub const FAULT_IP: usize = 34;
+/// 用户态线程指针
+pub const TPIDR_EL0: usize = 35;
+/// 用户态只读线程指针
+pub const TPIDRRO_EL0: usize = 36;
+/// `TCB_SetTLSBase` 设置的寄存器，对应 seL4 的 `TLS_BASE`
+pub const TLS_BASE: usize = TPIDR_EL0;
+
+/// 通过寄存器传递的消息，其余部分位于 IPC buffer 中，对应 seL4 的 `msgRegisters`
+pub const MSG_REGISTERS: [usize; 4] = [2, 3, 4, 5];
+
+/// 异常时保存的寄存器，顺序与 `seL4_UserContext` 一致，对应 seL4 的 `frameRegisters`
+pub const FRAME_REGISTERS: [usize; 17] = [
+    FAULT_IP, SP_EL0, SPSR_EL1, 0, 1, 2, 3, 4, 5, 6, 7, 8, 16, 17, 18, 29, 30,
+];
+
+/// 其余的通用寄存器，顺序与 `seL4_UserContext` 一致，对应 seL4 的 `gpRegisters`
+pub const GP_REGISTERS: [usize; 19] = [
+    9,
+    10,
+    11,
+    12,
+    13,
+    14,
+    15,
+    19,
+    20,
+    21,
+    22,
+    23,
+    24,
+    25,
+    26,
+    27,
+    28,
+    TPIDR_EL0,
+    TPIDRRO_EL0,
+];
+
+/// IPC buffer 的大小，对应 seL4 的 `seL4_IPCBufferSizeBits`
+pub const IPC_BUFFER_SIZE_BITS: usize = 10;
+
+/// PSTATE 中的 NZCV 条件标志
+const PSTATE_NZCV_MASK: usize = 0xf000_0000;
+
+/// 用户态的 PSTATE，处于 EL0t，屏蔽 FIQ 和 SError，对应 seL4 的 `PSTATE_USER`
+const PSTATE_USER: usize = bit!(6) | bit!(8);
+
+/// 页的访问权限，对应 seL4 的 `vm_rights_t`
+#[repr(usize)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VmRights {
+    KernelOnly = 1,
+    ReadOnly = 2,
+    ReadWrite = 3,
+}
+
+impl VmRights {
+    /// 从 capability 中保存的值转换，非法的值视为 [VmRights::KernelOnly]
+    pub const fn from_raw(raw: usize) -> Self {
+        match raw {
+            2 => Self::ReadOnly,
+            3 => Self::ReadWrite,
+            _ => Self::KernelOnly,
+        }
+    }
+}
+
+/// 用户写入寄存器的值，SPSR_EL1 只保留 NZCV 条件标志，其余位固定为 [PSTATE_USER]，
+/// 避免用户态打开 IRQ 屏蔽位或进入 EL1，对应 seL4 的 `sanitiseRegister`
+pub const fn sanitise_register(reg: usize, value: usize) -> usize {
+    if reg == SPSR_EL1 {
+        (value & PSTATE_NZCV_MASK) | PSTATE_USER
+    } else {
+        value
+    }
+}
+
+/// `cap` 能否作为线程的 VSpace，需要已经分配 ASID，对应 seL4 的 `isValidVTableRoot`
+pub const fn is_valid_vtable_root(cap: &Cap) -> bool {
+    matches!(cap.view(), CapView::VspaceCap(vspace) if vspace.get_cap_vs_is_mapped() != 0)
+}
+
+/// 检查 IPC buffer 的地址和所在的页，对应 seL4 的 `checkValidIPCBuffer`
+pub fn check_valid_ipc_buffer(vptr: usize, cap: &Cap) -> Result<(), SyscallError> {
+    let CapView::FrameCap(frame) = cap.view() else {
+        return Err(SyscallError::IllegalOperation);
+    };
+    if frame.get_cap_f_is_device() != 0 {
+        return Err(SyscallError::IllegalOperation);
+    }
+    if vptr & (bit!(IPC_BUFFER_SIZE_BITS) - 1) != 0 {
+        return Err(SyscallError::AlignmentError);
+    }
+    Ok(())
+}
+
+/// 线程 IPC buffer 的内核地址，`is_receiver` 时要求页可写，对应 seL4 的 `lookupIPCBuffer`
+pub fn lookup_ipc_buffer(is_receiver: bool, thread: &mut TCB) -> Option<*mut usize> {
+    let w_buffer_ptr = thread.ipc_buffer().raw();
+    let CapView::FrameCap(frame) = thread.cte(TcbCnodeIndex::Buffer).view() else {
+        return None;
+    };
+    if frame.get_cap_f_is_device() != 0 {
+        return None;
+    }
+    match VmRights::from_raw(frame.get_cap_fvm_rights()) {
+        VmRights::ReadWrite => {}
+        VmRights::ReadOnly if !is_receiver => {}
+        _ => return None,
+    }
+    let base_ptr = frame.get_cap_f_base_ptr();
+    let page_bits = page_bits_for_size(frame.get_cap_f_size());
+    Some((base_ptr + (w_buffer_ptr & (bit!(page_bits) - 1))) as *mut usize)
+}
+
+/// 按照 capability 的权限限制页的访问权限，对应 seL4 的 `maskVMRights`
+pub const fn mask_vm_rights(vm_rights: VmRights, rights: CapRights) -> VmRights {
+    match vm_rights {
+        VmRights::ReadOnly if rights.contains(CapRights::READ) => VmRights::ReadOnly,
+        VmRights::ReadWrite if rights.contains(CapRights::READ) => {
+            if rights.contains(CapRights::WRITE) {
+                VmRights::ReadWrite
+            } else {
+                VmRights::ReadOnly
+            }
+        }
+        _ => VmRights::KernelOnly,
+    }
+}
+
+/// 架构相关的 `deriveCap`，对应 seL4 的 `Arch_deriveCap`
+///
+/// 页表和 VSpace 只有映射之后才能复制，复制出的页 capability 不保留映射信息
+pub fn arch_derive_cap(cap: Cap) -> Result<Cap, SyscallError> {
+    match cap.view() {
+        CapView::PageTableCap(pt) if pt.get_cap_pt_is_mapped() == 0 => {
+            Err(SyscallError::IllegalOperation)
+        }
+        CapView::VspaceCap(vspace) if vspace.get_cap_vs_is_mapped() == 0 => {
+            Err(SyscallError::IllegalOperation)
+        }
+        CapView::FrameCap(mut frame) => {
+            frame.set_cap_f_mapped_address(0);
+            frame.set_cap_f_mapped_asid(0);
+            Ok(frame.into())
+        }
+        _ => Ok(cap),
+    }
+}
+
+/// 架构相关对象的大小，对应 seL4 的 `Arch_getObjectSize`
+pub const fn arch_object_size_bits(object_type: ObjectType) -> usize {
+    match object_type {
+        ObjectType::SmallPage => page_bits_for_size(ARM_SMALL_PAGE),
+        ObjectType::LargePage => page_bits_for_size(ARM_LARGE_PAGE),
+        ObjectType::HugePage => page_bits_for_size(ARM_HUGE_PAGE),
+        ObjectType::PageTable => PAGE_TABLE_BITS,
+        ObjectType::VSpace => VSPACE_BITS,
+        _ => panic!("invalid object type"),
+    }
+}
+
+/// 在 `region_base` 创建架构相关的对象，对应 seL4 的 `Arch_createObject`
+///
+/// 创建的页可读写，页表和 VSpace 处于未映射的状态。内存已经在 reset 时清零，除设备内存中的页之外
+/// 只需要写回缓存，使关闭缓存的映射和页表遍历都能看到清零之后的内容
+///
+/// # Safety
+///
+/// `region_base` 需要指向未被使用且按照对象大小对齐的内存
+pub unsafe fn arch_create_object(
+    object_type: ObjectType,
+    region_base: usize,
+    device_memory: bool,
+) -> Cap {
+    if !device_memory {
+        let size = bit!(arch_object_size_bits(object_type));
+        clean_dcache_poc(va!(region_base), va!(region_base + size));
+    }
+    let frame = |size| {
+        FrameCap::new(
+            0,
+            region_base,
+            size,
+            VmRights::ReadWrite as usize,
+            device_memory,
+            0,
+        )
+        .into()
+    };
+    match object_type {
+        ObjectType::SmallPage => frame(ARM_SMALL_PAGE),
+        ObjectType::LargePage => frame(ARM_LARGE_PAGE),
+        ObjectType::HugePage => frame(ARM_HUGE_PAGE),
+        ObjectType::PageTable => PageTableCap::new(0, region_base, false, 0).into(),
+        ObjectType::VSpace => VspaceCap::new(0, region_base, false).into(),
+        _ => panic!("invalid object type"),
+    }
+}
+
+/// 架构相关的 `cap_get_capIsPhysical`
+pub const fn arch_cap_is_physical(cap: &Cap) -> bool {
+    !matches!(cap.view(), CapView::AsidControlCap(_))
+}
+
+/// 架构相关的 `cap_get_capPtr`
+pub const fn arch_cap_ptr(cap: &Cap) -> usize {
+    match cap.view() {
+        CapView::FrameCap(frame) => frame.get_cap_f_base_ptr(),
+        CapView::PageTableCap(pt) => pt.get_cap_pt_base_ptr(),
+        CapView::VspaceCap(vspace) => vspace.get_cap_vs_base_ptr(),
+        CapView::AsidPoolCap(pool) => pool.get_cap_asid_pool(),
+        _ => 0,
+    }
+}
+
+/// 架构相关的 `cap_get_capSizeBits`
+pub const fn arch_cap_size_bits(cap: &Cap) -> usize {
+    match cap.view() {
+        CapView::FrameCap(frame) => page_bits_for_size(frame.get_cap_f_size()),
+        CapView::PageTableCap(_) => PAGE_TABLE_BITS,
+        CapView::VspaceCap(_) => VSPACE_BITS,
+        CapView::AsidPoolCap(_) => ASID_POOL_BITS,
+        _ => 0,
+    }
+}
+
+/// 对应 seL4 的 `Arch_sameRegionAs`
+pub fn arch_same_region_as(cap_a: &Cap, cap_b: &Cap) -> bool {
+    match (cap_a.view(), cap_b.view()) {
+        (CapView::FrameCap(a), CapView::FrameCap(b)) => {
+            let (bot_a, bot_b) = (a.get_cap_f_base_ptr(), b.get_cap_f_base_ptr());
+            let top_a = bot_a + (bit!(page_bits_for_size(a.get_cap_f_size())) - 1);
+            let top_b = bot_b + (bit!(page_bits_for_size(b.get_cap_f_size())) - 1);
+            bot_a <= bot_b && top_a >= top_b && bot_b <= top_b
+        }
+        (CapView::PageTableCap(a), CapView::PageTableCap(b)) => {
+            a.get_cap_pt_base_ptr() == b.get_cap_pt_base_ptr()
+        }
+        (CapView::VspaceCap(a), CapView::VspaceCap(b)) => {
+            a.get_cap_vs_base_ptr() == b.get_cap_vs_base_ptr()
+        }
+        (CapView::AsidControlCap(_), CapView::AsidControlCap(_)) => true,
+        (CapView::AsidPoolCap(a), CapView::AsidPoolCap(b)) => {
+            a.get_cap_asid_pool() == b.get_cap_asid_pool()
+        }
+        _ => false,
+    }
+}
+
+/// 对应 seL4 的 `Arch_sameObjectAs`，页的大小和类型也需要相同
+pub fn arch_same_object_as(cap_a: &Cap, cap_b: &Cap) -> bool {
+    if let (CapView::FrameCap(a), CapView::FrameCap(b)) = (cap_a.view(), cap_b.view()) {
+        return a.get_cap_f_base_ptr() == b.get_cap_f_base_ptr()
+            && a.get_cap_f_size() == b.get_cap_f_size()
+            && a.get_cap_f_is_device() == b.get_cap_f_is_device();
+    }
+    arch_same_region_as(cap_a, cap_b)
+}
+
+/// 架构相关的 `finaliseCap`，对应 seL4 的 `Arch_finaliseCap`
+///
+/// 已映射的页在每次删除时都解除映射，页表、VSpace 和 ASID pool 在最后一个 capability 删除时才解除映射或删除 ASID。
+/// 架构相关的对象都可以立即回收，不会产生 zombie
+pub fn arch_finalise_cap(cap: Cap, is_final: bool) -> FinaliseCap {
+    match cap.view() {
+        CapView::FrameCap(frame) if frame.get_cap_f_mapped_asid() != 0 => unmap_page(
+            frame.get_cap_f_size(),
+            frame.get_cap_f_mapped_asid(),
+            frame.get_cap_f_mapped_address(),
+            frame.get_cap_f_base_ptr(),
+        ),
+        CapView::PageTableCap(pt) if is_final && pt.get_cap_pt_is_mapped() != 0 => {
+            unmap_page_table(
+                pt.get_cap_pt_mapped_asid(),
+                pt.get_cap_pt_mapped_address(),
+                pt.get_cap_pt_base_ptr(),
+            )
+        }
+        CapView::VspaceCap(vspace) if is_final && vspace.get_cap_vs_is_mapped() != 0 => {
+            delete_asid(
+                vspace.get_cap_vs_mapped_asid(),
+                vspace.get_cap_vs_base_ptr(),
+            )
+        }
+        CapView::AsidPoolCap(pool) if is_final => {
+            delete_asid_pool(pool.get_cap_asid_base(), pool.get_cap_asid_pool())
+        }
+        _ => {}
+    }
+    FinaliseCap::null()
+}
+
+/// 对应 seL4 的 `Arch_isCapRevocable`，架构相关的 capability 都不可撤销
+pub const fn arch_is_cap_revocable(_derived_cap: &Cap, _src_cap: &Cap) -> bool {
+    false
+}
+
+/// 架构相关的 `maskCapRights`，对应 seL4 的 `Arch_maskCapRights`
+pub fn arch_mask_cap_rights(rights: CapRights, cap: Cap) -> Cap {
+    match cap.view() {
+        CapView::FrameCap(mut frame) => {
+            let vm_rights = VmRights::from_raw(frame.get_cap_fvm_rights());
+            frame.set_cap_fvm_rights(mask_vm_rights(vm_rights, rights) as usize);
+            frame.into()
+        }
+        _ => cap,
+    }
+}
+
+#[repr(C)]
 pub struct ArchTCB {
-    context: UserContext,
+    pub context: UserContext,
+}
+
+#[allow(clippy::new_without_default)]
+impl ArchTCB {
+    pub const fn new() -> Self {
+        Self {
+            context: UserContext::new(),
+        }
+    }
 }
 
+/// 用户态寄存器，顺序与 trap.S 中的 `PT_*` 偏移一致
+#[repr(C)]
 pub struct UserContext {
     regs: [usize; CONTEXT_REGS_NUM],
 }
+
+impl UserContext {
+    /// 初始化用户态上下文，对应 seL4 的 `Arch_initContext`
+    pub const fn new() -> Self {
+        let mut regs = [0; CONTEXT_REGS_NUM];
+        regs[SPSR_EL1] = PSTATE_USER;
+        Self { regs }
+    }
+
+    pub const fn get_register(&self, reg: usize) -> usize {
+        self.regs[reg]
+    }
+
+    pub const fn set_register(&mut self, reg: usize, value: usize) {
+        self.regs[reg] = value;
+    }
+}
+
+impl Default for UserContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// ASID pool，保存 2^[ASID_LOW_BITS] 个 VSpace 根页表
+#[repr(C)]
+pub struct AsidPool {
+    pub array: [usize; bit!(ASID_LOW_BITS)],
+}
+
+const _: () = assert!(size_of::<AsidPool>() == bit!(ASID_POOL_BITS));
@@ -0,0 +1,168 @@
+//! 多核启动
+//!
+//! 主核通过 PSCI `cpu_on` 启动次核，次核从物理地址上的 [secondary_start] 开始执行，
+//! 使用主核的 MAIR/TCR/SCTLR 和恒等映射开启 MMU 后跳转到内核虚拟地址，
+//! 根据逻辑编号选择 [KERNEL_STACK_ALLOC] 中的内核栈，完成 [cpu::init_cpu] 后在启动屏障处等待主核。
+
+use core::{
+    arch::naked_asm,
+    hint::spin_loop,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use aarch64_cpu::{
+    asm::wfi,
+    registers::{Readable, MAIR_EL1, MPIDR_EL1, SCTLR_EL1, TCR_EL1},
+};
+use macros::boot_code;
+
+use super::{cpu, vspace::map_secondary_boot_window};
+use crate::{
+    arch::generic::KERNEL_STACK_ALLOC,
+    config::{KERNEL_STACK_BITS, MAX_NUM_NODES},
+    driver::cpu_on,
+    platform::PLATFORM,
+};
+
+/// MPIDR_EL1 中的亲和性字段 Aff3、Aff2、Aff1 和 Aff0
+const MPIDR_AFF_MASK: usize = 0xff_00ff_ffff;
+
+/// 次核开启 MMU 需要的系统寄存器，次核在关闭缓存的情况下读取
+#[repr(C, align(64))]
+struct SecondaryBootData {
+    mair: usize,
+    tcr: usize,
+    ttbr0: usize,
+    ttbr1: usize,
+    sctlr: usize,
+}
+
+static mut SECONDARY_BOOT_DATA: SecondaryBootData = SecondaryBootData {
+    mair: 0,
+    tcr: 0,
+    ttbr0: 0,
+    ttbr1: 0,
+    sctlr: 0,
+};
+
+/// 启动成功的核数量，包括主核
+static NUM_NODES: AtomicUsize = AtomicUsize::new(1);
+/// 完成初始化的核数量，对应 seL4 的 `ksNumCPUs`
+static NUM_CPUS: AtomicUsize = AtomicUsize::new(1);
+/// 主核完成 init_kernel 后释放次核，对应 seL4 的 `node_boot_lock`
+static NODE_BOOT_LOCK: AtomicBool = AtomicBool::new(false);
+
+/// 次核的入口，`x0` 为 `cpu_on` 传入的逻辑编号
+///
+/// 此时 MMU 和缓存均未开启，代码运行在物理地址上，只能使用 PC 相对寻址
+#[unsafe(naked)]
+unsafe extern "C" fn secondary_start() -> ! {
+    naked_asm!(
+        include_defines!(),"
+        msr     daifset, DAIFSET_MASK
+        msr     spsel, #1
+        mov     x19, x0
+
+        adrp    x8, {boot_data}
+        add     x8, x8, :lo12:{boot_data}
+        ldp     x9, x10, [x8]
+        ldp     x11, x12, [x8, #16]
+        ldr     x13, [x8, #32]
+        msr     mair_el1, x9
+        msr     tcr_el1, x10
+        msr     ttbr0_el1, x11
+        msr     ttbr1_el1, x12
+        isb
+        tlbi    vmalle1
+        ic      iallu
+        dsb     nsh
+        isb
+        msr     sctlr_el1, x13
+        isb
+
+        ldr     x8, ={secondary_start_virt}
+        br      x8",
+        boot_data = sym SECONDARY_BOOT_DATA,
+        secondary_start_virt = sym secondary_start_virt,
+    );
+}
+
+/// 次核开启 MMU 后跳转到内核虚拟地址，栈顶为 `KERNEL_STACK_ALLOC[id + 1]`，并将 `栈顶 | id` 写入 TPIDR_EL1
+#[unsafe(naked)]
+unsafe extern "C" fn secondary_start_virt() -> ! {
+    naked_asm!(
+        "
+        ldr     x8, ={stack}
+        add     x9, x19, #1
+        lsl     x9, x9, #{stack_bits}
+        add     x8, x8, x9
+        mov     sp, x8
+        orr     x8, x8, x19
+        msr     tpidr_el1, x8
+
+        mov     x0, x19
+        bl      {init_kernel_secondary}
+        b       .",
+        stack = sym KERNEL_STACK_ALLOC,
+        stack_bits = const KERNEL_STACK_BITS,
+        init_kernel_secondary = sym init_kernel_secondary,
+    );
+}
+
+/// 次核的初始化，对应 seL4 的 `try_init_kernel_secondary_core`
+extern "C" fn init_kernel_secondary(core_id: usize) -> ! {
+    cpu::init_cpu();
+    NUM_CPUS.fetch_add(1, Ordering::Release);
+    while !NODE_BOOT_LOCK.load(Ordering::Acquire) {
+        spin_loop();
+    }
+    log::debug!("core {} is up", core_id);
+
+    // TODO: 运行当前核的 idle 线程
+    loop {
+        wfi();
+    }
+}
+
+/// 通过 PSCI 启动 [PLATFORM] 中的其他核，返回启动成功的核数量，包括主核
+///
+/// 需要在主核完成 [cpu::init_cpu] 之后调用，次核复用主核的 MMU 配置
+#[boot_code]
+pub fn start_secondary_cpus() -> usize {
+    let (ttbr0, ttbr1) = map_secondary_boot_window();
+    unsafe {
+        SECONDARY_BOOT_DATA = SecondaryBootData {
+            mair: MAIR_EL1.get() as usize,
+            tcr: TCR_EL1.get() as usize,
+            ttbr0: ttbr0.raw(),
+            ttbr1: ttbr1.raw(),
+            sctlr: SCTLR_EL1.get() as usize,
+        };
+        let start = (&raw const SECONDARY_BOOT_DATA) as usize;
+        cpu::clean_dcache_poc(va!(start), va!(start + size_of::<SecondaryBootData>()));
+    }
+
+    let entry = va!(secondary_start as *const () as usize).paddr();
+    let current = MPIDR_EL1.get() as usize & MPIDR_AFF_MASK;
+    let platform = PLATFORM.lock();
+    let mut num_nodes = 1;
+    for &mpidr in platform.cpus[..platform.num_cpus].iter() {
+        if mpidr == current || num_nodes == MAX_NUM_NODES {
+            continue;
+        }
+        if cpu_on(mpidr, entry.raw(), num_nodes).is_ok() {
+            num_nodes += 1;
+        }
+    }
+    NUM_NODES.store(num_nodes, Ordering::Relaxed);
+    num_nodes
+}
+
+/// 等待所有次核完成 [cpu::init_cpu]，然后释放启动屏障，对应 seL4 的 `release_secondary_cpus`
+pub fn release_secondary_cpus() {
+    let num_nodes = NUM_NODES.load(Ordering::Relaxed);
+    while NUM_CPUS.load(Ordering::Acquire) != num_nodes {
+        spin_loop();
+    }
+    NODE_BOOT_LOCK.store(true, Ordering::Release);
+}
@@ -1,18 +1,104 @@
 use core::arch::global_asm;
 
-global_asm!(include_defines!(), include_str!("trap.S"));
+use sel4_types::syscall::Syscall;
 
+use super::{vspace::set_vm_root, UserContext};
+use crate::{
+    api::syscall::handle_invocation,
+    config::KERNEL_STACK_BITS,
+    driver::{ack_interrupt, get_active_irq, reset_timer, IRQ_INVALID},
+    kernel::thread::{activate_thread, cur_thread, schedule, timer_tick},
+    platform::PLATFORM,
+};
+
+global_asm!(
+    include_defines!(),
+    ".equ KERNEL_STACK_MASK, {mask}",
+    include_str!("trap.S"),
+    mask = const bit!(KERNEL_STACK_BITS) - 1
+);
+
+extern "C" {
+    fn restore_user_context_asm(context: *const UserContext) -> !;
+}
+
+/// 切换到当前线程的地址空间，恢复用户态寄存器并通过 `eret` 返回用户态，对应 seL4 的 `restore_user_context`
+///
+/// SP_EL1 指向当前线程的 [UserContext]，之后 EL0 的异常会直接将寄存器保存在其中，
+/// 再从 TPIDR_EL1 中取出内核栈顶
+pub fn restore_user_context() -> ! {
+    let thread = unsafe { &mut *cur_thread() };
+    set_vm_root(thread);
+    #[cfg(all(debug_assertions, feature = "mdb-check"))]
+    crate::object::mdb_check::check_all();
+    unsafe { restore_user_context_asm(thread.context()) }
+}
+
+/// 处理完异常后重新调度并返回用户态
+fn exit_kernel() -> ! {
+    schedule();
+    activate_thread();
+    restore_user_context()
+}
+
+/// 对应 seL4 的 `handleInterrupt`
+// TODO: 其他中断通过 IRQHandler 通知用户态
+fn handle_interrupt(irq: usize) {
+    if irq == PLATFORM.lock().kernel_timer_irq() {
+        timer_tick();
+        reset_timer();
+    }
+    ack_interrupt(irq);
+}
+
+/// 处理中断，对应 seL4 的 `handleInterruptEntry`
 #[no_mangle]
-unsafe extern "C" fn c_handle_interrupt() {}
+unsafe extern "C" fn c_handle_interrupt() -> ! {
+    let irq = get_active_irq();
+    if irq != IRQ_INVALID {
+        handle_interrupt(irq);
+    }
+    exit_kernel()
+}
 
+// TODO: 产生 VMFault
 #[no_mangle]
-unsafe extern "C" fn c_handle_data_fault() {}
+unsafe extern "C" fn c_handle_data_fault() -> ! {
+    exit_kernel()
+}
 
+// TODO: 产生 VMFault
 #[no_mangle]
-unsafe extern "C" fn c_handle_instruction_fault() {}
+unsafe extern "C" fn c_handle_instruction_fault() -> ! {
+    exit_kernel()
+}
+
+/// 调用内核对象，被抢占时先处理等待的中断，对应 seL4 `handleSyscall` 中的 Send、NBSend 和 Call
+fn handle_invocation_syscall(is_call: bool) {
+    if handle_invocation(is_call).is_err() {
+        let irq = get_active_irq();
+        if irq != IRQ_INVALID {
+            handle_interrupt(irq);
+        }
+    }
+}
 
+/// 处理系统调用，`syscall` 为 x7 中的系统调用编号
 #[no_mangle]
-unsafe extern "C" fn c_handle_syscall() {}
+unsafe extern "C" fn c_handle_syscall(_cptr: usize, _msg_info: usize, syscall: usize) -> ! {
+    match Syscall::from_raw(syscall) {
+        Some(Syscall::Send | Syscall::NBSend) => handle_invocation_syscall(false),
+        Some(Syscall::Call) => handle_invocation_syscall(true),
+        #[cfg(feature = "mdb-check")]
+        Some(Syscall::DebugCheckMdb) => crate::object::mdb_check::check_all(),
+        // TODO: 处理 IPC 和 Yield，未知的系统调用产生 UnknownSyscall
+        _ => {}
+    }
+    exit_kernel()
+}
 
+// TODO: 产生 UserException
 #[no_mangle]
-unsafe extern "C" fn c_handle_undefined_instruction() {}
+unsafe extern "C" fn c_handle_undefined_instruction() -> ! {
+    exit_kernel()
+}
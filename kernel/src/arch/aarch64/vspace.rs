@@ -1,12 +1,29 @@
-use super::VSPACE_INDEX_BITS;
-use crate::arch::PPTR_BASE;
+use super::{
+    page_bits_for_size, AsidPool, VmRights, ASID_HIGH_BITS, ASID_LOW_BITS, PAGE_BITS,
+    PT_INDEX_BITS, VSPACE_INDEX_BITS,
+};
+use crate::{
+    arch::{PhysAddr, VirtAddr, KDEV_BASE, PPTR_BASE},
+    kernel::thread::cur_thread,
+    object::{
+        cap::CapView,
+        structures::{FrameCap, PageTableCap},
+        tcb::{TcbCnodeIndex, TCB},
+    },
+    platform::{GIC_CPU_PPTR, GIC_DIST_PPTR, PLATFORM, UART_PPTR},
+};
 use aarch64_cpu::{
-    asm::barrier::{self, dsb},
-    registers::{Writeable, TTBR0_EL1, TTBR1_EL1},
+    asm::barrier::{self, dsb, isb},
+    registers::{ReadWriteable, Readable, Writeable, MAIR_EL1, TTBR0_EL1, TTBR1_EL1},
 };
 use hal::aarch64::{PTEFlags, PTE};
+use macros::boot_code;
 
 const PTE_LEN: usize = 512;
+/// 2MB 大页的大小
+const LARGE_PAGE_BITS: usize = PAGE_BITS + PT_INDEX_BITS;
+/// 内核镜像最多占用的 2MB 大页数量，这些大页使用 4K 页表映射
+const KERNEL_IMAGE_PT_NUM: usize = 4;
 
 #[repr(align(4096))]
 struct GlobalPageTable {
@@ -18,8 +35,13 @@ struct GlobalPageTable {
     pud: [PTE; PTE_LEN],
     /// 2MB Large Page
     pds: [[PTE; PTE_LEN]; PTE_LEN],
-    /// 内核设备对应的页表
+    /// 内核设备对应的页表，映射 [KDEV_BASE] 开始的 2MB
     pt: [PTE; PTE_LEN],
+    /// 内核镜像对应的页表，按照段设置权限
+    image_pts: [[PTE; PTE_LEN]; KERNEL_IMAGE_PT_NUM],
+    /// 次核开启 MMU 时使用的恒等映射，次核入口运行在物理地址上
+    boot_pgd: [PTE; PTE_LEN],
+    boot_pud: [PTE; PTE_LEN],
 }
 
 impl GlobalPageTable {
@@ -30,6 +52,9 @@ impl GlobalPageTable {
             pud: [PTE::empty(); PTE_LEN],
             pds: [[PTE::empty(); PTE_LEN]; PTE_LEN],
             pt: [PTE::empty(); PTE_LEN],
+            image_pts: [[PTE::empty(); PTE_LEN]; KERNEL_IMAGE_PT_NUM],
+            boot_pgd: [PTE::empty(); PTE_LEN],
+            boot_pud: [PTE::empty(); PTE_LEN],
         }
     }
 }
@@ -40,12 +65,338 @@ pub fn flush_all() {
     unsafe { core::arch::asm!("tlbi vmalle1; dsb sy; isb") }
 }
 
+/// 刷新所有核的 TLB，修改次核也在使用的内核页表之后调用
+#[inline]
+fn flush_all_is() {
+    unsafe { core::arch::asm!("dsb ishst; tlbi vmalle1is; dsb ish; isb") }
+}
+
+/// 刷新 `asid` 对应的所有 TLB 项，对应 seL4 的 `invalidateTLBByASID`
+fn invalidate_tlb_by_asid(asid: usize) {
+    unsafe { core::arch::asm!("dsb ishst; tlbi aside1is, {}; dsb ish; isb", in(reg) asid << 48) }
+}
+
+/// 刷新 `asid` 中 `vaddr` 对应的 TLB 项，对应 seL4 的 `invalidateTLBByASIDVA`
+fn invalidate_tlb_by_asid_va(asid: usize, vaddr: usize) {
+    let operand = (asid << 48) | ((vaddr >> PAGE_BITS) & (bit!(44) - 1));
+    unsafe { core::arch::asm!("dsb ishst; tlbi vae1is, {}; dsb ish; isb", in(reg) operand) }
+}
+
 static mut GLOBAL_PT: GlobalPageTable = GlobalPageTable::new();
 
+/// 全局 ASID 表，根据 ASID 的高位索引 [AsidPool]
+static mut ASID_TABLE: [*mut AsidPool; bit!(ASID_HIGH_BITS)] =
+    [core::ptr::null_mut(); bit!(ASID_HIGH_BITS)];
+
+/// 将 root task 的 VSpace 写入 ASID pool，并将 ASID pool 加入 [ASID_TABLE]
+///
+/// # Safety
+///
+/// `pool` 需要指向一个有效的 [AsidPool]，且仅在启动阶段调用
+#[boot_code]
+pub unsafe fn write_it_asid_pool(asid: usize, pool: *mut AsidPool, vspace: usize) {
+    unsafe {
+        (*pool).array[asid & (bit!(ASID_LOW_BITS) - 1)] = vspace;
+        ASID_TABLE[asid >> ASID_LOW_BITS] = pool;
+    }
+}
+
+/// 根据 ASID 查找 VSpace 根页表，对应 seL4 的 `findVSpaceForASID`
+pub fn find_vspace_for_asid(asid: usize) -> Option<usize> {
+    let pool = unsafe { (&raw const ASID_TABLE).as_ref().unwrap()[asid >> ASID_LOW_BITS] };
+    let pool = unsafe { pool.as_ref() }?;
+    match pool.array[asid & (bit!(ASID_LOW_BITS) - 1)] {
+        0 => None,
+        vspace => Some(vspace),
+    }
+}
+
+/// 删除 ASID pool，刷新其中所有 ASID 的 TLB，对应 seL4 的 `deleteASIDPool`
+pub fn delete_asid_pool(asid_base: usize, pool: usize) {
+    let pool = pool as *mut AsidPool;
+    let asid_table = unsafe { (&raw mut ASID_TABLE).as_mut().unwrap() };
+    if asid_table[asid_base >> ASID_LOW_BITS] != pool {
+        return;
+    }
+    for (offset, vspace) in unsafe { (*pool).array.iter().enumerate() } {
+        if *vspace != 0 {
+            invalidate_tlb_by_asid(asid_base + offset);
+        }
+    }
+    asid_table[asid_base >> ASID_LOW_BITS] = core::ptr::null_mut();
+    set_vm_root(unsafe { &mut *cur_thread() });
+}
+
+/// 删除 `asid` 与 VSpace 的对应关系，对应 seL4 的 `deleteASID`
+pub fn delete_asid(asid: usize, vspace: usize) {
+    let pool = unsafe { (&raw const ASID_TABLE).as_ref().unwrap()[asid >> ASID_LOW_BITS] };
+    let Some(pool) = (unsafe { pool.as_mut() }) else {
+        return;
+    };
+    let entry = &mut pool.array[asid & (bit!(ASID_LOW_BITS) - 1)];
+    if *entry == vspace {
+        invalidate_tlb_by_asid(asid);
+        *entry = 0;
+        set_vm_root(unsafe { &mut *cur_thread() });
+    }
+}
+
+impl VmRights {
+    /// 对应的页表项访问权限
+    const fn ap_flags(self) -> PTEFlags {
+        match self {
+            Self::KernelOnly => PTEFlags::empty(),
+            Self::ReadOnly => PTEFlags::AP_EL0.union(PTEFlags::AP_RO),
+            Self::ReadWrite => PTEFlags::AP_EL0,
+        }
+    }
+}
+
+/// 第 `level` 级页表中 `vaddr` 对应的索引，第 0 级为 VSpace 根页表，第 3 级页表中保存 4K 页
+const fn pt_index(vaddr: usize, level: usize) -> usize {
+    (vaddr >> (PAGE_BITS + PT_INDEX_BITS * (3 - level))) & (bit!(PT_INDEX_BITS) - 1)
+}
+
+/// 页表项指向的下一级页表
+fn next_table(pte: PTE) -> *mut PTE {
+    pa!(pte.address()).vaddr().raw() as *mut PTE
+}
+
+/// 页表项中的物理地址，去掉高位的属性
+const fn pte_paddr(pte: PTE) -> usize {
+    pte.address() & (bit!(48) - 1)
+}
+
+/// 解除页表的映射，对应 seL4 的 `unmapPageTable`
+///
+/// 从 VSpace 根页表开始查找指向 `pt` 的页表项，页表已经不在 `asid` 的地址空间中时不做处理
+pub fn unmap_page_table(asid: usize, vaddr: usize, pt: usize) {
+    let Some(vspace) = find_vspace_for_asid(asid) else {
+        return;
+    };
+    let mut table = vspace as *mut PTE;
+    for level in 0..3 {
+        let slot = unsafe { table.add(pt_index(vaddr, level)) };
+        let pte = unsafe { *slot };
+        if !pte.is_table() {
+            return;
+        }
+        table = next_table(pte);
+        if table as usize == pt {
+            unsafe { *slot = PTE::empty() };
+            invalidate_tlb_by_asid(asid);
+            return;
+        }
+    }
+}
+
+/// 解除页的映射，对应 seL4 的 `unmapPage`
+///
+/// 页表项已经不指向 `pptr` 时不做处理，4K 页位于第 3 级页表，2M 和 1G 的页为 block
+pub fn unmap_page(size: usize, asid: usize, vaddr: usize, pptr: usize) {
+    let Some(vspace) = find_vspace_for_asid(asid) else {
+        return;
+    };
+    let level = 3 - (page_bits_for_size(size) - PAGE_BITS) / PT_INDEX_BITS;
+    let mut table = vspace as *mut PTE;
+    for l in 0..level {
+        let pte = unsafe { *table.add(pt_index(vaddr, l)) };
+        if !pte.is_table() {
+            return;
+        }
+        table = next_table(pte);
+    }
+    let slot = unsafe { table.add(pt_index(vaddr, level)) };
+    let pte = unsafe { *slot };
+    if pte.is_valid() && pte.is_table() == (level == 3) && pte_paddr(pte) == va!(pptr).paddr().raw()
+    {
+        unsafe { *slot = PTE::empty() };
+        invalidate_tlb_by_asid_va(asid, vaddr);
+    }
+}
+
+/// 将 root task 的页表映射到 VSpace 中
+///
+/// 页表需要按照从高到低的级别依次映射，页表会被放在 `mapped_address` 对应的第一个空页表项中
+///
+/// # Safety
+///
+/// `vspace` 需要指向 VSpace 根页表，且仅在启动阶段调用
+#[boot_code]
+pub unsafe fn map_it_pt_cap(vspace: VirtAddr, pt: PageTableCap) {
+    let vaddr = pt.get_cap_pt_mapped_address();
+    let paddr = pt.get_cap_pt_base_ptr() - PPTR_BASE;
+    let mut table = vspace.raw() as *mut PTE;
+    for level in 0..3 {
+        unsafe {
+            let slot = table.add(pt_index(vaddr, level));
+            if !(*slot).is_valid() {
+                *slot = PTE::new_table(paddr);
+                return;
+            }
+            table = next_table(*slot);
+        }
+    }
+    panic!("page table for {vaddr:#x} is already mapped");
+}
+
+/// 将 root task 的 4K 页映射到 VSpace 中，需要的页表已经通过 [map_it_pt_cap] 映射
+///
+/// # Safety
+///
+/// `vspace` 需要指向 VSpace 根页表，且仅在启动阶段调用
+#[boot_code]
+pub unsafe fn map_it_frame_cap(vspace: VirtAddr, frame: FrameCap, executable: bool) {
+    let vaddr = frame.get_cap_f_mapped_address();
+    let paddr = frame.get_cap_f_base_ptr() - PPTR_BASE;
+    let mut flags = PTEFlags::VALID
+        | PTEFlags::NON_BLOCK
+        | PTEFlags::ATTR_INDX
+        | PTEFlags::AF
+        | PTEFlags::NG
+        | PTEFlags::PXN
+        | VmRights::from_raw(frame.get_cap_fvm_rights()).ap_flags();
+    if !executable {
+        flags |= PTEFlags::UXN;
+    }
+    let mut table = vspace.raw() as *mut PTE;
+    unsafe {
+        for level in 0..3 {
+            let pte = *table.add(pt_index(vaddr, level));
+            assert!(pte.is_table(), "missing page table for {vaddr:#x}");
+            table = next_table(pte);
+        }
+        *table.add(pt_index(vaddr, 3)) = PTE::new_page(paddr, flags);
+    }
+}
+
+/// 回收 `.boot` 段，将其重新映射为可写不可执行
+///
+/// `.boot` 段在启动阶段已经作为 untyped 交给 root task，调用之后不能再执行启动代码。
+/// 次核此时已经在使用内核页表，需要刷新所有核的 TLB
+pub fn reclaim_boot_region() {
+    let sections = KernelSections::new();
+    let image_start = sections.skernel >> LARGE_PAGE_BITS;
+    let flags = KERNEL_WINDOW_FLAGS | PTEFlags::NON_BLOCK | PTEFlags::PXN;
+    unsafe {
+        let global_pt = (&raw mut GLOBAL_PT).as_mut().unwrap();
+        for page in (sections.skernel..sections.ki_boot_end).step_by(bit!(PAGE_BITS)) {
+            let pt = &mut global_pt.image_pts[(page >> LARGE_PAGE_BITS) - image_start];
+            pt[pt_index(page, 3)] = PTE::new_page(page - PPTR_BASE, flags);
+        }
+    }
+    flush_all_is();
+}
+
+/// 切换用户地址空间，对应 seL4 的 `setCurrentUserVSpaceRoot`
+///
+/// 用户页表项都带有 nG，TLB 按 ASID 区分，切换时不需要刷新 TLB，ASID 被回收时再刷新对应的项
+pub fn set_vspace_root(root: PhysAddr, asid: usize) {
+    let ttbr0 = TTBR0_EL1::ASID.val(asid as _) + TTBR0_EL1::BADDR.val(root.raw() as u64 >> 1);
+    if TTBR0_EL1.get() == ttbr0.value {
+        return;
+    }
+    dsb(barrier::SY);
+    TTBR0_EL1.write(ttbr0);
+    isb(barrier::SY);
+}
+
+/// 切换到线程的地址空间，对应 seL4 的 `setVMRoot`
+///
+/// 线程的 VTable 中不是已分配 ASID 的 VSpace 时，切换到不包含用户映射的全局用户地址空间
+pub fn set_vm_root(tcb: &mut TCB) {
+    if let CapView::VspaceCap(cap) = tcb.cte(TcbCnodeIndex::VTable).view() {
+        let (base, asid) = (cap.get_cap_vs_base_ptr(), cap.get_cap_vs_mapped_asid());
+        if cap.get_cap_vs_is_mapped() != 0 && find_vspace_for_asid(asid) == Some(base) {
+            set_vspace_root(va!(base).paddr(), asid);
+            return;
+        }
+    }
+    let global_user_vspace = unsafe { (&raw const GLOBAL_PT.user_vspace) as usize };
+    set_vspace_root(va!(global_user_vspace).paddr(), 0);
+}
+
+/// 内核窗口的基本属性，所有物理内存的别名在用户态都不可执行，多核之间共享使用 Inner Shareable
+///
+/// 内核映射在所有地址空间中都相同，不设置 nG，切换 ASID 时保留在 TLB 中
+const KERNEL_WINDOW_FLAGS: PTEFlags = PTEFlags::VALID
+    .union(PTEFlags::AF)
+    .union(PTEFlags::ATTR_INDX)
+    .union(PTEFlags::INNER)
+    .union(PTEFlags::SHAREABLE)
+    .union(PTEFlags::UXN);
+
+/// 内核设备的页表项属性，AttrIndx 为 0，对应 MAIR 中的 Device-nGnRnE
+const KERNEL_DEVICE_FLAGS: PTEFlags = PTEFlags::VALID
+    .union(PTEFlags::NON_BLOCK)
+    .union(PTEFlags::AF)
+    .union(PTEFlags::PXN)
+    .union(PTEFlags::UXN);
+
+/// 内核镜像中各个段的虚拟地址
+struct KernelSections {
+    skernel: usize,
+    sboot_data: usize,
+    ki_boot_end: usize,
+    etext: usize,
+    srodata: usize,
+    erodata: usize,
+    ki_end: usize,
+}
+
+impl KernelSections {
+    fn new() -> Self {
+        extern "C" {
+            fn _skernel();
+            fn sboot_data();
+            fn ki_boot_end();
+            fn etext();
+            fn srodata();
+            fn erodata();
+            fn ki_end();
+        }
+        Self {
+            skernel: _skernel as *const () as usize,
+            sboot_data: sboot_data as *const () as usize,
+            ki_boot_end: ki_boot_end as *const () as usize,
+            etext: etext as *const () as usize,
+            srodata: srodata as *const () as usize,
+            erodata: erodata as *const () as usize,
+            ki_end: ki_end as *const () as usize,
+        }
+    }
+
+    /// 内核镜像中 4K 页的权限
+    ///
+    /// `.boot` 和 `.text` 只读可执行，`.rodata` 只读不可执行，`.boot.data`、`.data` 和 `.bss` 可写不可执行
+    fn page_flags(&self, vaddr: usize) -> PTEFlags {
+        let flags = KERNEL_WINDOW_FLAGS | PTEFlags::NON_BLOCK;
+        if (self.sboot_data..self.ki_boot_end).contains(&vaddr) {
+            flags | PTEFlags::PXN
+        } else if (self.skernel..self.etext).contains(&vaddr) {
+            flags | PTEFlags::AP_RO
+        } else if (self.srodata..self.erodata).contains(&vaddr) {
+            flags | PTEFlags::AP_RO | PTEFlags::PXN
+        } else {
+            flags | PTEFlags::PXN
+        }
+    }
+}
+
 /// 映射内核内存
 ///
 /// aarch64 为四级页表，在 [GLOBAL_PT] 映射内核内存，内存范围为 [PPTR_BASE] - [crate::arch::PPTR_TOP]，映射单位为 2MB 内存
+///
+/// 内核镜像所在的 2MB 内存使用 4K 页表映射，按照段设置权限，其余内存可写但不可执行
+#[boot_code]
 pub fn map_kernel_window() {
+    let sections = KernelSections::new();
+    let image_start = sections.skernel >> LARGE_PAGE_BITS;
+    let image_end = sections.ki_end.next_multiple_of(bit!(LARGE_PAGE_BITS)) >> LARGE_PAGE_BITS;
+    assert!(
+        image_end - image_start <= KERNEL_IMAGE_PT_NUM,
+        "kernel image is too large"
+    );
     unsafe {
         let global_pt = (&raw mut GLOBAL_PT).as_mut().unwrap();
         global_pt.pgd[PTE_LEN - 1] = PTE::new_table(global_pt.pud.as_ptr() as usize - PPTR_BASE);
@@ -53,21 +404,84 @@ pub fn map_kernel_window() {
             global_pt.pud[i] = PTE::new_table(global_pt.pds[i].as_ptr() as usize - PPTR_BASE);
         }
         for i in 0..PTE_LEN * PTE_LEN {
-            global_pt.pds[i / PTE_LEN][i % PTE_LEN] = PTE::new_page(
-                i * 0x20_0000,
-                PTEFlags::VALID | PTEFlags::AF | PTEFlags::ATTR_INDX | PTEFlags::NG,
-            );
+            let vaddr = PPTR_BASE + (i << LARGE_PAGE_BITS);
+            let block = vaddr >> LARGE_PAGE_BITS;
+            global_pt.pds[i / PTE_LEN][i % PTE_LEN] = if (image_start..image_end).contains(&block) {
+                let pt = &mut global_pt.image_pts[block - image_start];
+                for (j, pte) in pt.iter_mut().enumerate() {
+                    let page = vaddr + (j << PAGE_BITS);
+                    *pte = PTE::new_page(page - PPTR_BASE, sections.page_flags(page));
+                }
+                PTE::new_table(pt.as_ptr() as usize - PPTR_BASE)
+            } else {
+                PTE::new_page(i << LARGE_PAGE_BITS, KERNEL_WINDOW_FLAGS | PTEFlags::PXN)
+            };
         }
     }
-    // TODO: 映射设备物理内存并判断是否为用户态保留
+}
+
+/// 将平台设备映射到 [KDEV_BASE] 开始的虚拟地址，对应 seL4 的 `map_kernel_devices`
+///
+/// 设备的物理地址从 DTB 中获取，需要在 [crate::platform::init] 之后调用，使用 [GlobalPageTable::pt] 映射为 4K 页
+#[boot_code]
+pub fn map_kernel_devices() {
+    let devices = {
+        let platform = PLATFORM.lock();
+        [
+            (platform.uart, UART_PPTR, 1),
+            (platform.gic_dist, GIC_DIST_PPTR, 1),
+            (platform.gic_cpu, GIC_CPU_PPTR, 2),
+        ]
+    };
+    unsafe {
+        let global_pt = (&raw mut GLOBAL_PT).as_mut().unwrap();
+        for (paddr, vaddr, pages) in devices {
+            for page in 0..pages {
+                let offset = page << PAGE_BITS;
+                global_pt.pt[pt_index(vaddr.raw() + offset, 3)] =
+                    PTE::new_page(paddr.raw() + offset, KERNEL_DEVICE_FLAGS);
+            }
+        }
+        global_pt.pds[pt_index(KDEV_BASE, 1)][pt_index(KDEV_BASE, 2)] =
+            PTE::new_table(global_pt.pt.as_ptr() as usize - PPTR_BASE);
+    }
+}
+
+/// 为次核建立内核镜像所在 1GB 物理内存的恒等映射，返回 `(ttbr0, ttbr1)` 对应的物理地址
+///
+/// 次核开启 MMU 后继续在物理地址上执行，直到跳转到内核虚拟地址，之后由 [activate_kernel_vspace] 替换 TTBR0
+#[boot_code]
+pub fn map_secondary_boot_window() -> (PhysAddr, PhysAddr) {
+    let paddr = va!(KernelSections::new().skernel).paddr().raw();
+    unsafe {
+        let global_pt = (&raw mut GLOBAL_PT).as_mut().unwrap();
+        global_pt.boot_pgd[pt_index(paddr, 0)] =
+            PTE::new_table(global_pt.boot_pud.as_ptr() as usize - PPTR_BASE);
+        // 恒等映射位于 TTBR0 的范围内，设置 nG 避免与用户地址空间的映射混在一起
+        global_pt.boot_pud[pt_index(paddr, 1)] = PTE::new_page(
+            paddr & !(bit!(LARGE_PAGE_BITS + PT_INDEX_BITS) - 1),
+            KERNEL_WINDOW_FLAGS | PTEFlags::NG,
+        );
+        (
+            va!(global_pt.boot_pgd.as_ptr()).paddr(),
+            va!(global_pt.pgd.as_ptr()).paddr(),
+        )
+    }
 }
 
 /// 激活内核虚拟地址空间
 ///
 /// 激活 [GLOBAL_PT] 中的虚拟地址空间，内核地址为 [GlobalPageTable::pgd]，用户地址空间为 [GlobalPageTable::user_vspace]
+///
+/// 同时设置内核使用的 MAIR 属性：AttrIndx 0 为 Device-nGnRnE，AttrIndx 7 为 Normal Write-Back
 pub fn activate_kernel_vspace() {
     unsafe {
         dsb(barrier::SY);
+        MAIR_EL1.modify(
+            MAIR_EL1::Attr0_Device::nonGathering_nonReordering_noEarlyWriteAck
+                + MAIR_EL1::Attr7_Normal_Outer::WriteBack_NonTransient_ReadWriteAlloc
+                + MAIR_EL1::Attr7_Normal_Inner::WriteBack_NonTransient_ReadWriteAlloc,
+        );
         TTBR0_EL1.write(TTBR0_EL1::ASID.val(0));
         TTBR0_EL1.set_baddr((&raw mut GLOBAL_PT.user_vspace) as u64 - PPTR_BASE as u64);
 
@@ -7,11 +7,13 @@ pub struct PhysAddr(usize);
 #[derive(Clone, Copy)]
 pub struct VirtAddr(usize);
 
+#[derive(Clone, Copy)]
 pub struct VirtAddrRange {
     pub start: VirtAddr,
     pub end: VirtAddr,
 }
 
+#[derive(Clone, Copy)]
 pub struct PhysAddrRange {
     pub start: PhysAddr,
     pub end: PhysAddr,
@@ -47,20 +49,172 @@ macro_rules! impl_addr {
 
 impl_addr!(PhysAddr, VirtAddr);
 
+macro_rules! impl_addr_range {
+    ($($name:ident => $addr:ident),*) => {
+        $(
+            impl $name {
+                pub const fn new(start: $addr, end: $addr) -> Self {
+                    Self { start, end }
+                }
+
+                pub const fn size(&self) -> usize {
+                    self.end.raw() - self.start.raw()
+                }
+
+                pub const fn is_empty(&self) -> bool {
+                    self.start.raw() >= self.end.raw()
+                }
+
+                /// 判断 `addr` 是否在 `[start, end)` 范围内
+                pub const fn contains(&self, addr: $addr) -> bool {
+                    self.start.raw() <= addr.raw() && addr.raw() < self.end.raw()
+                }
+            }
+        )*
+    };
+}
+
+impl_addr_range!(PhysAddrRange => PhysAddr, VirtAddrRange => VirtAddr);
+
+/// 固定容量的物理内存区域列表
+#[derive(Clone, Copy)]
+pub struct RegionList<const N: usize> {
+    regions: [PhysAddrRange; N],
+    len: usize,
+}
+
+impl<const N: usize> Default for RegionList<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> RegionList<N> {
+    pub const fn new() -> Self {
+        Self {
+            regions: [PhysAddrRange::new(PhysAddr(0), PhysAddr(0)); N],
+            len: 0,
+        }
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 添加一个区域，空区域会被忽略，列表已满时返回 `false`
+    pub const fn push(&mut self, region: PhysAddrRange) -> bool {
+        if region.is_empty() {
+            return true;
+        }
+        if self.len == N {
+            return false;
+        }
+        self.regions[self.len] = region;
+        self.len += 1;
+        true
+    }
+
+    /// 移除第 `idx` 个区域，后面的区域依次前移
+    pub fn remove(&mut self, idx: usize) -> PhysAddrRange {
+        assert!(idx < self.len);
+        let region = self.regions[idx];
+        self.regions.copy_within(idx + 1..self.len, idx);
+        self.len -= 1;
+        region
+    }
+
+    /// 在第 `idx` 个位置插入区域，后面的区域依次后移
+    fn insert_at(&mut self, idx: usize, region: PhysAddrRange) -> bool {
+        if self.len == N {
+            return false;
+        }
+        self.regions.copy_within(idx..self.len, idx + 1);
+        self.regions[idx] = region;
+        self.len += 1;
+        true
+    }
+
+    /// 按照起始地址有序插入区域，并与重叠或相邻的区域合并，列表已满时返回 `false`
+    pub fn insert(&mut self, region: PhysAddrRange) -> bool {
+        if region.is_empty() {
+            return true;
+        }
+        let mut merged = region;
+        let mut idx = 0;
+        while idx < self.len {
+            let cur = self.regions[idx];
+            if cur.end.raw() < merged.start.raw() {
+                idx += 1;
+            } else if merged.end.raw() < cur.start.raw() {
+                break;
+            } else {
+                merged.start = PhysAddr(cur.start.raw().min(merged.start.raw()));
+                merged.end = PhysAddr(cur.end.raw().max(merged.end.raw()));
+                self.remove(idx);
+            }
+        }
+        self.insert_at(idx, merged)
+    }
+
+    /// 从所有区域中移除 `region` 覆盖的部分，区域被拆分且列表已满时返回 `false`
+    pub fn subtract(&mut self, region: PhysAddrRange) -> bool {
+        let mut idx = 0;
+        while idx < self.len {
+            let cur = self.regions[idx];
+            if cur.end.raw() <= region.start.raw() || region.end.raw() <= cur.start.raw() {
+                idx += 1;
+                continue;
+            }
+            let low = PhysAddrRange::new(cur.start, region.start);
+            let high = PhysAddrRange::new(region.end, cur.end);
+            self.remove(idx);
+            if !high.is_empty() && !self.insert_at(idx, high) {
+                return false;
+            }
+            if !low.is_empty() && !self.insert_at(idx, low) {
+                return false;
+            }
+            idx += !low.is_empty() as usize + !high.is_empty() as usize;
+        }
+        true
+    }
+
+    pub fn as_slice(&self) -> &[PhysAddrRange] {
+        &self.regions[..self.len]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PhysAddrRange> {
+        self.as_slice().iter()
+    }
+}
+
 impl PhysAddr {
     pub const fn vaddr(&self) -> VirtAddr {
         VirtAddr(self.0 | PPTR_BASE)
     }
 }
 
+impl VirtAddr {
+    /// 内核窗口中的虚拟地址对应的物理地址
+    pub const fn paddr(&self) -> PhysAddr {
+        PhysAddr(self.0 - PPTR_BASE)
+    }
+}
+
+#[cfg_attr(test, allow(unused_macros))]
 macro_rules! pa {
     ($addr:expr) => {
         $crate::arch::PhysAddr::new($addr as usize)
     };
 }
 
+#[cfg_attr(test, allow(unused_macros))]
 macro_rules! va {
     ($addr:expr) => {
-        VirtAddr::new($addr as usize)
+        $crate::arch::VirtAddr::new($addr as usize)
     };
 }
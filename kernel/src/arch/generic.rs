@@ -4,7 +4,15 @@ use crate::config::{KERNEL_STACK_BITS, MAX_NUM_NODES};
 
 pub const STACK_SIZE: usize = 0x10000;
 
-pub static mut KERNEL_STACK_ALLOC: [[u8; bit!(KERNEL_STACK_BITS)]; MAX_NUM_NODES] = [[0; _]; _];
+/// 内核栈，按照栈大小对齐，栈顶的低位可以用于保存核的逻辑编号
+#[repr(C, align(4096))]
+pub struct KernelStack([u8; bit!(KERNEL_STACK_BITS)]);
+
+/// 每个核的内核栈，对应 seL4 的 `kernel_stack_alloc`
+pub static mut KERNEL_STACK_ALLOC: [KernelStack; MAX_NUM_NODES] =
+    [const { KernelStack([0; _]) }; _];
+
+const _: () = assert!(align_of::<KernelStack>() == size_of::<KernelStack>());
 
 global_asm!("
     .section .bss
@@ -2,6 +2,7 @@
 mod addr;
 
 mod aarch64;
+#[cfg(not(test))]
 mod generic;
 
 pub use aarch64::*;
@@ -0,0 +1,152 @@
+//! 启动阶段的物理内存管理
+//!
+//! 参考 seL4 `init_freemem` 和 `create_untypeds`，从 DTB 描述的物理内存中去掉内核镜像、
+//! 用户镜像、DTB 和保留内存，剩余部分作为空闲内存，最终拆分为 untyped 交给 root task。
+
+use macros::boot_code;
+
+use super::BootError;
+use crate::{
+    arch::{PhysAddr, PhysAddrRange, RegionList, MAX_UNTYPED_BITS, MIN_UNTYPED_BITS},
+    platform::{PADDR_TOP, PADDR_USER_DEVICE_TOP},
+};
+
+/// 最多记录的空闲内存区域数量
+pub const MAX_NUM_FREEMEM_REG: usize = 16;
+/// 最多记录的保留内存区域数量
+pub const MAX_NUM_RESV_REG: usize = 32;
+
+/// 启动阶段的物理内存布局
+pub struct MemoryMap {
+    /// 空闲的物理内存，均位于内核窗口中
+    pub free: RegionList<MAX_NUM_FREEMEM_REG>,
+    /// 不能作为设备 untyped 的区域，包含所有物理内存和保留内存，按地址排序
+    pub reserved: RegionList<MAX_NUM_RESV_REG>,
+}
+
+/// untyped 对应的物理内存
+#[derive(Clone, Copy)]
+pub struct UntypedRegion {
+    pub paddr: PhysAddr,
+    pub size_bits: usize,
+    pub is_device: bool,
+}
+
+impl MemoryMap {
+    /// 根据物理内存 `memory` 以及需要保留的区域 `reserved` 计算空闲内存
+    #[boot_code]
+    pub fn new(memory: &[PhysAddrRange], reserved: &[PhysAddrRange]) -> Result<Self, BootError> {
+        let mut map = Self {
+            free: RegionList::new(),
+            reserved: RegionList::new(),
+        };
+        for mem in memory {
+            let end = mem.end.raw().min(PADDR_TOP);
+            if end < mem.end.raw() {
+                log::warn!(
+                    "memory [{:#x}, {:#x}) is out of kernel window",
+                    end.max(mem.start.raw()),
+                    mem.end.raw()
+                );
+            }
+            if !map.free.insert(PhysAddrRange::new(mem.start, pa!(end)))
+                || !map.reserved.insert(*mem)
+            {
+                return Err(BootError::TooManyRegions);
+            }
+        }
+        for resv in reserved {
+            if !map.free.subtract(*resv) || !map.reserved.insert(*resv) {
+                return Err(BootError::TooManyRegions);
+            }
+        }
+        Ok(map)
+    }
+
+    /// 从最高的空闲内存中分配 `size` 字节，起始地址按照 `align_bits` 对齐
+    #[boot_code]
+    pub fn alloc(&mut self, size: usize, align_bits: usize) -> Result<PhysAddr, BootError> {
+        let start = self
+            .free
+            .as_slice()
+            .iter()
+            .rev()
+            .find_map(|region| {
+                let start = region.end.raw().checked_sub(size)? & !(bit!(align_bits) - 1);
+                (start >= region.start.raw()).then_some(start)
+            })
+            .ok_or(BootError::OutOfMemory)?;
+        let region = PhysAddrRange::new(pa!(start), pa!(start + size));
+        if !self.free.subtract(region) {
+            return Err(BootError::TooManyRegions);
+        }
+        Ok(region.start)
+    }
+
+    /// 空闲内存的总大小
+    pub fn free_size(&self) -> usize {
+        self.free.iter().map(PhysAddrRange::size).sum()
+    }
+
+    /// 不属于物理内存和保留内存的区域，作为设备内存交给用户
+    pub fn device_regions(&self) -> impl Iterator<Item = PhysAddrRange> + '_ {
+        let mut start = 0;
+        self.reserved
+            .iter()
+            .map(|resv| resv.start)
+            .chain([pa!(PADDR_USER_DEVICE_TOP)])
+            .zip(self.reserved.iter().map(|resv| resv.end).chain([pa!(0)]))
+            .map(move |(end, next)| {
+                let end = end.raw().min(PADDR_USER_DEVICE_TOP).max(start);
+                let region = PhysAddrRange::new(pa!(start), pa!(end));
+                start = next.raw().min(PADDR_USER_DEVICE_TOP);
+                region
+            })
+            .filter(|region| !region.is_empty())
+    }
+
+    /// 所有 untyped 区域，依次为设备内存、回收的 `.boot` 段 `boot_mem_reuse` 和空闲内存
+    pub fn untypeds(
+        &self,
+        boot_mem_reuse: PhysAddrRange,
+    ) -> impl Iterator<Item = UntypedRegion> + '_ {
+        self.device_regions()
+            .flat_map(|region| untyped_regions(region, true))
+            .chain(untyped_regions(boot_mem_reuse, false))
+            .chain(
+                self.free
+                    .iter()
+                    .flat_map(|region| untyped_regions(*region, false)),
+            )
+    }
+}
+
+/// 将 `region` 拆分为大小为 2 的幂并且按照大小对齐的 untyped 区域
+///
+/// 小于 [MIN_UNTYPED_BITS] 的碎片会被丢弃，单个 untyped 不超过 [MAX_UNTYPED_BITS]
+pub fn untyped_regions(
+    region: PhysAddrRange,
+    is_device: bool,
+) -> impl Iterator<Item = UntypedRegion> {
+    let mut start = region.start.raw();
+    let end = region.end.raw();
+    core::iter::from_fn(move || {
+        while start < end {
+            let mut size_bits = (end - start).ilog2() as usize;
+            size_bits = size_bits.min(MAX_UNTYPED_BITS);
+            if start != 0 {
+                size_bits = size_bits.min(start.trailing_zeros() as usize);
+            }
+            let paddr = start;
+            start += bit!(size_bits);
+            if size_bits >= MIN_UNTYPED_BITS {
+                return Some(UntypedRegion {
+                    paddr: pa!(paddr),
+                    size_bits,
+                    is_device,
+                });
+            }
+        }
+        None
+    })
+}
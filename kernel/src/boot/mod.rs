@@ -0,0 +1,214 @@
+//! 与架构无关的启动信息
+//!
+//! kernel loader 跳转到内核时会传入用户镜像以及 DTB 的位置，这里将其整理为 [BootArgs]，
+//! 后续创建 root task 时使用其中的地址信息。
+
+pub mod freemem;
+pub mod rootserver;
+
+use macros::boot_code;
+use sel4_types::bootinfo::BootInfoHeader;
+
+use crate::{
+    arch::{
+        arch_configure_idle_thread, PhysAddr, PhysAddrRange, VirtAddr, VirtAddrRange, PAGE_BITS,
+        PPTR_BASE, TCB_BITS,
+    },
+    config::{BI_FRAME_SIZE_BITS, MAX_NUM_NODES},
+    kernel::thread::set_thread_state,
+    model::statedata::node_state_on_core,
+    object::{
+        fault::ThreadStateType,
+        tcb::{TCB, TCB_OFFSET},
+    },
+    platform::{fdt::FdtError, PADDR_TOP},
+};
+
+/// 启动参数检查错误
+#[derive(PartialEq, Debug)]
+pub enum BootError {
+    /// 用户镜像的物理地址范围为空或者没有按页对齐
+    InvalidUserImage,
+    /// 用户镜像的入口不在用户镜像范围内
+    InvalidEntry,
+    /// 用户镜像不在内核窗口中
+    UserImageOutOfWindow,
+    /// DTB 不在内核窗口中
+    DtbOutOfWindow,
+    /// DTB 解析失败
+    InvalidDtb(FdtError),
+    /// 内存区域数量超过上限
+    TooManyRegions,
+    /// 没有足够的空闲内存
+    OutOfMemory,
+    /// root CNode 中没有空闲的 slot
+    OutOfSlots,
+}
+
+/// 内核镜像所在的物理内存，包括 `.boot` 段
+#[boot_code]
+pub fn kernel_image_p_reg() -> PhysAddrRange {
+    extern "C" {
+        fn _skernel();
+        fn ki_end();
+    }
+    PhysAddrRange::new(
+        pa!(_skernel as *const () as usize - PPTR_BASE),
+        pa!(ki_end as *const () as usize - PPTR_BASE),
+    )
+}
+
+/// `.boot` 段所在的物理内存，启动完成后作为 untyped 交给 root task
+#[boot_code]
+pub fn boot_mem_reuse_p_reg() -> PhysAddrRange {
+    extern "C" {
+        fn _skernel();
+        fn ki_boot_end();
+    }
+    PhysAddrRange::new(
+        pa!(_skernel as *const () as usize - PPTR_BASE),
+        pa!(ki_boot_end as *const () as usize - PPTR_BASE),
+    )
+}
+
+/// kernel loader 传入的启动参数
+///
+/// 对应 seL4 `init_kernel` 的参数，`pv_offset` 为物理地址减去虚拟地址的差值
+#[derive(Clone, Copy)]
+pub struct BootArgs {
+    /// 用户镜像所在的物理内存
+    pub ui_p_reg: PhysAddrRange,
+    /// 用户镜像的物理地址与虚拟地址的差值
+    pub pv_offset: isize,
+    /// 用户镜像入口
+    pub v_entry: VirtAddr,
+    /// DTB 所在的物理内存，loader 没有传入 DTB 时为 [None]
+    pub dtb_p_reg: Option<PhysAddrRange>,
+}
+
+impl BootArgs {
+    /// 检查 kernel loader 传入的参数，并生成 [BootArgs]
+    #[boot_code]
+    pub fn new(
+        ui_p_reg_start: usize,
+        ui_p_reg_end: usize,
+        pv_offset: isize,
+        v_entry: usize,
+        dtb_addr_p: usize,
+        dtb_size: usize,
+    ) -> Result<Self, BootError> {
+        if ui_p_reg_start >= ui_p_reg_end || !ui_p_reg_start.is_multiple_of(bit!(PAGE_BITS)) {
+            return Err(BootError::InvalidUserImage);
+        }
+        // loader 传入的地址可能溢出，溢出时同样不在内核窗口中
+        let ui_p_reg_end = ui_p_reg_end
+            .checked_next_multiple_of(bit!(PAGE_BITS))
+            .filter(|&end| end <= PADDR_TOP)
+            .ok_or(BootError::UserImageOutOfWindow)?;
+        let ui_p_reg = PhysAddrRange::new(pa!(ui_p_reg_start), pa!(ui_p_reg_end));
+
+        let dtb_p_reg = match dtb_size {
+            0 => None,
+            _ => {
+                let dtb_end = dtb_addr_p
+                    .checked_add(dtb_size)
+                    .filter(|&end| end <= PADDR_TOP)
+                    .ok_or(BootError::DtbOutOfWindow)?;
+                Some(PhysAddrRange::new(pa!(dtb_addr_p), pa!(dtb_end)))
+            }
+        };
+
+        let args = Self {
+            ui_p_reg,
+            pv_offset,
+            v_entry: va!(v_entry),
+            dtb_p_reg,
+        };
+        if !args.ui_v_reg().contains(args.v_entry) {
+            return Err(BootError::InvalidEntry);
+        }
+        Ok(args)
+    }
+
+    /// 用户镜像的虚拟地址范围
+    pub const fn ui_v_reg(&self) -> VirtAddrRange {
+        VirtAddrRange::new(
+            self.p_to_v(self.ui_p_reg.start),
+            self.p_to_v(self.ui_p_reg.end),
+        )
+    }
+
+    /// root task IPC buffer 的虚拟地址，紧跟在用户镜像之后
+    pub const fn ipc_buf_vptr(&self) -> VirtAddr {
+        self.ui_v_reg().end
+    }
+
+    /// BootInfo 页的虚拟地址，紧跟在 IPC buffer 之后
+    pub const fn bi_frame_vptr(&self) -> VirtAddr {
+        va!(self.ipc_buf_vptr().raw() + bit!(PAGE_BITS))
+    }
+
+    /// 额外 BootInfo（例如 DTB）的虚拟地址，紧跟在 BootInfo 页之后
+    pub const fn extra_bi_frame_vptr(&self) -> VirtAddr {
+        va!(self.bi_frame_vptr().raw() + bit!(BI_FRAME_SIZE_BITS))
+    }
+
+    /// 额外 BootInfo 的大小，目前只包含 DTB
+    pub const fn extra_bi_size(&self) -> usize {
+        match self.dtb_p_reg {
+            Some(dtb) => size_of::<BootInfoHeader>() + dtb.size(),
+            None => 0,
+        }
+    }
+
+    /// 额外 BootInfo 占用的内存大小，至少为一页，对应 seL4 的 `calculate_extra_bi_size_bits`
+    pub const fn extra_bi_size_bits(&self) -> usize {
+        match self.extra_bi_size() {
+            0 => 0,
+            size => size
+                .next_multiple_of(bit!(PAGE_BITS))
+                .next_power_of_two()
+                .ilog2() as usize,
+        }
+    }
+
+    /// root task 的虚拟地址范围，包括用户镜像、IPC buffer、BootInfo 和额外 BootInfo
+    pub const fn it_v_reg(&self) -> VirtAddrRange {
+        let end = match self.extra_bi_size_bits() {
+            0 => self.extra_bi_frame_vptr(),
+            bits => va!(self.extra_bi_frame_vptr().raw() + bit!(bits)),
+        };
+        VirtAddrRange::new(self.ui_v_reg().start, end)
+    }
+
+    /// 将用户镜像中的物理地址转换为虚拟地址
+    const fn p_to_v(&self, paddr: PhysAddr) -> VirtAddr {
+        va!(paddr.raw().wrapping_sub(self.pv_offset as usize))
+    }
+}
+
+/// idle 线程的 TCB 对象，对应 seL4 的 `ksIdleThreadTCB`
+#[repr(C, align(2048))]
+struct IdleThreadTcb([u8; bit!(TCB_BITS)]);
+
+static mut IDLE_THREAD_TCB: [IdleThreadTcb; MAX_NUM_NODES] = [const { IdleThreadTcb([0; _]) }; _];
+
+const _: () = assert!(align_of::<IdleThreadTcb>() == size_of::<IdleThreadTcb>());
+
+/// 为每个核创建 idle 线程并设为当前线程，对应 seL4 的 `create_idle_thread` 和 `init_core_state`
+#[boot_code]
+pub fn create_idle_thread() {
+    let idle_tcbs = unsafe { (&raw mut IDLE_THREAD_TCB).as_mut().unwrap() };
+    for (cpu, idle_tcb) in idle_tcbs.iter_mut().enumerate() {
+        let tcb = unsafe { TCB::from_ptr(idle_tcb as *mut IdleThreadTcb as usize + TCB_OFFSET) };
+        *tcb = TCB::new();
+        arch_configure_idle_thread(tcb);
+        set_thread_state(tcb, ThreadStateType::IdleThreadState);
+        tcb.set_name("idle_thread");
+        tcb.set_affinity(cpu);
+
+        let node = unsafe { &mut *node_state_on_core(cpu) };
+        node.idle_thread = tcb;
+        node.cur_thread = tcb;
+    }
+}
@@ -0,0 +1,478 @@
+//! root task 的内核对象以及初始 CSpace
+//!
+//! 参考 seL4 `create_rootserver_objects`，一次性从空闲内存中分配 root task 需要的所有对象，
+//! 然后将对应的 capability 写入 root CNode 中约定的 slot。
+
+use core::ptr::{copy_nonoverlapping, write_bytes};
+
+use macros::boot_code;
+
+use sel4_types::bootinfo::{
+    BootInfo, BootInfoHeader, SlotRegion, UntypedDesc, CAP_ASID_CONTROL, CAP_BOOT_INFO_FRAME,
+    CAP_DOMAIN, CAP_INIT_THREAD_ASID_POOL, CAP_INIT_THREAD_CNODE, CAP_INIT_THREAD_IPC_BUFFER,
+    CAP_INIT_THREAD_TCB, CAP_INIT_THREAD_VSPACE, CAP_IRQ_CONTROL, MAX_NUM_BOOTINFO_UNTYPED_CAPS,
+    NUM_INITIAL_CAPS, SEL4_BOOTINFO_HEADER_FDT, SEL4_BOOTINFO_HEADER_PADDING,
+};
+
+use super::{boot_mem_reuse_p_reg, freemem::MemoryMap, BootError};
+use crate::{
+    arch::{
+        map_it_frame_cap, map_it_pt_cap, write_it_asid_pool, AsidPool, PhysAddrRange, VirtAddr,
+        VirtAddrRange, VmRights, ARM_SMALL_PAGE, ASID_LOW_BITS, ASID_POOL_BITS, CAP_REGISTER,
+        IT_ASID, NEXT_IP, PAGE_BITS, PAGE_TABLE_BITS, PPTR_BASE, PT_INDEX_BITS, TCB_BITS,
+        VSPACE_BITS,
+    },
+    config::{BI_FRAME_SIZE_BITS, DOMAIN_SCHEDULE, MAX_PRIO, ROOT_CNODE_SIZE_BITS},
+    kernel::thread::{set_mcp, set_priority, set_thread_state, switch_to_thread},
+    model::statedata::kernel_state,
+    object::{
+        cap::{write_slot, Cap, Cte, SLOT_BITS},
+        cnode::{cte_insert, CNode},
+        fault::ThreadStateType,
+        objecttype::derive_cap,
+        structures::{
+            AsidControlCap, AsidPoolCap, CnodeCap, DomainCap, FrameCap, IrqControlCap,
+            PageTableCap, ThreadCap, UntypedCap, VspaceCap,
+        },
+        tcb::{TcbCnodeIndex, TCB, TCB_OFFSET},
+        untyped::max_free_index,
+    },
+};
+
+/// 覆盖 `reg` 需要的 `bits` 大小的页表数量
+const fn get_n_paging(reg: VirtAddrRange, bits: usize) -> usize {
+    let start = reg.start.raw() & !(bit!(bits) - 1);
+    let end = reg.end.raw().next_multiple_of(bit!(bits));
+    (end - start) >> bits
+}
+
+/// 映射 `reg` 需要的页表数量，不包括 VSpace 根页表
+///
+/// 每一级页表覆盖的范围依次为 512G、1G 和 2M
+pub const fn arch_get_n_paging(reg: VirtAddrRange) -> usize {
+    let mut n = 0;
+    let mut level = 1;
+    while level < 4 {
+        n += get_n_paging(reg, PAGE_BITS + PT_INDEX_BITS * (4 - level));
+        level += 1;
+    }
+    n
+}
+
+/// root task 使用的内核对象，地址均为内核窗口中的虚拟地址
+pub struct RootServer {
+    pub cnode: VirtAddr,
+    pub vspace: VirtAddr,
+    pub asid_pool: VirtAddr,
+    pub ipc_buf: VirtAddr,
+    pub boot_info: VirtAddr,
+    /// 额外 BootInfo，没有额外 BootInfo 时为空
+    pub extra_bi: VirtAddrRange,
+    pub tcb: VirtAddr,
+    /// 映射 root task 地址空间需要的页表
+    pub paging: VirtAddrRange,
+    /// 下一个未使用的页表
+    paging_cur: VirtAddr,
+    /// root CNode 中下一个空闲的 slot
+    pub slot_pos_cur: usize,
+}
+
+/// 按照对象大小从大到小依次分配，保证每个对象都按照自身大小对齐
+struct RootServerAlloc {
+    cur: usize,
+    end: usize,
+}
+
+impl RootServerAlloc {
+    #[boot_code]
+    fn alloc(&mut self, size_bits: usize, n: usize) -> VirtAddr {
+        let size = n * bit!(size_bits);
+        assert!(self.cur.is_multiple_of(bit!(size_bits)));
+        assert!(self.cur + size <= self.end);
+        let ptr = self.cur;
+        unsafe { write_bytes(ptr as *mut u8, 0, size) };
+        self.cur += size;
+        va!(ptr)
+    }
+}
+
+impl RootServer {
+    /// 从空闲内存中分配 root task 的内核对象，分配的内存会被清零
+    ///
+    /// `extra_bi_size_bits` 为 0 时不分配额外 BootInfo
+    #[boot_code]
+    pub fn new(
+        mem_map: &mut MemoryMap,
+        it_v_reg: VirtAddrRange,
+        extra_bi_size_bits: usize,
+    ) -> Result<Self, BootError> {
+        let cnode_size_bits = ROOT_CNODE_SIZE_BITS + SLOT_BITS;
+        let n_paging = arch_get_n_paging(it_v_reg);
+        let extra_bi_size = match extra_bi_size_bits {
+            0 => 0,
+            bits => bit!(bits),
+        };
+        let size = extra_bi_size
+            + bit!(cnode_size_bits)
+            + bit!(VSPACE_BITS)
+            + bit!(ASID_POOL_BITS)
+            + bit!(PAGE_BITS)
+            + bit!(BI_FRAME_SIZE_BITS)
+            + n_paging * bit!(PAGE_TABLE_BITS)
+            + bit!(TCB_BITS);
+        let max_bits = cnode_size_bits.max(VSPACE_BITS).max(extra_bi_size_bits);
+        let start = mem_map.alloc(size, max_bits)?.vaddr().raw();
+
+        let mut alloc = RootServerAlloc {
+            cur: start,
+            end: start + size,
+        };
+        // 额外 BootInfo 的大小不固定，需要根据大小决定分配的顺序
+        let mut extra_bi = None;
+        if extra_bi_size_bits >= cnode_size_bits {
+            extra_bi = Some(alloc.alloc(extra_bi_size_bits, 1));
+        }
+        let cnode = alloc.alloc(cnode_size_bits, 1);
+        if extra_bi_size_bits != 0 && extra_bi.is_none() {
+            extra_bi = Some(alloc.alloc(extra_bi_size_bits, 1));
+        }
+        let extra_bi = extra_bi.unwrap_or(va!(0));
+        let vspace = alloc.alloc(VSPACE_BITS, 1);
+        let asid_pool = alloc.alloc(ASID_POOL_BITS, 1);
+        let ipc_buf = alloc.alloc(PAGE_BITS, 1);
+        let boot_info = alloc.alloc(BI_FRAME_SIZE_BITS, 1);
+        let paging = alloc.alloc(PAGE_TABLE_BITS, n_paging);
+        let tcb = alloc.alloc(TCB_BITS, 1);
+        assert_eq!(alloc.cur, alloc.end);
+
+        Ok(Self {
+            cnode,
+            vspace,
+            asid_pool,
+            ipc_buf,
+            boot_info,
+            extra_bi: VirtAddrRange::new(extra_bi, va!(extra_bi.raw() + extra_bi_size)),
+            tcb,
+            paging: VirtAddrRange::new(
+                paging,
+                va!(paging.raw() + n_paging * bit!(PAGE_TABLE_BITS)),
+            ),
+            paging_cur: paging,
+            slot_pos_cur: NUM_INITIAL_CAPS,
+        })
+    }
+
+    /// 向 root CNode 的 `pos` 写入 capability
+    #[boot_code]
+    pub fn write_slot(&self, pos: usize, cap: Cap) {
+        unsafe { write_slot(self.slot(pos), cap) };
+    }
+
+    /// root CNode 中的第 `pos` 个 slot
+    #[boot_code]
+    fn slot(&self, pos: usize) -> *mut Cte {
+        let cnode = CNode::new(self.cnode.raw(), ROOT_CNODE_SIZE_BITS);
+        cnode.slot_ptr(pos).expect("slot out of root cnode")
+    }
+
+    /// BootInfo 页，启动结束后只会被 root task 读取
+    #[boot_code]
+    fn boot_info(&mut self) -> &'static mut BootInfo {
+        unsafe { &mut *(self.boot_info.raw() as *mut BootInfo) }
+    }
+
+    /// 填写 BootInfo 中与 capability 无关的部分，对应 seL4 的 `populate_bi_frame`
+    #[boot_code]
+    pub fn populate_bi_frame(
+        &mut self,
+        node_id: usize,
+        num_nodes: usize,
+        ipc_buf_vptr: VirtAddr,
+        extra_bi_size: usize,
+    ) {
+        let bi = self.boot_info();
+        bi.node_id = node_id;
+        bi.num_nodes = num_nodes;
+        bi.num_iopt_levels = 0;
+        bi.ipc_buffer = ipc_buf_vptr.raw();
+        bi.init_thread_cnode_size_bits = ROOT_CNODE_SIZE_BITS;
+        bi.init_thread_domain = 0;
+        bi.extra_len = extra_bi_size;
+    }
+
+    /// 将 DTB 复制到额外 BootInfo 中，并以只读的方式映射到 root task 的 `vptr` 处
+    #[boot_code]
+    pub fn create_extra_bi(
+        &mut self,
+        dtb_p_reg: Option<PhysAddrRange>,
+        extra_bi_size: usize,
+        vptr: VirtAddr,
+    ) -> Result<(), BootError> {
+        if self.extra_bi.is_empty() {
+            return Ok(());
+        }
+        let extra_bi = self.extra_bi.start.raw();
+        let mut offset = 0;
+        if let Some(dtb) = dtb_p_reg {
+            let header = BootInfoHeader {
+                id: SEL4_BOOTINFO_HEADER_FDT,
+                len: size_of::<BootInfoHeader>() + dtb.size(),
+            };
+            unsafe {
+                (extra_bi as *mut BootInfoHeader).write(header);
+                offset += size_of::<BootInfoHeader>();
+                let dtb_ptr = dtb.start.vaddr().raw() as *const u8;
+                copy_nonoverlapping(dtb_ptr, (extra_bi + offset) as *mut u8, dtb.size());
+                offset += dtb.size();
+            }
+        }
+        if extra_bi_size > offset {
+            let header = BootInfoHeader {
+                id: SEL4_BOOTINFO_HEADER_PADDING,
+                len: extra_bi_size - offset,
+            };
+            unsafe { ((extra_bi + offset) as *mut BootInfoHeader).write(header) };
+        }
+
+        let p_reg = PhysAddrRange::new(self.extra_bi.start.paddr(), self.extra_bi.end.paddr());
+        let pv_offset = p_reg.start.raw().wrapping_sub(vptr.raw()) as isize;
+        let slots = self.create_frames_of_region(p_reg, pv_offset, VmRights::ReadOnly, false)?;
+        self.boot_info().extra_bi_pages = slots;
+        Ok(())
+    }
+
+    /// 为所有 untyped 区域创建 capability，并记录在 BootInfo 中，对应 seL4 的 `create_untypeds`
+    ///
+    /// 超过 [MAX_NUM_BOOTINFO_UNTYPED_CAPS] 的 untyped 会被丢弃
+    #[boot_code]
+    pub fn create_untypeds(&mut self, mem_map: &MemoryMap) -> Result<(), BootError> {
+        let bi = self.boot_info();
+        let slot_pos_before = self.slot_pos_cur;
+        for ut in mem_map.untypeds(boot_mem_reuse_p_reg()) {
+            let idx = self.slot_pos_cur - slot_pos_before;
+            if idx >= MAX_NUM_BOOTINFO_UNTYPED_CAPS {
+                log::warn!(
+                    "too many untyped regions for boot info, dropping {:#x} ({} bits)",
+                    ut.paddr.raw(),
+                    ut.size_bits
+                );
+                continue;
+            }
+            // 设备内存可能不在内核窗口中，这里只保存与物理地址对应的指针，不会访问
+            let pptr = ut.paddr.raw().wrapping_add(PPTR_BASE);
+            // 初始的 untyped 标记为已用完，第一次 retype 时通过 reset 清零整个区域
+            let cap = UntypedCap::new(
+                max_free_index(ut.size_bits),
+                ut.is_device,
+                ut.size_bits,
+                pptr,
+            );
+            self.provide_cap(cap.into())?;
+            bi.untyped_list[idx] = UntypedDesc::new(ut.paddr.raw(), ut.size_bits, ut.is_device);
+        }
+        bi.untyped = SlotRegion::new(slot_pos_before, self.slot_pos_cur);
+        Ok(())
+    }
+
+    /// 将 root CNode 中剩余的 slot 记录在 BootInfo 中，所有 capability 创建完成后调用
+    #[boot_code]
+    pub fn set_empty_slots(&mut self) {
+        let slot_pos_cur = self.slot_pos_cur;
+        self.boot_info().empty = SlotRegion::new(slot_pos_cur, bit!(ROOT_CNODE_SIZE_BITS));
+    }
+
+    /// 将 capability 放到下一个空闲 slot 中，返回 slot 的位置
+    #[boot_code]
+    pub fn provide_cap(&mut self, cap: Cap) -> Result<usize, BootError> {
+        if self.slot_pos_cur >= bit!(ROOT_CNODE_SIZE_BITS) {
+            return Err(BootError::OutOfSlots);
+        }
+        let pos = self.slot_pos_cur;
+        self.write_slot(pos, cap);
+        self.slot_pos_cur += 1;
+        Ok(pos)
+    }
+
+    /// 创建 root CNode，guard 占满剩余的地址位，保证单层 CNode 可以解析所有的 CPtr
+    #[boot_code]
+    pub fn create_root_cnode(&self) -> Cap {
+        let cap = CnodeCap::new(
+            ROOT_CNODE_SIZE_BITS,
+            usize::BITS as usize - ROOT_CNODE_SIZE_BITS,
+            0,
+            self.cnode.raw(),
+        )
+        .into();
+        self.write_slot(CAP_INIT_THREAD_CNODE, cap);
+        #[cfg(feature = "mdb-check")]
+        crate::object::mdb_check::register_root_cnode(CNode::new(
+            self.cnode.raw(),
+            ROOT_CNODE_SIZE_BITS,
+        ));
+        cap
+    }
+
+    /// 创建 domain、IRQ 和 ASID 的全局控制 capability
+    #[boot_code]
+    pub fn create_control_caps(&self) {
+        self.write_slot(CAP_DOMAIN, DomainCap::new().into());
+        self.write_slot(CAP_IRQ_CONTROL, IrqControlCap::new().into());
+        self.write_slot(CAP_ASID_CONTROL, AsidControlCap::new().into());
+    }
+
+    /// 创建 root task 的 VSpace 和 ASID pool，并将 VSpace 加入 ASID pool
+    ///
+    /// 同时创建覆盖 `it_v_reg` 的所有页表，页表 capability 所在的 slot 记录在 BootInfo 中
+    #[boot_code]
+    pub fn create_it_address_space(&mut self, it_v_reg: VirtAddrRange) -> Result<(), BootError> {
+        let vspace_cap = VspaceCap::new(IT_ASID, self.vspace.raw(), true).into();
+        self.write_slot(CAP_INIT_THREAD_VSPACE, vspace_cap);
+
+        let pool = self.asid_pool.raw();
+        let ap_cap = AsidPoolCap::new(IT_ASID >> ASID_LOW_BITS, pool).into();
+        self.write_slot(CAP_INIT_THREAD_ASID_POOL, ap_cap);
+        unsafe { write_it_asid_pool(IT_ASID, pool as *mut AsidPool, self.vspace.raw()) };
+
+        let slot_pos_before = self.slot_pos_cur;
+        for level in 1..4 {
+            let bits = PAGE_BITS + PT_INDEX_BITS * (4 - level);
+            let mut vaddr = it_v_reg.start.raw() & !(bit!(bits) - 1);
+            while vaddr < it_v_reg.end.raw() {
+                let pt_cap = self.create_it_pt_cap(vaddr);
+                unsafe { map_it_pt_cap(self.vspace, pt_cap) };
+                self.provide_cap(pt_cap.into())?;
+                vaddr += bit!(bits);
+            }
+        }
+        self.boot_info().user_image_paging = SlotRegion::new(slot_pos_before, self.slot_pos_cur);
+        Ok(())
+    }
+
+    /// 从预先分配的页表中取出一个，映射在 `vaddr` 处
+    #[boot_code]
+    fn create_it_pt_cap(&mut self, vaddr: usize) -> PageTableCap {
+        let pt = self.paging_cur;
+        assert!(pt.raw() < self.paging.end.raw());
+        self.paging_cur = va!(pt.raw() + bit!(PAGE_TABLE_BITS));
+        PageTableCap::new(IT_ASID, pt.raw(), true, vaddr)
+    }
+
+    /// 创建映射在 root task 中的页，`pptr` 为页在内核窗口中的地址
+    #[boot_code]
+    fn create_mapped_it_frame_cap(
+        &self,
+        pptr: VirtAddr,
+        vptr: VirtAddr,
+        rights: VmRights,
+        executable: bool,
+    ) -> FrameCap {
+        let cap = FrameCap::new(
+            IT_ASID,
+            pptr.raw(),
+            ARM_SMALL_PAGE,
+            rights as usize,
+            false,
+            vptr.raw(),
+        );
+        unsafe { map_it_frame_cap(self.vspace, cap, executable) };
+        cap
+    }
+
+    /// 为物理内存 `reg` 中的每一页创建 capability，并映射到 `reg - pv_offset` 处
+    ///
+    /// 返回页 capability 所在的 slot
+    #[boot_code]
+    pub fn create_frames_of_region(
+        &mut self,
+        reg: PhysAddrRange,
+        pv_offset: isize,
+        rights: VmRights,
+        executable: bool,
+    ) -> Result<SlotRegion, BootError> {
+        let slot_pos_before = self.slot_pos_cur;
+        for paddr in (reg.start.raw()..reg.end.raw()).step_by(bit!(PAGE_BITS)) {
+            let pptr = pa!(paddr).vaddr();
+            let vptr = va!(paddr.wrapping_sub(pv_offset as usize));
+            let cap = self.create_mapped_it_frame_cap(pptr, vptr, rights, executable);
+            self.provide_cap(cap.into())?;
+        }
+        Ok(SlotRegion::new(slot_pos_before, self.slot_pos_cur))
+    }
+
+    /// 创建用户镜像的页，页可读可写可执行，所在的 slot 记录在 BootInfo 中
+    #[boot_code]
+    pub fn create_user_image_frames(
+        &mut self,
+        ui_p_reg: PhysAddrRange,
+        pv_offset: isize,
+    ) -> Result<(), BootError> {
+        let slots = self.create_frames_of_region(ui_p_reg, pv_offset, VmRights::ReadWrite, true)?;
+        self.boot_info().user_image_frames = slots;
+        Ok(())
+    }
+
+    /// 创建 root task 的 IPC buffer 和 BootInfo 页，两者都映射在 root task 的 VSpace 中
+    #[boot_code]
+    pub fn create_frame_caps(&self, ipc_buf_vptr: VirtAddr, bi_frame_vptr: VirtAddr) -> (Cap, Cap) {
+        let ipc_buf_cap = self
+            .create_mapped_it_frame_cap(self.ipc_buf, ipc_buf_vptr, VmRights::ReadWrite, false)
+            .into();
+        let bi_frame_cap = self
+            .create_mapped_it_frame_cap(self.boot_info, bi_frame_vptr, VmRights::ReadOnly, false)
+            .into();
+        self.write_slot(CAP_INIT_THREAD_IPC_BUFFER, ipc_buf_cap);
+        self.write_slot(CAP_BOOT_INFO_FRAME, bi_frame_cap);
+        (ipc_buf_cap, bi_frame_cap)
+    }
+
+    /// 创建 root task 的 TCB，从 `v_entry` 开始执行，x0 中保存 BootInfo 的地址
+    ///
+    /// root CNode、VSpace 和 IPC buffer 的 capability 被复制到 TCB 的 CTE 中
+    #[boot_code]
+    pub fn create_initial_thread(
+        &self,
+        v_entry: VirtAddr,
+        bi_frame_vptr: VirtAddr,
+        ipc_buf_vptr: VirtAddr,
+    ) -> Cap {
+        let tcb = unsafe { TCB::from_ptr(self.tcb.raw() + TCB_OFFSET) };
+        *tcb = TCB::new();
+
+        let cnode_slot = self.slot(CAP_INIT_THREAD_CNODE);
+        let vspace_slot = self.slot(CAP_INIT_THREAD_VSPACE);
+        let ipc_buf_slot = self.slot(CAP_INIT_THREAD_IPC_BUFFER);
+        unsafe {
+            cte_insert(**cnode_slot, cnode_slot, tcb.cte_ptr(TcbCnodeIndex::CTable));
+            cte_insert(
+                **vspace_slot,
+                vspace_slot,
+                tcb.cte_ptr(TcbCnodeIndex::VTable),
+            );
+            let ipc_buf_cap = derive_cap(&*ipc_buf_slot, **ipc_buf_slot)
+                .expect("failed to derive ipc buffer cap");
+            cte_insert(
+                ipc_buf_cap,
+                ipc_buf_slot,
+                tcb.cte_ptr(TcbCnodeIndex::Buffer),
+            );
+        }
+        tcb.set_ipc_buffer(ipc_buf_vptr);
+        tcb.set_name("rootserver");
+        tcb.set_domain(DOMAIN_SCHEDULE[unsafe { (*kernel_state()).dom_schedule_idx }].domain);
+        set_mcp(tcb, MAX_PRIO);
+        set_priority(tcb, MAX_PRIO);
+
+        let context = tcb.context();
+        context.set_register(CAP_REGISTER, bi_frame_vptr.raw());
+        context.set_register(NEXT_IP, v_entry.raw());
+
+        set_thread_state(tcb, ThreadStateType::Running);
+        switch_to_thread(tcb);
+
+        let cap = ThreadCap::new(self.tcb.raw() + TCB_OFFSET).into();
+        self.write_slot(CAP_INIT_THREAD_TCB, cap);
+        cap
+    }
+}
+
+const _: () = assert!(size_of::<BootInfo>() <= bit!(BI_FRAME_SIZE_BITS));
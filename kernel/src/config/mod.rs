@@ -1,2 +1,136 @@
-pub const MAX_NUM_NODES: usize = 1;
+/// 最多支持的核数量，编译时通过环境变量 `SMP` 指定，默认为 1，需要不小于 QEMU 的 `-smp`
+pub const MAX_NUM_NODES: usize = parse_usize(env!("MAX_NUM_NODES"));
+/// 每个核的内核栈大小，栈顶按照该大小对齐，低位用于保存核的逻辑编号
 pub const KERNEL_STACK_BITS: usize = 12;
+/// BootInfo 页的大小
+pub const BI_FRAME_SIZE_BITS: usize = 12;
+/// root task CNode 的 slot 数量
+pub const ROOT_CNODE_SIZE_BITS: usize = 12;
+/// 两次检查中断之间最多完成的工作单元数量，对应 seL4 的 `CONFIG_MAX_NUM_WORK_UNITS_PER_PREEMPTION`
+pub const MAX_NUM_WORK_UNITS_PER_PREEMPTION: usize = 100;
+/// 一次 Untyped_Retype 最多创建的对象数量，对应 seL4 的 `CONFIG_RETYPE_FAN_OUT_LIMIT`
+pub const RETYPE_FAN_OUT_LIMIT: usize = 256;
+/// reset untyped 时每次清零的大小，每清零一块检查一次抢占，对应 seL4 的 `CONFIG_RESET_CHUNK_BITS`
+pub const RESET_CHUNK_BITS: usize = 8;
+/// 优先级的数量，对应 seL4 的 `CONFIG_NUM_PRIORITIES`
+pub const NUM_PRIORITIES: usize = 256;
+/// 最高优先级，对应 seL4 的 `seL4_MaxPrio`
+pub const MAX_PRIO: usize = NUM_PRIORITIES - 1;
+/// 调度 domain 的数量，编译时通过环境变量 `NUM_DOMAINS` 指定，默认为 1，对应 seL4 的 `CONFIG_NUM_DOMAINS`
+pub const NUM_DOMAINS: usize = parse_usize(env!("NUM_DOMAINS"));
+/// domain 调度表，编译时通过环境变量 `DOMAIN_SCHEDULE` 以 `domain:length,...` 的格式指定，
+/// 默认为 `0:1`，按顺序循环执行，长度的单位为时钟中断的次数，对应 seL4 的 `ksDomSchedule`
+pub const DOMAIN_SCHEDULE: [DomainSchedule; domain_schedule_length(env!("DOMAIN_SCHEDULE"))] =
+    parse_domain_schedule(env!("DOMAIN_SCHEDULE"));
+/// 线程每次被调度时的时间片长度，单位为时钟中断的次数，对应 seL4 的 `CONFIG_TIME_SLICE`
+pub const TIME_SLICE: usize = 5;
+/// 是否允许非对齐访问，关闭时开启 SCTLR 的对齐检查
+pub const ALLOW_UNALIGNED_ACCESS: bool = true;
+/// 调试时关闭 L1 指令缓存
+pub const DEBUG_DISABLE_L1_ICACHE: bool = false;
+/// 调试时关闭 L1 数据缓存
+pub const DEBUG_DISABLE_L1_DCACHE: bool = false;
+/// 允许用户态执行缓存维护指令以及读取 CTR_EL0
+pub const AARCH64_USER_CACHE_ENABLE: bool = true;
+
+/// 在编译期解析十进制数字
+const fn parse_usize(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut value = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        assert!(bytes[i].is_ascii_digit(), "invalid number in config");
+        value = value * 10 + (bytes[i] - b'0') as usize;
+        i += 1;
+    }
+    value
+}
+
+/// domain 调度表中的一项，对应 seL4 的 `dschedule_t`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DomainSchedule {
+    pub domain: usize,
+    pub length: usize,
+}
+
+/// domain 调度表中的项数
+const fn domain_schedule_length(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut count = 1;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b',' {
+            count += 1;
+        }
+        i += 1;
+    }
+    count
+}
+
+/// 在编译期解析 `domain:length,...` 格式的 domain 调度表
+const fn parse_domain_schedule<const N: usize>(s: &str) -> [DomainSchedule; N] {
+    let bytes = s.as_bytes();
+    let mut schedule = [DomainSchedule {
+        domain: 0,
+        length: 0,
+    }; N];
+    let (mut entry, mut value, mut domain) = (0, 0, None);
+    let mut i = 0;
+    while i <= bytes.len() {
+        let c = if i < bytes.len() { bytes[i] } else { b',' };
+        if c == b':' && domain.is_none() {
+            domain = Some(value);
+            value = 0;
+        } else if c == b',' {
+            let Some(domain) = domain.take() else {
+                panic!("invalid domain schedule in config");
+            };
+            schedule[entry] = DomainSchedule {
+                domain,
+                length: value,
+            };
+            entry += 1;
+            value = 0;
+        } else {
+            assert!(c.is_ascii_digit(), "invalid domain schedule in config");
+            value = value * 10 + (c - b'0') as usize;
+        }
+        i += 1;
+    }
+    schedule
+}
+
+const fn _check_domain_schedule() {
+    let mut i = 0;
+    while i < DOMAIN_SCHEDULE.len() {
+        assert!(
+            DOMAIN_SCHEDULE[i].domain < NUM_DOMAINS,
+            "domain out of range in schedule"
+        );
+        assert!(DOMAIN_SCHEDULE[i].length > 0, "empty domain in schedule");
+        i += 1;
+    }
+}
+const _: () = _check_domain_schedule();
+// seL4 中 TCB 的 domain 只有 1 字节
+const _: () = assert!(NUM_DOMAINS >= 1 && NUM_DOMAINS <= 256);
+
+// 核的逻辑编号保存在内核栈顶的低位
+const _: () = assert!(MAX_NUM_NODES >= 1 && MAX_NUM_NODES <= bit!(KERNEL_STACK_BITS));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn domain_schedule() {
+        const SCHEDULE: &str = "0:5,2:10,1:1";
+        assert_eq!(domain_schedule_length(SCHEDULE), 3);
+        let parse = |domain, length| DomainSchedule { domain, length };
+        assert_eq!(
+            parse_domain_schedule::<3>(SCHEDULE),
+            [parse(0, 5), parse(2, 10), parse(1, 1)]
+        );
+        assert_eq!(parse_domain_schedule::<1>("0:1"), [parse(0, 1)]);
+    }
+}
@@ -1 +1,17 @@
-pub fn init_timer() {}
+//! ARM Generic Timer.
+
+use aarch64_cpu::registers::{Readable, Writeable, CNTFRQ_EL0, CNTP_CTL_EL0, CNTP_TVAL_EL0};
+
+/// 时钟中断的间隔，单位为毫秒，对应 seL4 的 `CONFIG_TIMER_TICK_MS`
+const TIMER_TICK_MS: u64 = 2;
+
+/// 重新装载 non-secure 物理计时器，同时清除本次时钟中断，对应 seL4 的 `resetTimer`
+pub fn reset_timer() {
+    CNTP_TVAL_EL0.set(CNTFRQ_EL0.get() * TIMER_TICK_MS / 1000);
+}
+
+/// 启动当前核的 non-secure 物理计时器，对应 seL4 的 `initTimer`
+pub fn init_timer() {
+    reset_timer();
+    CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET + CNTP_CTL_EL0::IMASK::CLEAR);
+}
@@ -1,3 +1,46 @@
-pub fn cpu_init_local_irq_controller() {}
+//! ARM Generic Interrupt Controller v2.
 
-pub fn init_irq_controller() {}
+use arm_gicv2::{GicCpuInterface, GicDistributor};
+use spin::Mutex;
+
+use crate::platform::{GIC_CPU_PPTR, GIC_DIST_PPTR, PLATFORM};
+
+/// 没有等待处理的中断时 GICC_IAR 返回的编号，对应 seL4 的 `irqInvalid`
+pub const IRQ_INVALID: usize = 1023;
+
+static GIC_DIST: Mutex<GicDistributor> = Mutex::new(GicDistributor::new(GIC_DIST_PPTR.raw() as _));
+static GIC_CPU: Mutex<GicCpuInterface> = Mutex::new(GicCpuInterface::new(GIC_CPU_PPTR.raw() as _));
+
+/// 初始化当前核的 CPU interface 并打开内核时钟中断，对应 seL4 的 `cpu_initLocalIRQController`
+///
+/// 时钟中断是 PPI，每个核的使能位是独立的
+pub fn cpu_init_local_irq_controller() {
+    let timer_irq = PLATFORM.lock().kernel_timer_irq();
+    GIC_CPU.lock().init();
+    mask_interrupt(false, timer_irq);
+}
+
+/// 初始化 distributor，对应 seL4 的 `initIRQController`
+///
+/// distributor 初始化时会关闭所有中断，包括主核已经打开的时钟中断，需要重新打开
+pub fn init_irq_controller() {
+    let timer_irq = PLATFORM.lock().kernel_timer_irq();
+    GIC_DIST.lock().init();
+    mask_interrupt(false, timer_irq);
+}
+
+/// 屏蔽或打开中断，对应 seL4 的 `maskInterrupt`
+pub fn mask_interrupt(disable: bool, irq: usize) {
+    GIC_DIST.lock().set_enable(irq, !disable);
+}
+
+/// 读取 GICC_IAR 确认当前优先级最高的中断，返回中断编号，
+/// 没有中断时返回 [IRQ_INVALID]，对应 seL4 的 `getActiveIRQ`
+pub fn get_active_irq() -> usize {
+    GIC_CPU.lock().iar() as usize & 0x3ff
+}
+
+/// 写 GICC_EOIR 结束中断的处理，对应 seL4 的 `ackInterrupt`
+pub fn ack_interrupt(irq: usize) {
+    GIC_CPU.lock().eoi(irq as u32);
+}
@@ -3,6 +3,9 @@ mod gicv2;
 mod pl011;
 mod psci;
 
-pub use aarch_timer::init_timer;
-pub use gicv2::{cpu_init_local_irq_controller, init_irq_controller};
-pub use psci::system_off;
+pub use aarch_timer::{init_timer, reset_timer};
+pub use gicv2::{
+    ack_interrupt, cpu_init_local_irq_controller, get_active_irq, init_irq_controller,
+    mask_interrupt, IRQ_INVALID,
+};
+pub use psci::{cpu_on, set_psci_method, system_off};
@@ -3,11 +3,9 @@
 use arm_pl011::Pl011Uart;
 use spin::Mutex;
 
-use crate::{arch::PhysAddr, console::Console};
+use crate::{console::Console, platform::UART_PPTR};
 
-const PL011_UART: PhysAddr = pa!(0x0900_0000);
-
-static UART: Mutex<Pl011Uart> = Mutex::new(Pl011Uart::new(PL011_UART.vaddr().raw() as _));
+static UART: Mutex<Pl011Uart> = Mutex::new(Pl011Uart::new(UART_PPTR.raw() as _));
 
 impl Console {
     /// Writes a byte to the console.
@@ -2,7 +2,12 @@
 
 #![allow(dead_code)]
 
-use core::hint::spin_loop;
+use core::{
+    hint::spin_loop,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::platform::PsciMethod;
 
 pub const PSCI_0_2_FN_BASE: u32 = 0x84000000;
 pub const PSCI_0_2_64BIT: u32 = 0x40000000;
@@ -81,8 +86,19 @@ fn psci_hvc_call(func: u32, arg0: usize, arg1: usize, arg2: usize) -> usize {
     ret
 }
 
+/// 是否使用 hvc 调用 PSCI，默认使用 smc
+static PSCI_USE_HVC: AtomicBool = AtomicBool::new(false);
+
+/// 设置 PSCI 的调用方式，对应 DTB 中 psci 节点的 `method` 属性
+pub fn set_psci_method(method: PsciMethod) {
+    PSCI_USE_HVC.store(method == PsciMethod::Hvc, Ordering::Relaxed);
+}
+
 fn psci_call(func: u32, arg0: usize, arg1: usize, arg2: usize) -> Result<(), PsciError> {
-    let ret = arm_smccc_smc(func, arg0, arg1, arg2);
+    let ret = match PSCI_USE_HVC.load(Ordering::Relaxed) {
+        true => psci_hvc_call(func, arg0, arg1, arg2),
+        false => arm_smccc_smc(func, arg0, arg1, arg2),
+    };
     if ret == 0 {
         Ok(())
     } else {
@@ -106,11 +122,12 @@ pub fn system_off() -> ! {
 /// `target_cpu` contains a copy of the affinity fields of the MPIDR register.
 /// `entry_point` is the physical address of the secondary CPU's entry point.
 /// `arg` will be passed to the `X0` register of the secondary CPU.
-pub fn cpu_on(target_cpu: usize, entry_point: usize, arg: usize) {
+pub fn cpu_on(target_cpu: usize, entry_point: usize, arg: usize) -> Result<(), PsciError> {
     let res = psci_call(PSCI_0_2_FN64_CPU_ON, target_cpu, entry_point, arg);
-    if let Err(e) = res {
+    if let Err(e) = &res {
         log::error!("failed to boot CPU {:x} ({:?})", target_cpu, e);
     }
+    res
 }
 
 /// Power down the calling core. This call is intended for use in hotplug. A
@@ -0,0 +1,577 @@
+//! 线程状态和调度，对应 seL4 `kernel/thread.c`
+//!
+//! 内核在返回用户态之前调用 [schedule]，根据 [SchedulerAction] 决定继续运行当前线程、
+//! 切换到指定的线程或者从就绪队列中选择优先级最高的线程。就绪队列中不包括当前线程，
+//! 当前线程被切换出去时重新加入就绪队列。
+
+use sel4_types::message::MessageInfo;
+
+use crate::{
+    api::failures::SyscallError,
+    arch::{
+        get_current_cpu_index, lookup_ipc_buffer, BADGE_REGISTER, FAULT_IP, MSG_INFO_REGISTER,
+        NEXT_IP,
+    },
+    config::{DOMAIN_SCHEDULE, NUM_DOMAINS, TIME_SLICE},
+    model::{
+        preemption::reset_work_units,
+        statedata::{kernel_state, node_state, SchedulerAction},
+    },
+    object::{
+        cap::Cte,
+        fault::{LookupFault, ThreadStateType},
+        structures::ReplyCap,
+        tcb::{set_mr, tcb_sched_append, tcb_sched_dequeue, tcb_sched_enqueue, TcbCnodeIndex, TCB},
+    },
+};
+
+/// 线程可以被调度运行，对应 seL4 的 `isRunnable`
+pub fn is_runnable(tcb: &TCB) -> bool {
+    matches!(
+        tcb.state().ts_type(),
+        ThreadStateType::Running | ThreadStateType::Restart
+    )
+}
+
+/// 线程处于停止或阻塞状态，对应 seL4 的 `isStopped`
+pub fn is_stopped(tcb: &TCB) -> bool {
+    matches!(
+        tcb.state().ts_type(),
+        ThreadStateType::InActive
+            | ThreadStateType::BlockedOnReceive
+            | ThreadStateType::BlockedOnSend
+            | ThreadStateType::BlockedOnNotification
+            | ThreadStateType::BlockedOnReply
+    )
+}
+
+/// 当前线程，对应 seL4 的 `NODE_STATE(ksCurThread)`
+///
+/// # Safety
+///
+/// 同 [node_state]，解引用时不能与同一个 TCB 的其他引用重叠
+pub unsafe fn cur_thread() -> *mut TCB {
+    unsafe { (*node_state()).cur_thread }
+}
+
+pub fn is_cur_thread(tcb: &TCB) -> bool {
+    tcb.ptr() == unsafe { cur_thread() } as usize
+}
+
+/// 就绪队列使用的 domain，只有一个 domain 时总是 0
+fn ready_queue_domain() -> usize {
+    if NUM_DOMAINS > 1 {
+        unsafe { (*kernel_state()).cur_domain }
+    } else {
+        0
+    }
+}
+
+/// 对应 seL4 的 `SCHED_ENQUEUE`
+fn sched_enqueue(tcb: &mut TCB) {
+    tcb_sched_enqueue(tcb);
+    // TODO: 线程在其他核上时通过 IPI 通知该核重新调度，对应 seL4 的 `remoteQueueUpdate`
+}
+
+/// 对应 seL4 的 `SCHED_APPEND`
+fn sched_append(tcb: &mut TCB) {
+    tcb_sched_append(tcb);
+    // TODO: 线程在其他核上时通过 IPI 通知该核重新调度，对应 seL4 的 `remoteQueueUpdate`
+}
+
+/// 恢复线程的执行，`Restart` 状态的线程从触发异常的指令重新开始执行，对应 seL4 的 `activateThread`
+pub fn activate_thread() {
+    let thread = unsafe { &mut *cur_thread() };
+    match thread.state().ts_type() {
+        ThreadStateType::Running | ThreadStateType::IdleThreadState => {}
+        ThreadStateType::Restart => {
+            let context = thread.context();
+            context.set_register(NEXT_IP, context.get_register(FAULT_IP));
+            set_thread_state(thread, ThreadStateType::Running);
+        }
+        ts_type => panic!("current thread is blocked: {:?}", ts_type),
+    }
+}
+
+/// 设置线程状态，当前线程不再可运行时需要重新调度，对应 seL4 的 `setThreadState`
+pub fn set_thread_state(tcb: &mut TCB, ts_type: ThreadStateType) {
+    tcb.state_mut().set_ts_type(ts_type);
+    schedule_tcb(tcb);
+}
+
+/// 对应 seL4 的 `scheduleTCB`
+pub fn schedule_tcb(tcb: &mut TCB) {
+    if is_cur_thread(tcb)
+        && unsafe { (*node_state()).scheduler_action } == SchedulerAction::ResumeCurrentThread
+        && !is_runnable(tcb)
+    {
+        reschedule_required();
+    }
+}
+
+/// 放弃 [SchedulerAction::SwitchToThread] 中指定的线程，下次调度时重新选择，
+/// 对应 seL4 的 `rescheduleRequired`
+pub fn reschedule_required() {
+    if let SchedulerAction::SwitchToThread(candidate) = unsafe { (*node_state()).scheduler_action }
+    {
+        sched_enqueue(unsafe { &mut *candidate });
+    }
+    unsafe { (*node_state()).scheduler_action = SchedulerAction::ChooseNewThread };
+}
+
+/// 被唤醒的线程，可能抢占当前线程，对应 seL4 的 `possibleSwitchTo`
+///
+/// 不在当前 domain 或者不在当前核的线程只加入就绪队列
+pub fn possible_switch_to(target: &mut TCB) {
+    let cur_domain = unsafe { (*kernel_state()).cur_domain };
+    if cur_domain != target.domain() || target.affinity() != get_current_cpu_index() {
+        sched_enqueue(target);
+    } else if unsafe { (*node_state()).scheduler_action } != SchedulerAction::ResumeCurrentThread {
+        reschedule_required();
+        sched_enqueue(target);
+    } else {
+        unsafe { (*node_state()).scheduler_action = SchedulerAction::SwitchToThread(target) };
+    }
+}
+
+/// 根据 [SchedulerAction] 选择下一个运行的线程，对应 seL4 的 `schedule`
+///
+/// 就绪队列的操作会修改队列中其他 TCB 的指针，这里不长期持有当前线程和 [NodeState] 的引用
+pub fn schedule() {
+    let action = unsafe { (*node_state()).scheduler_action };
+    if action != SchedulerAction::ResumeCurrentThread {
+        let cur = unsafe { cur_thread() };
+        let was_runnable = is_runnable(unsafe { &*cur });
+        if was_runnable {
+            sched_enqueue(unsafe { &mut *cur });
+        }
+        let (cur_prio, is_idle) =
+            unsafe { ((*cur).priority(), cur == (*node_state()).idle_thread) };
+
+        match action {
+            SchedulerAction::SwitchToThread(candidate) => {
+                let candidate = unsafe { &mut *candidate };
+                debug_assert!(is_runnable(candidate));
+                // 当前线程的优先级更高时不需要检查位图，与 fastpath 保持一致
+                let fastfail = is_idle || candidate.priority() < cur_prio;
+                let dom = ready_queue_domain();
+                let ready_queues = unsafe { &(*node_state()).ready_queues };
+                if fastfail && !ready_queues.is_highest_prio(dom, candidate.priority()) {
+                    sched_enqueue(candidate);
+                    unsafe { (*node_state()).scheduler_action = SchedulerAction::ChooseNewThread };
+                    schedule_choose_new_thread();
+                } else if was_runnable && candidate.priority() == cur_prio {
+                    // 当前线程位于队列头部，将 candidate 放在队列尾部，由 choose_thread 选择当前线程
+                    sched_append(candidate);
+                    unsafe { (*node_state()).scheduler_action = SchedulerAction::ChooseNewThread };
+                    schedule_choose_new_thread();
+                } else {
+                    debug_assert!(!is_cur_thread(candidate));
+                    switch_to_thread(candidate);
+                }
+            }
+            _ => schedule_choose_new_thread(),
+        }
+    }
+    unsafe { (*node_state()).scheduler_action = SchedulerAction::ResumeCurrentThread };
+}
+
+/// 当前 domain 的时间用完时切换到下一个 domain，然后选择线程，对应 seL4 的 `scheduleChooseNewThread`
+fn schedule_choose_new_thread() {
+    if unsafe { (*kernel_state()).domain_time } == 0 {
+        next_domain();
+    }
+    choose_thread();
+}
+
+/// 切换到 [DOMAIN_SCHEDULE] 中的下一个 domain，对应 seL4 的 `nextDomain`
+fn next_domain() {
+    let state = unsafe { &mut *kernel_state() };
+    state.dom_schedule_idx = (state.dom_schedule_idx + 1) % DOMAIN_SCHEDULE.len();
+    let schedule = DOMAIN_SCHEDULE[state.dom_schedule_idx];
+    state.cur_domain = schedule.domain;
+    state.domain_time = schedule.length;
+    reset_work_units();
+}
+
+/// 切换到就绪队列中优先级最高的线程，没有就绪线程时切换到 idle 线程，对应 seL4 的 `chooseThread`
+fn choose_thread() {
+    let dom = ready_queue_domain();
+    let thread = unsafe {
+        let ready_queues = &(*node_state()).ready_queues;
+        (!ready_queues.is_empty(dom))
+            .then(|| ready_queues.head(dom, ready_queues.highest_prio(dom)))
+    };
+    match thread {
+        Some(thread) => {
+            let thread = unsafe { &mut *thread };
+            debug_assert!(is_runnable(thread));
+            switch_to_thread(thread);
+        }
+        None => switch_to_idle_thread(),
+    }
+}
+
+/// 将 `tcb` 设为当前线程，对应 seL4 的 `switchToThread`
+///
+/// 地址空间在返回用户态时切换
+pub fn switch_to_thread(tcb: &mut TCB) {
+    tcb_sched_dequeue(tcb);
+    unsafe { (*node_state()).cur_thread = tcb };
+}
+
+/// 将 idle 线程设为当前线程，对应 seL4 的 `switchToIdleThread`
+pub fn switch_to_idle_thread() {
+    let node = unsafe { &mut *node_state() };
+    node.cur_thread = node.idle_thread;
+}
+
+/// 在线程的 reply slot 中创建 master reply capability，对应 seL4 的 `setupReplyMaster`
+pub fn setup_reply_master(tcb: &mut TCB) {
+    let tcb_ptr = tcb.ptr();
+    let slot = tcb.cte(TcbCnodeIndex::Reply);
+    if slot.is_null() {
+        *slot = Cte::new(ReplyCap::new(true, true, tcb_ptr).into());
+        slot.set_revocable(true);
+        slot.set_first_badged(true);
+    }
+}
+
+/// 内核处理的调用成功时回复空消息，对应 seL4 的 `replyFromKernel_success_empty`
+pub fn reply_from_kernel_success_empty(thread: &mut TCB) {
+    let context = thread.context();
+    context.set_register(BADGE_REGISTER, 0);
+    context.set_register(MSG_INFO_REGISTER, MessageInfo::new(0, 0, 0, 0).to_word());
+}
+
+/// 内核处理的调用失败时回复错误码，label 为 `seL4_Error`，消息中为错误的详细信息，
+/// 对应 seL4 的 `replyFromKernel_error`
+pub fn reply_from_kernel_error(thread: &mut TCB, error: SyscallError) {
+    let ipc_buffer = lookup_ipc_buffer(true, thread);
+    thread.context().set_register(BADGE_REGISTER, 0);
+    let length = set_mrs_syscall_error(thread, ipc_buffer, error);
+    let info = MessageInfo::new(error.code() as usize, 0, 0, length);
+    thread
+        .context()
+        .set_register(MSG_INFO_REGISTER, info.to_word());
+}
+
+/// 写入系统调用错误的详细信息，返回消息长度，对应 seL4 的 `setMRs_syscall_error`
+fn set_mrs_syscall_error(
+    thread: &mut TCB,
+    ipc_buffer: Option<*mut usize>,
+    error: SyscallError,
+) -> usize {
+    match error {
+        SyscallError::InvalidArgument { number } | SyscallError::InvalidCapability { number } => {
+            set_mr(thread, ipc_buffer, 0, number)
+        }
+        SyscallError::RangeError { min, max } => {
+            set_mr(thread, ipc_buffer, 0, min);
+            set_mr(thread, ipc_buffer, 1, max)
+        }
+        SyscallError::FailedLookup { was_source, fault } => {
+            set_mr(thread, ipc_buffer, 0, was_source as usize);
+            set_mrs_lookup_failure(thread, ipc_buffer, fault, 1)
+        }
+        SyscallError::NotEnoughMemory { memory_left } => set_mr(thread, ipc_buffer, 0, memory_left),
+        SyscallError::IllegalOperation
+        | SyscallError::AlignmentError
+        | SyscallError::TruncatedMessage
+        | SyscallError::DeleteFirst
+        | SyscallError::RevokeFirst => 0,
+    }
+}
+
+/// 从第 `offset` 个消息开始写入查找失败的类型和信息，返回消息长度，
+/// 对应 seL4 的 `setMRs_lookup_failure`
+///
+/// 类型为 `lufType + 1`，与 libsel4 中的 `seL4_LookupFailureType` 一致
+fn set_mrs_lookup_failure(
+    thread: &mut TCB,
+    ipc_buffer: Option<*mut usize>,
+    fault: LookupFault,
+    offset: usize,
+) -> usize {
+    let mut set = |i: usize, value: usize| set_mr(thread, ipc_buffer, offset + i, value);
+    match fault {
+        LookupFault::InvalidRoot {} => set(0, 1),
+        LookupFault::MissingCapability { bits_left } => {
+            set(0, 2);
+            set(1, bits_left as usize)
+        }
+        LookupFault::DepthMismatch {
+            bits_left,
+            bits_found,
+        } => {
+            set(0, 3);
+            set(1, bits_left as usize);
+            set(2, bits_found as usize)
+        }
+        LookupFault::GuardMismatch {
+            bits_left,
+            bits_found,
+            guard_found,
+        } => {
+            set(0, 4);
+            set(1, bits_left as usize);
+            set(2, guard_found as usize);
+            set(3, bits_found as usize)
+        }
+    }
+}
+
+/// 挂起线程，正在运行的线程恢复时从下一条指令开始执行，对应 seL4 的 `suspend`
+pub fn suspend(tcb: &mut TCB) {
+    // TODO: 通过 cancelIPC 将线程移出 endpoint 和 notification 的等待队列
+    if tcb.state().ts_type() == ThreadStateType::Running {
+        let context = tcb.context();
+        context.set_register(FAULT_IP, context.get_register(NEXT_IP));
+    }
+    set_thread_state(tcb, ThreadStateType::InActive);
+    tcb_sched_dequeue(tcb);
+}
+
+/// 恢复停止或阻塞的线程，从触发异常的指令重新开始执行，对应 seL4 的 `restart`
+pub fn restart(tcb: &mut TCB) {
+    if is_stopped(tcb) {
+        // TODO: 通过 cancelIPC 将线程移出 endpoint 和 notification 的等待队列
+        setup_reply_master(tcb);
+        set_thread_state(tcb, ThreadStateType::Restart);
+        sched_enqueue(tcb);
+        possible_switch_to(tcb);
+    }
+}
+
+/// 设置线程的优先级，对应 seL4 的 `setPriority`
+pub fn set_priority(tcb: &mut TCB, prio: usize) {
+    tcb_sched_dequeue(tcb);
+    tcb.set_priority(prio);
+    if is_runnable(tcb) {
+        if is_cur_thread(tcb) {
+            reschedule_required();
+        } else {
+            possible_switch_to(tcb);
+        }
+    }
+}
+
+/// 将线程移到 `dom` 中，对应 seL4 的 `setDomain`
+pub fn set_domain(tcb: &mut TCB, dom: usize) {
+    tcb_sched_dequeue(tcb);
+    tcb.set_domain(dom);
+    if is_runnable(tcb) {
+        sched_enqueue(tcb);
+    }
+    if is_cur_thread(tcb) {
+        reschedule_required();
+    }
+}
+
+/// 设置线程的最大可控优先级，对应 seL4 的 `setMCPriority`
+pub fn set_mcp(tcb: &mut TCB, mcp: usize) {
+    tcb.set_mcp(mcp);
+}
+
+/// 时钟中断时减少当前线程的时间片，用完后放到队列尾部并重新调度，对应 seL4 的 `timerTick`
+///
+/// 有多个 domain 时同时减少当前 domain 的剩余时间，用完后在下次调度时切换 domain
+pub fn timer_tick() {
+    let cur = unsafe { &mut *cur_thread() };
+    if cur.state().ts_type() == ThreadStateType::Running {
+        if cur.time_slice() > 1 {
+            cur.set_time_slice(cur.time_slice() - 1);
+        } else {
+            cur.set_time_slice(TIME_SLICE);
+            sched_append(cur);
+            reschedule_required();
+        }
+    }
+
+    if NUM_DOMAINS > 1 {
+        let domain_time = unsafe {
+            (*kernel_state()).domain_time -= 1;
+            (*kernel_state()).domain_time
+        };
+        if domain_time == 0 {
+            reschedule_required();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::test_utils::alloc_tcb;
+
+    /// 当前线程为 idle 线程
+    fn setup() -> *mut TCB {
+        let idle = alloc_tcb();
+        idle.state_mut()
+            .set_ts_type(ThreadStateType::IdleThreadState);
+        let node = unsafe { &mut *node_state() };
+        node.idle_thread = idle;
+        node.cur_thread = idle;
+        node.scheduler_action = SchedulerAction::ResumeCurrentThread;
+        idle
+    }
+
+    fn runnable(prio: usize) -> &'static mut TCB {
+        let tcb = alloc_tcb();
+        tcb.set_priority(prio);
+        tcb.state_mut().set_ts_type(ThreadStateType::Running);
+        tcb
+    }
+
+    fn cur() -> *mut TCB {
+        unsafe { cur_thread() }
+    }
+
+    #[test]
+    fn choose_highest_priority() {
+        let idle = setup();
+        let [low1, high, low2] = [runnable(10), runnable(200), runnable(10)];
+        for tcb in [&mut *low1, high, low2] {
+            tcb_sched_enqueue(tcb);
+        }
+        schedule();
+        assert_eq!(cur(), idle);
+
+        reschedule_required();
+        schedule();
+        assert_eq!(cur(), high as *mut TCB);
+        assert!(!high.state().queued());
+
+        set_thread_state(high, ThreadStateType::InActive);
+        assert_eq!(
+            unsafe { (*node_state()).scheduler_action },
+            SchedulerAction::ChooseNewThread
+        );
+        schedule();
+        assert_eq!(cur(), low2 as *mut TCB);
+        assert!(!high.state().queued());
+
+        set_thread_state(low1, ThreadStateType::BlockedOnReceive);
+        tcb_sched_dequeue(low1);
+        set_thread_state(low2, ThreadStateType::BlockedOnSend);
+        schedule();
+        assert_eq!(cur(), idle);
+        assert_eq!(
+            unsafe { (*node_state()).scheduler_action },
+            SchedulerAction::ResumeCurrentThread
+        );
+    }
+
+    #[test]
+    fn wake_up_preempts_lower_priority() {
+        setup();
+        let cur_tcb = runnable(100);
+        switch_to_thread(cur_tcb);
+
+        let high = runnable(150);
+        possible_switch_to(high);
+        assert_eq!(
+            unsafe { (*node_state()).scheduler_action },
+            SchedulerAction::SwitchToThread(high)
+        );
+        schedule();
+        assert_eq!(cur(), high as *mut TCB);
+        assert!(cur_tcb.state().queued());
+
+        // 更低优先级的线程只加入就绪队列
+        let low = runnable(50);
+        possible_switch_to(low);
+        schedule();
+        assert_eq!(cur(), high as *mut TCB);
+        assert!(low.state().queued());
+
+        // 已经需要重新调度时，被唤醒的线程也只加入就绪队列
+        let other = runnable(150);
+        reschedule_required();
+        possible_switch_to(other);
+        assert!(other.state().queued());
+        schedule();
+        assert_eq!(cur(), high as *mut TCB);
+    }
+
+    #[test]
+    fn set_priority_reschedules() {
+        setup();
+        let [a, b] = [runnable(100), runnable(80)];
+        switch_to_thread(a);
+        tcb_sched_enqueue(b);
+
+        set_priority(b, 120);
+        assert_eq!(
+            unsafe { (*node_state()).scheduler_action },
+            SchedulerAction::SwitchToThread(b)
+        );
+        schedule();
+        assert_eq!(cur(), b as *mut TCB);
+
+        set_priority(b, 10);
+        schedule();
+        assert_eq!(cur(), a as *mut TCB);
+        assert_eq!(b.priority(), 10);
+        assert!(b.state().queued());
+    }
+
+    #[test]
+    fn round_robin_on_time_slice() {
+        setup();
+        let [a, b] = [runnable(100), runnable(100)];
+        switch_to_thread(a);
+        tcb_sched_enqueue(b);
+        // 有多个 domain 时不切换 domain
+        unsafe { (*kernel_state()).domain_time = TIME_SLICE + 1 };
+
+        for _ in 1..TIME_SLICE {
+            timer_tick();
+            schedule();
+            assert_eq!(cur(), a as *mut TCB);
+        }
+        timer_tick();
+        assert_eq!(a.time_slice(), TIME_SLICE);
+        schedule();
+        assert_eq!(cur(), b as *mut TCB);
+        assert_eq!(
+            unsafe { &(*node_state()).ready_queues }.head(0, 100),
+            a as *mut TCB
+        );
+    }
+
+    #[test]
+    fn next_domain_when_time_exhausted() {
+        setup();
+        let tcb = runnable(1);
+        switch_to_thread(tcb);
+        assert_eq!(
+            unsafe { &*kernel_state() }.domain_time,
+            DOMAIN_SCHEDULE[0].length
+        );
+
+        unsafe { (*kernel_state()).domain_time = 0 };
+        reschedule_required();
+        schedule();
+        let next = DOMAIN_SCHEDULE[1 % DOMAIN_SCHEDULE.len()];
+        assert_eq!(
+            unsafe { &*kernel_state() }.dom_schedule_idx,
+            1 % DOMAIN_SCHEDULE.len()
+        );
+        assert_eq!(unsafe { &*kernel_state() }.cur_domain, next.domain);
+        assert_eq!(unsafe { &*kernel_state() }.domain_time, next.length);
+    }
+
+    #[test]
+    fn activate_restarted_thread() {
+        setup();
+        let tcb = runnable(1);
+        tcb.context().set_register(FAULT_IP, 0x1000);
+        tcb.context().set_register(NEXT_IP, 0x1004);
+        set_thread_state(tcb, ThreadStateType::Restart);
+        switch_to_thread(tcb);
+        activate_thread();
+        assert_eq!(tcb.context().get_register(NEXT_IP), 0x1000);
+        assert_eq!(tcb.state().ts_type(), ThreadStateType::Running);
+    }
+}
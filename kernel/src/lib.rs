@@ -1,16 +1,29 @@
-#![no_std]
-#![feature(generic_arg_infer)]
+#![cfg_attr(not(test), no_std)]
 
 #[macro_use]
 extern crate hal;
 
+// 单元测试运行在 host 上，只编译与硬件无关的模块
+#[cfg(not(test))]
 #[macro_use]
 pub mod console;
 #[macro_use]
 pub mod arch;
 
+pub mod api;
+#[cfg(not(test))]
+pub mod boot;
 pub mod config;
+#[cfg(not(test))]
 pub mod driver;
+pub mod kernel;
+#[cfg(not(test))]
 mod lang_items;
+pub mod model;
 pub mod object;
+#[cfg(not(test))]
 pub mod platform;
+#[cfg(test)]
+pub mod platform {
+    pub mod fdt;
+}
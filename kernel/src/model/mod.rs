@@ -0,0 +1,2 @@
+pub mod preemption;
+pub mod statedata;
@@ -0,0 +1,28 @@
+//! 长时间运行的内核操作中的抢占点，对应 seL4 `model/preemption.c`
+//!
+//! 删除和撤销等操作每完成一个工作单元调用一次 [preemption_point]，
+//! 有中断等待处理时返回 [Exception::Preempted]，线程保持 `Restart` 状态，下次调度时重新执行系统调用。
+
+use super::statedata::kernel_state;
+use crate::{
+    api::failures::Exception, arch::is_irq_pending, config::MAX_NUM_WORK_UNITS_PER_PREEMPTION,
+};
+
+/// 记录完成了一个工作单元，每 [MAX_NUM_WORK_UNITS_PER_PREEMPTION] 个工作单元检查一次中断，
+/// 对应 seL4 的 `preemptionPoint`
+pub fn preemption_point() -> Result<(), Exception> {
+    let state = unsafe { &mut *kernel_state() };
+    state.work_units_completed += 1;
+    if state.work_units_completed >= MAX_NUM_WORK_UNITS_PER_PREEMPTION {
+        state.work_units_completed = 0;
+        if is_irq_pending() {
+            return Err(Exception::Preempted);
+        }
+    }
+    Ok(())
+}
+
+/// 清空已完成的工作单元数量，切换 domain 时调用
+pub fn reset_work_units() {
+    unsafe { (*kernel_state()).work_units_completed = 0 };
+}
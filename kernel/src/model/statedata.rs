@@ -0,0 +1,139 @@
+//! 内核的全局状态，对应 seL4 `model/statedata.c`
+//!
+//! 就绪队列、当前线程和调度动作每个核各有一份，通过 [node_state] 访问当前核的状态；
+//! 当前 domain 等调度状态所有核共享，通过 [kernel_state] 访问。
+
+use core::ptr::null_mut;
+
+#[cfg(not(test))]
+use crate::config::MAX_NUM_NODES;
+use crate::{
+    arch::get_current_cpu_index,
+    config::DOMAIN_SCHEDULE,
+    object::tcb::{ReadyQueues, TCB},
+};
+
+/// 下次返回用户态之前需要进行的调度，对应 seL4 的 `ksSchedulerAction`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SchedulerAction {
+    /// 继续运行当前线程
+    ResumeCurrentThread,
+    /// 从就绪队列中选择优先级最高的线程
+    ChooseNewThread,
+    /// 切换到指定的线程，该线程还没有加入就绪队列
+    SwitchToThread(*mut TCB),
+}
+
+/// 每个核各自的调度状态
+pub struct NodeState {
+    /// 对应 seL4 的 `ksReadyQueues` 及其位图
+    pub ready_queues: ReadyQueues,
+    /// 对应 seL4 的 `ksCurThread`
+    pub cur_thread: *mut TCB,
+    /// 对应 seL4 的 `ksIdleThread`
+    pub idle_thread: *mut TCB,
+    /// 对应 seL4 的 `ksSchedulerAction`
+    pub scheduler_action: SchedulerAction,
+}
+
+#[allow(clippy::new_without_default)]
+impl NodeState {
+    pub const fn new() -> Self {
+        Self {
+            ready_queues: ReadyQueues::new(),
+            cur_thread: null_mut(),
+            idle_thread: null_mut(),
+            scheduler_action: SchedulerAction::ResumeCurrentThread,
+        }
+    }
+}
+
+/// 所有核共享的调度状态
+pub struct KernelState {
+    /// 当前运行的 domain，对应 seL4 的 `ksCurDomain`
+    pub cur_domain: usize,
+    /// 当前 domain 剩余的时钟中断次数，对应 seL4 的 `ksDomainTime`
+    pub domain_time: usize,
+    /// 当前 domain 在 [DOMAIN_SCHEDULE] 中的位置，对应 seL4 的 `ksDomScheduleIdx`
+    pub dom_schedule_idx: usize,
+    /// 上次检查中断之后完成的工作单元数量，对应 seL4 的 `ksWorkUnitsCompleted`
+    pub work_units_completed: usize,
+}
+
+#[allow(clippy::new_without_default)]
+impl KernelState {
+    /// 从 [DOMAIN_SCHEDULE] 的第一项开始
+    pub const fn new() -> Self {
+        Self {
+            cur_domain: DOMAIN_SCHEDULE[0].domain,
+            domain_time: DOMAIN_SCHEDULE[0].length,
+            dom_schedule_idx: 0,
+            work_units_completed: 0,
+        }
+    }
+}
+
+#[cfg(not(test))]
+static mut NODE_STATE: [NodeState; MAX_NUM_NODES] = [const { NodeState::new() }; _];
+
+#[cfg(not(test))]
+static mut KERNEL_STATE: KernelState = KernelState::new();
+
+/// 第 `cpu` 个核的调度状态，对应 seL4 的 `NODE_STATE_ON_CORE`
+///
+/// # Safety
+///
+/// 返回的指针指向全局状态，解引用时创建的引用只能在不调用其他访问内核状态的函数的范围内使用，
+/// 不能与其他引用重叠
+#[cfg(not(test))]
+pub unsafe fn node_state_on_core(cpu: usize) -> *mut NodeState {
+    unsafe { &raw mut NODE_STATE[cpu] }
+}
+
+/// 所有核共享的调度状态
+///
+/// # Safety
+///
+/// 同 [node_state_on_core]
+#[cfg(not(test))]
+pub unsafe fn kernel_state() -> *mut KernelState {
+    &raw mut KERNEL_STATE
+}
+
+/// 单元测试并行运行，每个测试线程使用各自的内核状态。
+/// 调用内核对象时会修改当前线程的状态，当前线程默认为一个新创建的线程
+///
+/// # Safety
+///
+/// 同非测试版本
+#[cfg(test)]
+pub unsafe fn node_state_on_core(_cpu: usize) -> *mut NodeState {
+    std::thread_local! {
+        static NODE_STATE: *mut NodeState = {
+            let mut state = NodeState::new();
+            state.cur_thread = crate::object::test_utils::alloc_tcb();
+            Box::into_raw(Box::new(state))
+        };
+    }
+    NODE_STATE.with(|&state| state)
+}
+
+/// # Safety
+///
+/// 同非测试版本
+#[cfg(test)]
+pub unsafe fn kernel_state() -> *mut KernelState {
+    std::thread_local! {
+        static KERNEL_STATE: *mut KernelState = Box::into_raw(Box::new(KernelState::new()));
+    }
+    KERNEL_STATE.with(|&state| state)
+}
+
+/// 当前核的调度状态，对应 seL4 的 `NODE_STATE`
+///
+/// # Safety
+///
+/// 同 [node_state_on_core]
+pub unsafe fn node_state() -> *mut NodeState {
+    unsafe { node_state_on_core(get_current_cpu_index()) }
+}
@@ -0,0 +1,398 @@
+//! Capability
+//!
+//! [Cap] 与 seL4 中的 `cap_t` 保持一致，为 128 位，由 `structures.bf` 中的 `tagged_union cap`
+//! 生成在 [super::structures] 中，具体类型通过 [From] 转换为 [Cap]，通过 [Cap::view] 转换回具体类型。
+
+use sel4_types::rights::CapRights;
+
+pub use super::structures::{Cap, CapView};
+use super::{
+    ipc::{ENDPOINT_BITS, NOTIFICATION_BITS},
+    structures::{
+        AsidControlCap, AsidPoolCap, CapType, CnodeCap, DomainCap, EndpointCap, FrameCap,
+        IrqControlCap, IrqHandlerCap, NotificationCap, NullCap, PageTableCap, ReplyCap, ThreadCap,
+        UntypedCap, VspaceCap, ZombieCap,
+    },
+    MDBNode,
+};
+use crate::arch::{arch_cap_is_physical, arch_cap_ptr, arch_cap_size_bits, TCB_BITS};
+
+/// CTE 大小
+pub const SLOT_BITS: usize = 5;
+/// TCB 中 CTE 数量的位数，对应 seL4 的 `TCB_CNODE_RADIX`
+pub const TCB_CNODE_RADIX: usize = 4;
+
+const WORD_RADIX: usize = 6;
+/// TCB 对应的 zombie 类型，对应 seL4 的 `ZombieType_ZombieTCB`
+pub const ZOMBIE_TYPE_TCB: usize = bit!(WORD_RADIX);
+
+impl Cap {
+    pub const fn null() -> Self {
+        Self::from_raw(NullCap::empty().raw())
+    }
+
+    /// capability 的类型，所有类型的 `capType` 都位于相同的位置
+    pub const fn cap_type(&self) -> usize {
+        self.get_cap_type()
+    }
+
+    pub const fn is_null(&self) -> bool {
+        self.cap_type() == CapType::NullCap as usize
+    }
+
+    /// capability 是否指向物理内存中的对象，对应 seL4 的 `cap_get_capIsPhysical`
+    pub const fn is_physical(&self) -> bool {
+        match self.view() {
+            CapView::UntypedCap(_)
+            | CapView::EndpointCap(_)
+            | CapView::NotificationCap(_)
+            | CapView::CnodeCap(_)
+            | CapView::ThreadCap(_)
+            | CapView::ZombieCap(_) => true,
+            CapView::NullCap(_)
+            | CapView::ReplyCap(_)
+            | CapView::IrqControlCap(_)
+            | CapView::IrqHandlerCap(_)
+            | CapView::DomainCap(_) => false,
+            _ => arch_cap_is_physical(self),
+        }
+    }
+
+    /// capability 指向的对象的地址，对应 seL4 的 `cap_get_capPtr`
+    pub const fn ptr(&self) -> usize {
+        match self.view() {
+            CapView::UntypedCap(cap) => cap.get_cap_ptr(),
+            CapView::EndpointCap(cap) => cap.get_cap_ep_ptr(),
+            CapView::NotificationCap(cap) => cap.get_cap_ntfn_ptr(),
+            CapView::CnodeCap(cap) => cap.get_cap_c_node_ptr(),
+            // TCB 对象从 CTE 开始，对应 seL4 的 `TCB_PTR_CTE_PTR(capTCBPtr, 0)`
+            CapView::ThreadCap(cap) => cap.get_cap_tcb_ptr() & !(bit!(TCB_BITS) - 1),
+            CapView::ZombieCap(cap) => cap.zombie_ptr(),
+            CapView::NullCap(_)
+            | CapView::ReplyCap(_)
+            | CapView::IrqControlCap(_)
+            | CapView::IrqHandlerCap(_)
+            | CapView::DomainCap(_) => 0,
+            _ => arch_cap_ptr(self),
+        }
+    }
+
+    /// capability 指向的对象的大小，对应 seL4 的 `cap_get_capSizeBits`
+    pub const fn size_bits(&self) -> usize {
+        match self.view() {
+            CapView::UntypedCap(cap) => cap.get_cap_block_size(),
+            CapView::EndpointCap(_) => ENDPOINT_BITS,
+            CapView::NotificationCap(_) => NOTIFICATION_BITS,
+            CapView::CnodeCap(cap) => cap.get_cap_c_node_radix() + SLOT_BITS,
+            CapView::ThreadCap(_) => TCB_BITS,
+            CapView::ZombieCap(cap) => {
+                if cap.get_cap_zombie_type() == ZOMBIE_TYPE_TCB {
+                    TCB_BITS
+                } else {
+                    cap.zombie_bits() + SLOT_BITS
+                }
+            }
+            CapView::NullCap(_)
+            | CapView::ReplyCap(_)
+            | CapView::IrqControlCap(_)
+            | CapView::IrqHandlerCap(_)
+            | CapView::DomainCap(_) => 0,
+            _ => arch_cap_size_bits(self),
+        }
+    }
+}
+
+impl UntypedCap {
+    pub const fn new(free_index: usize, is_device: bool, block_size: usize, ptr: usize) -> Self {
+        let mut cap = Self::empty();
+        cap.set_cap_type(CapType::UntypedCap as usize);
+        cap.set_cap_free_index(free_index);
+        cap.set_cap_is_device(is_device as usize);
+        cap.set_cap_block_size(block_size);
+        cap.set_cap_ptr(ptr);
+        cap
+    }
+}
+
+impl EndpointCap {
+    pub const fn new(
+        badge: usize,
+        can_grant_reply: bool,
+        can_grant: bool,
+        can_send: bool,
+        can_receive: bool,
+        ep_ptr: usize,
+    ) -> Self {
+        let mut cap = Self::empty();
+        cap.set_cap_type(CapType::EndpointCap as usize);
+        cap.set_cap_ep_badge(badge);
+        cap.set_cap_can_grant_reply(can_grant_reply as usize);
+        cap.set_cap_can_grant(can_grant as usize);
+        cap.set_cap_can_send(can_send as usize);
+        cap.set_cap_can_receive(can_receive as usize);
+        cap.set_cap_ep_ptr(ep_ptr);
+        cap
+    }
+
+    /// send、receive、grant 和 grant-reply 对应的权限
+    pub const fn rights(&self) -> CapRights {
+        let mut rights = CapRights::empty();
+        if self.get_cap_can_send() != 0 {
+            rights = rights.union(CapRights::WRITE);
+        }
+        if self.get_cap_can_receive() != 0 {
+            rights = rights.union(CapRights::READ);
+        }
+        if self.get_cap_can_grant() != 0 {
+            rights = rights.union(CapRights::GRANT);
+        }
+        if self.get_cap_can_grant_reply() != 0 {
+            rights = rights.union(CapRights::GRANT_REPLY);
+        }
+        rights
+    }
+
+    pub const fn set_rights(&mut self, rights: CapRights) {
+        self.set_cap_can_send(rights.contains(CapRights::WRITE) as usize);
+        self.set_cap_can_receive(rights.contains(CapRights::READ) as usize);
+        self.set_cap_can_grant(rights.contains(CapRights::GRANT) as usize);
+        self.set_cap_can_grant_reply(rights.contains(CapRights::GRANT_REPLY) as usize);
+    }
+}
+
+impl NotificationCap {
+    pub const fn new(badge: usize, can_receive: bool, can_send: bool, ntfn_ptr: usize) -> Self {
+        let mut cap = Self::empty();
+        cap.set_cap_type(CapType::NotificationCap as usize);
+        cap.set_cap_ntfn_badge(badge);
+        cap.set_cap_ntfn_can_receive(can_receive as usize);
+        cap.set_cap_ntfn_can_send(can_send as usize);
+        cap.set_cap_ntfn_ptr(ntfn_ptr);
+        cap
+    }
+
+    /// send 和 receive 对应的权限，notification 没有 grant 权限
+    pub const fn rights(&self) -> CapRights {
+        let mut rights = CapRights::empty();
+        if self.get_cap_ntfn_can_send() != 0 {
+            rights = rights.union(CapRights::WRITE);
+        }
+        if self.get_cap_ntfn_can_receive() != 0 {
+            rights = rights.union(CapRights::READ);
+        }
+        rights
+    }
+
+    pub const fn set_rights(&mut self, rights: CapRights) {
+        self.set_cap_ntfn_can_send(rights.contains(CapRights::WRITE) as usize);
+        self.set_cap_ntfn_can_receive(rights.contains(CapRights::READ) as usize);
+    }
+}
+
+impl ReplyCap {
+    pub const fn new(can_grant: bool, master: bool, tcb_ptr: usize) -> Self {
+        let mut cap = Self::empty();
+        cap.set_cap_type(CapType::ReplyCap as usize);
+        cap.set_cap_reply_can_grant(can_grant as usize);
+        cap.set_cap_reply_master(master as usize);
+        cap.set_cap_tcb_ptr(tcb_ptr);
+        cap
+    }
+}
+
+impl CnodeCap {
+    pub const fn new(radix: usize, guard_size: usize, guard: usize, ptr: usize) -> Self {
+        let mut cap = Self::empty();
+        cap.set_cap_type(CapType::CnodeCap as usize);
+        cap.set_cap_c_node_radix(radix);
+        cap.set_cap_c_node_guard_size(guard_size);
+        cap.set_cap_c_node_guard(guard);
+        cap.set_cap_c_node_ptr(ptr);
+        cap
+    }
+}
+
+impl ThreadCap {
+    pub const fn new(tcb_ptr: usize) -> Self {
+        let mut cap = Self::empty();
+        cap.set_cap_type(CapType::ThreadCap as usize);
+        cap.set_cap_tcb_ptr(tcb_ptr);
+        cap
+    }
+}
+
+#[allow(clippy::new_without_default)]
+impl IrqControlCap {
+    pub const fn new() -> Self {
+        let mut cap = Self::empty();
+        cap.set_cap_type(CapType::IrqControlCap as usize);
+        cap
+    }
+}
+
+impl IrqHandlerCap {
+    pub const fn new(irq: usize) -> Self {
+        let mut cap = Self::empty();
+        cap.set_cap_type(CapType::IrqHandlerCap as usize);
+        cap.set_cap_irq(irq);
+        cap
+    }
+}
+
+impl ZombieCap {
+    pub const fn new(id: usize, zombie_type: usize) -> Self {
+        let mut cap = Self::empty();
+        cap.set_cap_type(CapType::ZombieCap as usize);
+        cap.set_cap_zombie_id(id);
+        cap.set_cap_zombie_type(zombie_type);
+        cap
+    }
+
+    /// 保存 `ptr` 开始的 `number` 个待清理的 slot，对应 seL4 的 `Zombie_new`
+    ///
+    /// `ptr` 按照 slot 数量对齐，低位用于保存 `number`
+    pub const fn zombie_new(number: usize, zombie_type: usize, ptr: usize) -> Self {
+        let mask = if zombie_type == ZOMBIE_TYPE_TCB {
+            bit!(TCB_CNODE_RADIX + 1) - 1
+        } else {
+            bit!(zombie_type + 1) - 1
+        };
+        Self::new((ptr & !mask) | (number & mask), zombie_type)
+    }
+
+    /// 对应 seL4 的 `cap_zombie_cap_set_capZombieNumber`
+    pub const fn set_zombie_number(&mut self, number: usize) {
+        let mask = bit!(self.zombie_bits() + 1) - 1;
+        self.set_cap_zombie_id((self.get_cap_zombie_id() & !mask) | (number & mask));
+    }
+
+    /// zombie 中 slot 数量的位数，对应 seL4 的 `cap_zombie_cap_get_capZombieBits`
+    pub const fn zombie_bits(&self) -> usize {
+        let zombie_type = self.get_cap_zombie_type();
+        if zombie_type == ZOMBIE_TYPE_TCB {
+            TCB_CNODE_RADIX
+        } else {
+            zombie_type & (bit!(WORD_RADIX) - 1)
+        }
+    }
+
+    /// zombie 指向的 CNode 或 TCB 中第一个 slot 的地址，保存在 `capZombieID` 的高位
+    pub const fn zombie_ptr(&self) -> usize {
+        self.get_cap_zombie_id() & !(bit!(self.zombie_bits() + 1) - 1)
+    }
+
+    /// zombie 中还未清理的 slot 数量，保存在 `capZombieID` 的低位
+    pub const fn zombie_number(&self) -> usize {
+        self.get_cap_zombie_id() & (bit!(self.zombie_bits() + 1) - 1)
+    }
+}
+
+#[allow(clippy::new_without_default)]
+impl DomainCap {
+    pub const fn new() -> Self {
+        let mut cap = Self::empty();
+        cap.set_cap_type(CapType::DomainCap as usize);
+        cap
+    }
+}
+
+impl FrameCap {
+    pub const fn new(
+        mapped_asid: usize,
+        base_ptr: usize,
+        size: usize,
+        vm_rights: usize,
+        is_device: bool,
+        mapped_address: usize,
+    ) -> Self {
+        let mut cap = Self::empty();
+        cap.set_cap_type(CapType::FrameCap as usize);
+        cap.set_cap_f_mapped_asid(mapped_asid);
+        cap.set_cap_f_base_ptr(base_ptr);
+        cap.set_cap_f_size(size);
+        cap.set_cap_fvm_rights(vm_rights);
+        cap.set_cap_f_is_device(is_device as usize);
+        cap.set_cap_f_mapped_address(mapped_address);
+        cap
+    }
+}
+
+impl PageTableCap {
+    pub const fn new(
+        mapped_asid: usize,
+        base_ptr: usize,
+        is_mapped: bool,
+        mapped_address: usize,
+    ) -> Self {
+        let mut cap = Self::empty();
+        cap.set_cap_type(CapType::PageTableCap as usize);
+        cap.set_cap_pt_mapped_asid(mapped_asid);
+        cap.set_cap_pt_base_ptr(base_ptr);
+        cap.set_cap_pt_is_mapped(is_mapped as usize);
+        cap.set_cap_pt_mapped_address(mapped_address);
+        cap
+    }
+}
+
+impl VspaceCap {
+    pub const fn new(mapped_asid: usize, base_ptr: usize, is_mapped: bool) -> Self {
+        let mut cap = Self::empty();
+        cap.set_cap_type(CapType::VspaceCap as usize);
+        cap.set_cap_vs_mapped_asid(mapped_asid);
+        cap.set_cap_vs_base_ptr(base_ptr);
+        cap.set_cap_vs_is_mapped(is_mapped as usize);
+        cap
+    }
+}
+
+#[allow(clippy::new_without_default)]
+impl AsidControlCap {
+    pub const fn new() -> Self {
+        let mut cap = Self::empty();
+        cap.set_cap_type(CapType::AsidControlCap as usize);
+        cap
+    }
+}
+
+impl AsidPoolCap {
+    pub const fn new(asid_base: usize, asid_pool: usize) -> Self {
+        let mut cap = Self::empty();
+        cap.set_cap_type(CapType::AsidPoolCap as usize);
+        cap.set_cap_asid_base(asid_base);
+        cap.set_cap_asid_pool(asid_pool);
+        cap
+    }
+}
+
+/// Capability Table Entry
+///
+/// ```c
+/// struct cte {
+///     cap_t cap;
+///     mdb_node_t cteMDBNode;
+/// };
+/// ```
+pub type Cte = MDBNode<Cap>;
+
+/// 对应 seL4 的 `SLOT_PTR`，获取 CNode 中第 `pos` 个 slot
+pub const fn slot_ptr(cnode: usize, pos: usize) -> *mut Cte {
+    (cnode as *mut Cte).wrapping_add(pos)
+}
+
+/// 启动阶段向空 slot 中写入 capability，该 capability 作为 MDB 树的根节点
+///
+/// # Safety
+///
+/// `slot` 需要指向一个有效的 [Cte]
+pub unsafe fn write_slot(slot: *mut Cte, cap: Cap) {
+    let mut cte = Cte::new(cap);
+    cte.set_revocable(true);
+    cte.set_first_badged(true);
+    unsafe { slot.write(cte) };
+}
+
+const fn _check_type_width() {
+    assert!(size_of::<Cap>() == 16);
+    assert!(size_of::<Cte>() == bit!(SLOT_BITS));
+}
+const _: () = _check_type_width();
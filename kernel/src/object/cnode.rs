@@ -0,0 +1,1111 @@
+//! CNode
+//!
+//! CNode 对象为 2^radix 个连续的 [Cte]，对象大小为 `radix + SLOT_BITS`。
+//! CNode 的调用和 MDB 的维护对应 seL4 `object/cnode.c`。
+
+use sel4_types::{invocation::InvocationLabel, rights::CapRights};
+
+use super::{
+    cap::{slot_ptr, Cap, CapView, Cte, SLOT_BITS},
+    cspace::{lookup_pivot_slot, lookup_source_slot, lookup_target_slot},
+    fault::{LookupFault, ThreadStateType},
+    ipc::cancel_badged_sends,
+    objecttype::{
+        derive_cap, finalise_cap, has_cancel_send_rights, is_cap_revocable, mask_cap_rights,
+        post_cap_deletion, same_object_as, same_region_as, update_cap_data,
+    },
+    structures::{CnodeCap, Endpoint, EndpointCap},
+    tcb::TcbCnodeIndex,
+    untyped::max_free_index,
+};
+use crate::{
+    api::failures::{Exception, SyscallError},
+    kernel::thread::{cur_thread, set_thread_state},
+    model::preemption::preemption_point,
+};
+
+/// CNode 对象，记录起始地址和 radix
+#[derive(Debug, Clone, Copy)]
+pub struct CNode {
+    ptr: usize,
+    radix: usize,
+}
+
+impl CNode {
+    pub const fn new(ptr: usize, radix: usize) -> Self {
+        Self { ptr, radix }
+    }
+
+    /// `cap` 指向的 CNode
+    pub const fn from_cap(cap: &CnodeCap) -> Self {
+        Self::new(cap.get_cap_c_node_ptr(), cap.get_cap_c_node_radix())
+    }
+
+    pub const fn ptr(&self) -> usize {
+        self.ptr
+    }
+
+    pub const fn radix(&self) -> usize {
+        self.radix
+    }
+
+    /// slot 数量
+    pub const fn len(&self) -> usize {
+        bit!(self.radix)
+    }
+
+    /// CNode 至少包含一个 slot
+    pub const fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// CNode 对象的大小
+    pub const fn size_bits(&self) -> usize {
+        self.radix + SLOT_BITS
+    }
+
+    /// 带边界检查的 `SLOT_PTR`，`pos` 超出 CNode 时返回 `None`
+    pub const fn slot_ptr(&self, pos: usize) -> Option<*mut Cte> {
+        if pos < self.len() {
+            Some(slot_ptr(self.ptr, pos))
+        } else {
+            None
+        }
+    }
+
+    /// CNode 中的第 `pos` 个 slot，`pos` 超出 CNode 时返回 `None`
+    ///
+    /// # Safety
+    ///
+    /// CNode 需要指向有效的内核对象，且调用者需要保证同一时刻只有一个可变引用
+    pub unsafe fn slot(&self, pos: usize) -> Option<&'static mut Cte> {
+        self.slot_ptr(pos).map(|slot| unsafe { &mut *slot })
+    }
+
+    /// CNode 中的所有 slot，通过切片索引进行边界检查
+    ///
+    /// # Safety
+    ///
+    /// 与 [CNode::slot] 相同
+    pub unsafe fn slots(&self) -> &'static mut [Cte] {
+        unsafe { core::slice::from_raw_parts_mut(self.ptr as *mut Cte, self.len()) }
+    }
+}
+
+/// 获取第 `index` 个消息参数，参数不足时返回 [SyscallError::TruncatedMessage]
+fn get_arg(args: &[usize], index: usize) -> Result<usize, SyscallError> {
+    args.get(index)
+        .copied()
+        .ok_or(SyscallError::TruncatedMessage)
+}
+
+/// 源 slot 或 pivot slot 为空时的错误
+const fn missing_capability(was_source: bool, depth: usize) -> SyscallError {
+    SyscallError::FailedLookup {
+        was_source,
+        fault: LookupFault::MissingCapability {
+            bits_left: depth as u8,
+        },
+    }
+}
+
+/// 解析 CNode 的调用，对应 seL4 的 `decodeCNodeInvocation`
+///
+/// `args` 为消息寄存器和 IPC buffer 中的参数，`extra_caps` 为消息中附带的 capability
+pub fn decode_cnode_invocation(
+    label: InvocationLabel,
+    cap: CnodeCap,
+    args: &[usize],
+    extra_caps: &[Cap],
+) -> Result<(), Exception> {
+    use InvocationLabel::*;
+
+    if !(CNodeRevoke as usize..=CNodeSaveCaller as usize).contains(&(label as usize)) {
+        return Err(SyscallError::IllegalOperation.into());
+    }
+    if args.len() < 2 {
+        return Err(SyscallError::TruncatedMessage.into());
+    }
+    let dest_slot = lookup_target_slot(cap.into(), args[0], args[1])?;
+
+    match label {
+        CNodeCopy | CNodeMint | CNodeMove | CNodeMutate => {
+            if args.len() < 4 || extra_caps.is_empty() {
+                return Err(SyscallError::TruncatedMessage.into());
+            }
+            let (src_index, src_depth) = (args[2], args[3]);
+            let src_root = extra_caps[0];
+
+            ensure_empty_slot(dest_slot)?;
+            let src_slot = lookup_source_slot(src_root, src_index, src_depth)?;
+            let src_cap = unsafe { **src_slot };
+            if src_cap.is_null() {
+                return Err(missing_capability(true, src_depth).into());
+            }
+
+            let (new_cap, is_move) = match label {
+                CNodeCopy => {
+                    let rights = CapRights::from_word(get_arg(args, 4)?);
+                    let cap = mask_cap_rights(rights, src_cap);
+                    (derive_cap(unsafe { &*src_slot }, cap)?, false)
+                }
+                CNodeMint => {
+                    let rights = CapRights::from_word(get_arg(args, 4)?);
+                    let cap_data = get_arg(args, 5)?;
+                    let cap = mask_cap_rights(rights, src_cap);
+                    let cap = update_cap_data(false, cap_data, cap);
+                    (derive_cap(unsafe { &*src_slot }, cap)?, false)
+                }
+                CNodeMove => (src_cap, true),
+                _ => {
+                    let cap_data = get_arg(args, 4)?;
+                    (update_cap_data(true, cap_data, src_cap), true)
+                }
+            };
+            if new_cap.is_null() {
+                return Err(SyscallError::IllegalOperation.into());
+            }
+
+            set_thread_state(unsafe { &mut *cur_thread() }, ThreadStateType::Restart);
+            if is_move {
+                invoke_cnode_move(new_cap, src_slot, dest_slot)
+            } else {
+                invoke_cnode_insert(new_cap, src_slot, dest_slot)
+            }
+        }
+        // 被抢占时线程处于 Restart 状态，重新执行时从剩余的部分继续
+        CNodeRevoke => {
+            set_thread_state(unsafe { &mut *cur_thread() }, ThreadStateType::Restart);
+            invoke_cnode_revoke(dest_slot)
+        }
+        CNodeDelete => {
+            set_thread_state(unsafe { &mut *cur_thread() }, ThreadStateType::Restart);
+            invoke_cnode_delete(dest_slot)
+        }
+        CNodeSaveCaller => {
+            ensure_empty_slot(dest_slot)?;
+            set_thread_state(unsafe { &mut *cur_thread() }, ThreadStateType::Restart);
+            invoke_cnode_save_caller(dest_slot)
+        }
+        CNodeCancelBadgedSends => {
+            let dest_cap = unsafe { **dest_slot };
+            let CapView::EndpointCap(ep_cap) = dest_cap.view() else {
+                return Err(SyscallError::IllegalOperation.into());
+            };
+            if !has_cancel_send_rights(dest_cap) {
+                return Err(SyscallError::IllegalOperation.into());
+            }
+            set_thread_state(unsafe { &mut *cur_thread() }, ThreadStateType::Restart);
+            invoke_cnode_cancel_badged_sends(ep_cap)
+        }
+        CNodeRotate => {
+            if args.len() < 8 || extra_caps.len() < 2 {
+                return Err(SyscallError::TruncatedMessage.into());
+            }
+            let (pivot_new_data, pivot_index, pivot_depth) = (args[2], args[3], args[4]);
+            let (src_new_data, src_index, src_depth) = (args[5], args[6], args[7]);
+            let (pivot_root, src_root) = (extra_caps[0], extra_caps[1]);
+
+            let src_slot = lookup_source_slot(src_root, src_index, src_depth)?;
+            let pivot_slot = lookup_pivot_slot(pivot_root, pivot_index, pivot_depth)?;
+            if pivot_slot == src_slot || pivot_slot == dest_slot {
+                return Err(SyscallError::IllegalOperation.into());
+            }
+            if src_slot != dest_slot {
+                ensure_empty_slot(dest_slot)?;
+            }
+
+            let (src_cap, pivot_cap) = unsafe { (**src_slot, **pivot_slot) };
+            if src_cap.is_null() {
+                return Err(missing_capability(true, src_depth).into());
+            }
+            if pivot_cap.is_null() {
+                return Err(missing_capability(false, pivot_depth).into());
+            }
+
+            let new_src_cap = update_cap_data(true, src_new_data, src_cap);
+            let new_pivot_cap = update_cap_data(true, pivot_new_data, pivot_cap);
+            if new_src_cap.is_null() || new_pivot_cap.is_null() {
+                return Err(SyscallError::IllegalOperation.into());
+            }
+
+            set_thread_state(unsafe { &mut *cur_thread() }, ThreadStateType::Restart);
+            invoke_cnode_rotate(new_src_cap, new_pivot_cap, src_slot, pivot_slot, dest_slot)
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// 对应 seL4 的 `invokeCNodeRevoke`
+fn invoke_cnode_revoke(dest_slot: *mut Cte) -> Result<(), Exception> {
+    unsafe { cte_revoke(dest_slot) }
+}
+
+/// 对应 seL4 的 `invokeCNodeDelete`
+fn invoke_cnode_delete(dest_slot: *mut Cte) -> Result<(), Exception> {
+    unsafe { cte_delete(dest_slot, true) }
+}
+
+/// 对应 seL4 的 `invokeCNodeCancelBadgedSends`
+///
+/// badge 为 0 的 capability 不做处理
+fn invoke_cnode_cancel_badged_sends(cap: EndpointCap) -> Result<(), Exception> {
+    let badge = cap.get_cap_ep_badge();
+    if badge != 0 {
+        let ep = unsafe { &mut *(cap.get_cap_ep_ptr() as *mut Endpoint) };
+        cancel_badged_sends(ep, badge);
+    }
+    Ok(())
+}
+
+/// 对应 seL4 的 `invokeCNodeInsert`
+fn invoke_cnode_insert(cap: Cap, src_slot: *mut Cte, dest_slot: *mut Cte) -> Result<(), Exception> {
+    unsafe { cte_insert(cap, src_slot, dest_slot) };
+    Ok(())
+}
+
+/// 对应 seL4 的 `invokeCNodeMove`
+fn invoke_cnode_move(cap: Cap, src_slot: *mut Cte, dest_slot: *mut Cte) -> Result<(), Exception> {
+    unsafe { cte_move(cap, src_slot, dest_slot) };
+    Ok(())
+}
+
+/// 将 `slot1` 移动到 `slot2`，`slot2` 移动到 `slot3`，对应 seL4 的 `invokeCNodeRotate`
+///
+/// `slot1` 与 `slot3` 相同时交换两个 slot
+fn invoke_cnode_rotate(
+    cap1: Cap,
+    cap2: Cap,
+    slot1: *mut Cte,
+    slot2: *mut Cte,
+    slot3: *mut Cte,
+) -> Result<(), Exception> {
+    unsafe {
+        if slot1 == slot3 {
+            cte_swap(cap1, slot1, cap2, slot2);
+        } else {
+            cte_move(cap2, slot2, slot3);
+            cte_move(cap1, slot1, slot2);
+        }
+    }
+    Ok(())
+}
+
+/// 将当前线程的 caller capability 移动到 `dest_slot`，对应 seL4 的 `invokeCNodeSaveCaller`
+///
+/// 没有 caller capability 时不做处理
+fn invoke_cnode_save_caller(dest_slot: *mut Cte) -> Result<(), Exception> {
+    let src_slot = unsafe { (*cur_thread()).cte_ptr(TcbCnodeIndex::Caller) };
+    let cap = unsafe { **src_slot };
+    match cap.view() {
+        CapView::NullCap(_) => {}
+        CapView::ReplyCap(_) => unsafe { cte_move(cap, src_slot, dest_slot) },
+        _ => panic!("caller capability must be null or reply"),
+    }
+    Ok(())
+}
+
+/// `slot` 在 MDB 中有子节点时返回 [SyscallError::RevokeFirst]，对应 seL4 的 `ensureNoChildren`
+pub fn ensure_no_children(slot: &Cte) -> Result<(), SyscallError> {
+    match slot.next_node() {
+        Some(next) if is_mdb_parent_of(slot, unsafe { &*next }) => Err(SyscallError::RevokeFirst),
+        _ => Ok(()),
+    }
+}
+
+/// `cte_b` 是否为 `cte_a` 在 MDB 中的子节点，对应 seL4 的 `isMDBParentOf`
+///
+/// 带 badge 的 endpoint 和 notification 只是同一 badge 中第一个节点的父节点，
+/// 未设置 badge 的 capability 是所有派生节点的父节点
+pub fn is_mdb_parent_of(cte_a: &Cte, cte_b: &Cte) -> bool {
+    if !cte_a.revocable() || !same_region_as(cte_a, cte_b) {
+        return false;
+    }
+
+    match (cte_a.view(), cte_b.view()) {
+        (CapView::EndpointCap(a), CapView::EndpointCap(b)) => {
+            let badge = a.get_cap_ep_badge();
+            badge == 0 || (badge == b.get_cap_ep_badge() && !cte_b.first_badge())
+        }
+        (CapView::NotificationCap(a), CapView::NotificationCap(b)) => {
+            let badge = a.get_cap_ntfn_badge();
+            badge == 0 || (badge == b.get_cap_ntfn_badge() && !cte_b.first_badge())
+        }
+        _ => true,
+    }
+}
+
+/// `slot` 不为空时返回 [SyscallError::DeleteFirst]，对应 seL4 的 `ensureEmptySlot`
+fn ensure_empty_slot(slot: *mut Cte) -> Result<(), SyscallError> {
+    if unsafe { (**slot).is_null() } {
+        Ok(())
+    } else {
+        Err(SyscallError::DeleteFirst)
+    }
+}
+
+/// 将由 `src_slot` 派生出的 `new_cap` 写入空的 `dest_slot`，并插入到 MDB 中 `src_slot` 之后，
+/// 对应 seL4 的 `cteInsert`
+///
+/// # Safety
+///
+/// slot 需要指向有效的 [Cte]
+pub unsafe fn cte_insert(new_cap: Cap, src_slot: *mut Cte, dest_slot: *mut Cte) {
+    let (src, dest) = unsafe { (&mut *src_slot, &mut *dest_slot) };
+    assert!(dest.is_null(), "cteInsert to non-empty destination");
+    assert!(dest.next() == 0 && dest.prev() == 0);
+
+    // 新的 badge 作为 MDB 中同一 badge 的第一个节点
+    let revocable = is_cap_revocable(&new_cap, src);
+    set_untyped_cap_as_full(src, &new_cap);
+    let mut new_node = Cte::new(new_cap);
+    new_node.set_prev(src_slot as usize);
+    new_node.set_next(src.next());
+    new_node.set_revocable(revocable);
+    new_node.set_first_badged(revocable);
+    *dest = new_node;
+
+    src.set_next(dest_slot as usize);
+    if let Some(next) = dest.next_node() {
+        unsafe { (*next).set_prev(dest_slot as usize) };
+    }
+}
+
+/// 将新创建的对象的 capability 写入空的 `slot`，并作为 `parent` 的子节点插入到 MDB 中，
+/// 对应 seL4 的 `insertNewCap`
+///
+/// 新对象的 capability 总是可撤销的，并作为同一 badge 的第一个节点
+///
+/// # Safety
+///
+/// slot 需要指向有效的 [Cte]
+pub unsafe fn insert_new_cap(parent: *mut Cte, slot: *mut Cte, cap: Cap) {
+    let (parent_cte, cte) = unsafe { (&mut *parent, &mut *slot) };
+    let next = parent_cte.next();
+
+    let mut new_node = Cte::new(cap);
+    new_node.set_prev(parent as usize);
+    new_node.set_next(next);
+    new_node.set_revocable(true);
+    new_node.set_first_badged(true);
+    *cte = new_node;
+
+    if let Some(next) = cte.next_node() {
+        unsafe { (*next).set_prev(slot as usize) };
+    }
+    parent_cte.set_next(slot as usize);
+}
+
+/// 从 untyped 复制出覆盖整个区域的 untyped 时，将原来的 untyped 标记为已用完，
+/// 避免在两个 untyped 中重复创建对象，对应 seL4 的 `setUntypedCapAsFull`
+fn set_untyped_cap_as_full(src: &mut Cte, new_cap: &Cap) {
+    if let (CapView::UntypedCap(mut src_cap), CapView::UntypedCap(new_cap)) =
+        (src.view(), new_cap.view())
+    {
+        let size_bits = src_cap.get_cap_block_size();
+        if src_cap.get_cap_ptr() == new_cap.get_cap_ptr()
+            && size_bits == new_cap.get_cap_block_size()
+        {
+            src_cap.set_cap_free_index(max_free_index(size_bits));
+            **src = src_cap.into();
+        }
+    }
+}
+
+/// 将 `src_slot` 中的 capability 替换为 `new_cap` 并移动到空的 `dest_slot`，
+/// MDB 节点保持在原来的位置，对应 seL4 的 `cteMove`
+///
+/// # Safety
+///
+/// slot 需要指向有效的 [Cte]
+pub unsafe fn cte_move(new_cap: Cap, src_slot: *mut Cte, dest_slot: *mut Cte) {
+    let (src, dest) = unsafe { (&mut *src_slot, &mut *dest_slot) };
+    assert!(dest.is_null(), "cteMove to non-empty destination");
+    assert!(dest.next() == 0 && dest.prev() == 0);
+
+    let mdb = src.mdb();
+    **dest = new_cap;
+    **src = Cap::null();
+    dest.set_mdb(mdb);
+    src.set_mdb([0; 2]);
+
+    if let Some(prev) = dest.prev_node() {
+        unsafe { (*prev).set_next(dest_slot as usize) };
+    }
+    if let Some(next) = dest.next_node() {
+        unsafe { (*next).set_prev(dest_slot as usize) };
+    }
+}
+
+/// 交换两个 slot 中的 capability 及其 MDB 节点，对应 seL4 的 `cteSwap`
+///
+/// # Safety
+///
+/// slot 需要指向有效的 [Cte]
+pub unsafe fn cte_swap(cap1: Cap, slot1: *mut Cte, cap2: Cap, slot2: *mut Cte) {
+    // 两个 slot 在 MDB 中可能相邻，按照 seL4 的顺序逐个通过指针更新
+    unsafe {
+        **slot1 = cap2;
+        **slot2 = cap1;
+
+        let mdb1 = (*slot1).mdb();
+        if let Some(prev) = (*slot1).prev_node() {
+            (*prev).set_next(slot2 as usize);
+        }
+        if let Some(next) = (*slot1).next_node() {
+            (*next).set_prev(slot2 as usize);
+        }
+
+        let mdb2 = (*slot2).mdb();
+        (*slot1).set_mdb(mdb2);
+        (*slot2).set_mdb(mdb1);
+
+        if let Some(prev) = (*slot1).prev_node() {
+            (*prev).set_next(slot1 as usize);
+        }
+        if let Some(next) = (*slot1).next_node() {
+            (*next).set_prev(slot1 as usize);
+        }
+    }
+}
+
+/// 删除 `slot` 在 MDB 中的所有子节点，对应 seL4 的 `cteRevoke`
+///
+/// 每删除一个子节点检查一次抢占，被抢占时已经删除的子节点不会恢复，重新执行时从剩余的子节点继续
+///
+/// # Safety
+///
+/// slot 需要指向有效的 [Cte]
+pub unsafe fn cte_revoke(slot: *mut Cte) -> Result<(), Exception> {
+    let cte = unsafe { &*slot };
+    // 子节点总是紧跟在父节点之后
+    while let Some(next) = cte.next_node() {
+        if !is_mdb_parent_of(cte, unsafe { &*next }) {
+            break;
+        }
+        unsafe { cte_delete(next, true)? };
+        preemption_point()?;
+    }
+    Ok(())
+}
+
+/// 删除 `slot` 中的 capability，对应 seL4 的 `cteDelete`
+///
+/// `exposed` 表示 slot 是否对用户可见，用户可见的 slot 总是会被清空，
+/// 内部的 slot 只有在成功回收之后才会清空
+///
+/// # Safety
+///
+/// slot 需要指向有效的 [Cte]
+pub unsafe fn cte_delete(slot: *mut Cte, exposed: bool) -> Result<(), Exception> {
+    let ret = unsafe { finalise_slot(slot, exposed)? };
+    if exposed || ret.success {
+        unsafe { empty_slot(slot, ret.cleanup_info) };
+    }
+    Ok(())
+}
+
+/// 删除一个可以立即回收的 capability，对应 seL4 的 `cteDeleteOne`
+///
+/// # Safety
+///
+/// slot 需要指向有效的 [Cte]
+pub unsafe fn cte_delete_one(slot: *mut Cte) {
+    let cte = unsafe { &*slot };
+    if cte.is_null() {
+        return;
+    }
+    let ret = finalise_cap(**cte, is_final_capability(cte), true);
+    assert!(
+        cap_removable(&ret.remainder, slot) && ret.cleanup_info.is_null(),
+        "cteDeleteOne: cap should be removable"
+    );
+    unsafe { empty_slot(slot, Cap::null()) };
+}
+
+/// [finalise_slot] 的结果
+struct FinaliseSlot {
+    /// slot 是否已经完成回收，可以清空
+    success: bool,
+    cleanup_info: Cap,
+}
+
+/// 回收 `slot` 中的 capability，对应 seL4 的 `finaliseSlot`
+///
+/// CNode 和 TCB 的最后一个 capability 变为 zombie 后，每次删除其中的一个 slot 并检查抢占。
+/// `immediate` 为 `false` 时遇到指向自身的 zombie 直接返回，由上一级的 [reduce_zombie] 处理
+unsafe fn finalise_slot(slot: *mut Cte, immediate: bool) -> Result<FinaliseSlot, Exception> {
+    while unsafe { !(**slot).is_null() } {
+        let cte = unsafe { &mut *slot };
+        let ret = finalise_cap(**cte, is_final_capability(cte), false);
+
+        if cap_removable(&ret.remainder, slot) {
+            return Ok(FinaliseSlot {
+                success: true,
+                cleanup_info: ret.cleanup_info,
+            });
+        }
+
+        **cte = ret.remainder;
+
+        if !immediate && cap_cyclic_zombie(&ret.remainder, slot) {
+            return Ok(FinaliseSlot {
+                success: false,
+                cleanup_info: ret.cleanup_info,
+            });
+        }
+
+        unsafe { reduce_zombie(slot, immediate)? };
+        preemption_point()?;
+    }
+    Ok(FinaliseSlot {
+        success: true,
+        cleanup_info: Cap::null(),
+    })
+}
+
+/// 删除 zombie 中的一个 slot，对应 seL4 的 `reduceZombie`
+///
+/// `immediate` 为 `true` 时删除 zombie 中的最后一个 slot，否则将 zombie 与其指向的第一个 slot 交换，
+/// 使删除在 zombie 指向的对象内部继续进行
+unsafe fn reduce_zombie(slot: *mut Cte, immediate: bool) -> Result<(), Exception> {
+    let CapView::ZombieCap(zombie) = (unsafe { **slot }).view() else {
+        panic!("reduceZombie: expected zombie");
+    };
+    let ptr = zombie.zombie_ptr() as *mut Cte;
+    let n = zombie.zombie_number();
+    let zombie_type = zombie.get_cap_zombie_type();
+    assert!(n > 0, "reduceZombie: expected unremovable zombie");
+
+    if immediate {
+        let end_slot = ptr.wrapping_add(n - 1);
+        unsafe { cte_delete(end_slot, false)? };
+
+        match unsafe { **slot }.view() {
+            CapView::NullCap(_) => {}
+            CapView::ZombieCap(mut zombie2) => {
+                let ptr2 = zombie2.zombie_ptr() as *mut Cte;
+                if ptr == ptr2
+                    && zombie2.zombie_number() == n
+                    && zombie2.get_cap_zombie_type() == zombie_type
+                {
+                    assert!(unsafe { (**end_slot).is_null() });
+                    zombie2.set_zombie_number(n - 1);
+                    unsafe { **slot = zombie2.into() };
+                } else {
+                    assert!(
+                        ptr2 == slot && ptr != slot,
+                        "Expected new Zombie to be self-referential."
+                    );
+                }
+            }
+            _ => panic!("Expected recursion to result in Zombie."),
+        }
+    } else {
+        assert!(
+            ptr != slot,
+            "Cyclic zombie passed to unexposed reduceZombie"
+        );
+        if let CapView::ZombieCap(inner) = unsafe { **ptr }.view() {
+            assert!(
+                ptr != inner.zombie_ptr() as *mut Cte,
+                "Moving self-referential Zombie aside."
+            );
+        }
+        unsafe { cap_swap_for_delete(ptr, slot) };
+    }
+    Ok(())
+}
+
+/// 对应 seL4 的 `capSwapForDelete`
+unsafe fn cap_swap_for_delete(slot1: *mut Cte, slot2: *mut Cte) {
+    if slot1 == slot2 {
+        return;
+    }
+    unsafe { cte_swap(**slot1, slot1, **slot2, slot2) };
+}
+
+/// [finalise_cap] 返回的剩余部分是否可以直接从 `slot` 中删除，对应 seL4 的 `capRemovable`
+fn cap_removable(cap: &Cap, slot: *mut Cte) -> bool {
+    match cap.view() {
+        CapView::NullCap(_) => true,
+        CapView::ZombieCap(zombie) => {
+            let n = zombie.zombie_number();
+            n == 0 || (n == 1 && slot == zombie.zombie_ptr() as *mut Cte)
+        }
+        _ => panic!("finaliseCap should only return Zombie or NullCap"),
+    }
+}
+
+/// `cap` 是否为指向 `slot` 的 zombie，对应 seL4 的 `capCyclicZombie`
+fn cap_cyclic_zombie(cap: &Cap, slot: *mut Cte) -> bool {
+    matches!(cap.view(), CapView::ZombieCap(zombie) if zombie.zombie_ptr() as *mut Cte == slot)
+}
+
+/// `cte` 是否为对象的最后一个 capability，对应 seL4 的 `isFinalCapability`
+///
+/// 指向同一对象的 capability 在 MDB 中相邻，只需要检查前后两个节点
+pub fn is_final_capability(cte: &Cte) -> bool {
+    let prev_is_same_object = cte
+        .prev_node()
+        .is_some_and(|prev| same_object_as(unsafe { &*prev }, cte));
+    !prev_is_same_object
+        && cte
+            .next_node()
+            .is_none_or(|next| !same_object_as(cte, unsafe { &*next }))
+}
+
+/// 删除 `slot` 是否可能需要多次抢占才能完成，对应 seL4 的 `slotCapLongRunningDelete`
+pub fn slot_cap_long_running_delete(slot: &Cte) -> bool {
+    if slot.is_null() || !is_final_capability(slot) {
+        return false;
+    }
+    matches!(
+        slot.view(),
+        CapView::ThreadCap(_) | CapView::ZombieCap(_) | CapView::CnodeCap(_)
+    )
+}
+
+/// 清空 `slot` 并将其从 MDB 中移除，对应 seL4 的 `emptySlot`
+///
+/// # Safety
+///
+/// slot 需要指向有效的 [Cte]
+pub unsafe fn empty_slot(slot: *mut Cte, cleanup_info: Cap) {
+    let cte = unsafe { &mut *slot };
+    if cte.is_null() {
+        return;
+    }
+
+    if let Some(prev) = cte.prev_node() {
+        unsafe { (*prev).set_next(cte.next()) };
+    }
+    if let Some(next) = cte.next_node() {
+        let next = unsafe { &mut *next };
+        next.set_prev(cte.prev());
+        next.set_first_badged(next.first_badge() || cte.first_badge());
+    }
+
+    **cte = Cap::null();
+    cte.set_mdb([0; 2]);
+    post_cap_deletion(cleanup_info);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arch::TCB_BITS,
+        model::statedata::node_state,
+        object::{
+            cap::write_slot,
+            ipc::{bind_notification, EndPointState, ENDPOINT_BITS, NOTIFICATION_BITS},
+            structures::{
+                EndpointCap, Notification, NotificationCap, ReplyCap, ThreadCap, UntypedCap,
+            },
+            tcb::{tcb_cte_ptr, tcb_ep_append, TcbQueue, TCB},
+            test_utils::{alloc_object, alloc_tcb, cnode_cap, slot, write},
+        },
+    };
+    use InvocationLabel::*;
+
+    const ALL_RIGHTS: usize = 0xf;
+
+    fn cnode(cap: Cap) -> CnodeCap {
+        match cap.view() {
+            CapView::CnodeCap(cap) => cap,
+            _ => unreachable!(),
+        }
+    }
+
+    fn endpoint(cap: Cap) -> EndpointCap {
+        match cap.view() {
+            CapView::EndpointCap(cap) => cap,
+            _ => panic!("not an endpoint cap"),
+        }
+    }
+
+    fn full_ep() -> Cap {
+        EndpointCap::new(0, true, true, true, true, 0x1000).into()
+    }
+
+    #[test]
+    fn illegal_and_truncated() {
+        let root = cnode_cap(4, 0, 0);
+        assert_eq!(
+            decode_cnode_invocation(UntypedRetype, cnode(root), &[1, 4], &[]),
+            Err(SyscallError::IllegalOperation.into())
+        );
+        assert_eq!(
+            decode_cnode_invocation(CNodeCopy, cnode(root), &[1], &[]),
+            Err(SyscallError::TruncatedMessage.into())
+        );
+        assert_eq!(
+            decode_cnode_invocation(CNodeCopy, cnode(root), &[1, 4, 2, 4], &[]),
+            Err(SyscallError::TruncatedMessage.into())
+        );
+        assert_eq!(
+            decode_cnode_invocation(CNodeCopy, cnode(root), &[1, 0], &[]),
+            Err(SyscallError::RangeError { min: 1, max: 64 }.into())
+        );
+    }
+
+    #[test]
+    fn mint_badge_and_rights() {
+        let root = cnode_cap(4, 0, 0);
+        write(root, 1, full_ep());
+
+        // 只保留写权限，设置 badge
+        decode_cnode_invocation(CNodeMint, cnode(root), &[2, 4, 1, 4, 0x1, 0x42], &[root]).unwrap();
+        let ep = endpoint(unsafe { **slot(root, 2) });
+        assert_eq!(ep.get_cap_ep_badge(), 0x42);
+        assert_eq!(ep.get_cap_can_send(), 1);
+        assert_eq!(ep.get_cap_can_receive(), 0);
+        assert_eq!(ep.get_cap_can_grant(), 0);
+        unsafe {
+            assert_eq!((*slot(root, 1)).next(), slot(root, 2) as usize);
+            assert_eq!((*slot(root, 2)).prev(), slot(root, 1) as usize);
+        }
+
+        // 已经设置 badge 的 capability 不能再次修改 badge
+        assert_eq!(
+            decode_cnode_invocation(
+                CNodeMint,
+                cnode(root),
+                &[3, 4, 2, 4, ALL_RIGHTS, 0x43],
+                &[root]
+            ),
+            Err(SyscallError::IllegalOperation.into())
+        );
+        assert_eq!(
+            decode_cnode_invocation(CNodeMint, cnode(root), &[3, 4, 2, 4, ALL_RIGHTS], &[root]),
+            Err(SyscallError::TruncatedMessage.into())
+        );
+    }
+
+    #[test]
+    fn copy_errors() {
+        let root = cnode_cap(4, 0, 0);
+        write(root, 1, full_ep());
+        write(root, 2, full_ep());
+
+        assert_eq!(
+            decode_cnode_invocation(CNodeCopy, cnode(root), &[2, 4, 1, 4, ALL_RIGHTS], &[root]),
+            Err(SyscallError::DeleteFirst.into())
+        );
+        assert_eq!(
+            decode_cnode_invocation(CNodeCopy, cnode(root), &[3, 4, 5, 4, ALL_RIGHTS], &[root]),
+            Err(SyscallError::FailedLookup {
+                was_source: true,
+                fault: LookupFault::MissingCapability { bits_left: 4 },
+            }
+            .into())
+        );
+        assert_eq!(
+            decode_cnode_invocation(
+                CNodeCopy,
+                cnode(root),
+                &[3, 4, 1, 4, ALL_RIGHTS],
+                &[Cap::null()]
+            ),
+            Err(SyscallError::FailedLookup {
+                was_source: true,
+                fault: LookupFault::InvalidRoot {},
+            }
+            .into())
+        );
+    }
+
+    #[test]
+    fn move_and_mutate() {
+        let root = cnode_cap(4, 0, 0);
+        let ep = full_ep();
+        write(root, 1, ep);
+
+        decode_cnode_invocation(CNodeMove, cnode(root), &[2, 4, 1, 4], &[root]).unwrap();
+        assert!(unsafe { (**slot(root, 1)).is_null() });
+        assert_eq!(unsafe { **slot(root, 2) }.raw(), ep.raw());
+
+        // Mutate 修改 CNode 的 guard，guard 位数为 4，guard 为 0x5
+        write(root, 3, cnode_cap(4, 0, 0));
+        decode_cnode_invocation(
+            CNodeMutate,
+            cnode(root),
+            &[4, 4, 3, 4, (0x5 << 6) | 4],
+            &[root],
+        )
+        .unwrap();
+        let mutated = cnode(unsafe { **slot(root, 4) });
+        assert_eq!(mutated.get_cap_c_node_guard_size(), 4);
+        assert_eq!(mutated.get_cap_c_node_guard(), 0x5);
+
+        // Mutate 不能修改 badge
+        assert_eq!(
+            decode_cnode_invocation(CNodeMutate, cnode(root), &[5, 4, 2, 4, 0x42], &[root]),
+            Err(SyscallError::IllegalOperation.into())
+        );
+    }
+
+    #[test]
+    fn rotate() {
+        let root = cnode_cap(4, 0, 0);
+        // Rotate 使用 preserve 更新 capability，endpoint 会变为空 capability
+        let (a, b) = (cnode_cap(2, 0, 0), cnode_cap(2, 0, 0));
+        write(root, 1, a);
+        write(root, 2, b);
+
+        // 1 -> 2 -> 3
+        let args = [3, 4, 0, 2, 4, 0, 1, 4];
+        decode_cnode_invocation(CNodeRotate, cnode(root), &args, &[root, root]).unwrap();
+        assert!(unsafe { (**slot(root, 1)).is_null() });
+        assert_eq!(unsafe { **slot(root, 2) }.raw(), a.raw());
+        assert_eq!(unsafe { **slot(root, 3) }.raw(), b.raw());
+
+        // 目标与源相同时交换
+        let args = [2, 4, 0, 3, 4, 0, 2, 4];
+        decode_cnode_invocation(CNodeRotate, cnode(root), &args, &[root, root]).unwrap();
+        assert_eq!(unsafe { **slot(root, 2) }.raw(), b.raw());
+        assert_eq!(unsafe { **slot(root, 3) }.raw(), a.raw());
+
+        // pivot 为空
+        write(root, 6, full_ep());
+        let args = [1, 4, 0, 5, 4, 0, 2, 4];
+        assert_eq!(
+            decode_cnode_invocation(CNodeRotate, cnode(root), &args, &[root, root]),
+            Err(SyscallError::FailedLookup {
+                was_source: false,
+                fault: LookupFault::MissingCapability { bits_left: 4 },
+            }
+            .into())
+        );
+        let args = [1, 4, 0, 6, 4, 0, 2, 4];
+        assert_eq!(
+            decode_cnode_invocation(CNodeRotate, cnode(root), &args, &[root, root]),
+            Err(SyscallError::IllegalOperation.into())
+        );
+    }
+
+    #[test]
+    fn delete_unlinks() {
+        let root = cnode_cap(4, 0, 0);
+        write(root, 1, full_ep());
+        decode_cnode_invocation(CNodeCopy, cnode(root), &[2, 4, 1, 4, ALL_RIGHTS], &[root])
+            .unwrap();
+        decode_cnode_invocation(CNodeCopy, cnode(root), &[3, 4, 2, 4, ALL_RIGHTS], &[root])
+            .unwrap();
+
+        decode_cnode_invocation(CNodeDelete, cnode(root), &[2, 4], &[]).unwrap();
+        unsafe {
+            assert!((**slot(root, 2)).is_null());
+            assert_eq!((*slot(root, 2)).mdb(), [0; 2]);
+            assert_eq!((*slot(root, 1)).next(), slot(root, 3) as usize);
+            assert_eq!((*slot(root, 3)).prev(), slot(root, 1) as usize);
+        }
+    }
+
+    #[test]
+    fn badged_derivation_tree() {
+        let root = cnode_cap(4, 0, 0);
+        unsafe { write_slot(slot(root, 1), full_ep()) };
+        let mint = |dest, src, badge| {
+            decode_cnode_invocation(
+                CNodeMint,
+                cnode(root),
+                &[dest, 4, src, 4, ALL_RIGHTS, badge],
+                &[root],
+            )
+        };
+        let copy = |dest, src| {
+            decode_cnode_invocation(
+                CNodeCopy,
+                cnode(root),
+                &[dest, 4, src, 4, ALL_RIGHTS],
+                &[root],
+            )
+        };
+
+        mint(2, 1, 5).unwrap();
+        copy(3, 2).unwrap();
+        mint(4, 1, 5).unwrap();
+        let cte = |pos| unsafe { &*slot(root, pos) };
+        assert!(cte(2).revocable() && cte(2).first_badge());
+        assert!(!cte(3).revocable() && !cte(3).first_badge());
+        assert!(cte(4).revocable() && cte(4).first_badge());
+
+        // MDB 中的顺序为 1 4 2 3，相同 badge 的第二次 Mint 不是第一次 Mint 的父节点
+        assert!(is_mdb_parent_of(cte(1), cte(4)));
+        assert!(is_mdb_parent_of(cte(2), cte(3)));
+        assert!(!is_mdb_parent_of(cte(4), cte(2)));
+        assert!(!is_mdb_parent_of(cte(3), cte(2)));
+
+        decode_cnode_invocation(CNodeRevoke, cnode(root), &[4, 4], &[]).unwrap();
+        assert!(!cte(2).is_null() && !cte(3).is_null());
+        decode_cnode_invocation(CNodeRevoke, cnode(root), &[2, 4], &[]).unwrap();
+        assert!(cte(3).is_null());
+        decode_cnode_invocation(CNodeRevoke, cnode(root), &[1, 4], &[]).unwrap();
+        assert!(cte(2).is_null() && cte(4).is_null());
+        assert_eq!(cte(1).mdb(), [0, 0x3]);
+    }
+
+    #[test]
+    fn untyped_with_children() {
+        let root = cnode_cap(4, 0, 0);
+        let ut = UntypedCap::new(0, false, 16, 0x10000).into();
+        unsafe { write_slot(slot(root, 1), ut) };
+        let copy = |dest, src| {
+            decode_cnode_invocation(
+                CNodeCopy,
+                cnode(root),
+                &[dest, 4, src, 4, ALL_RIGHTS],
+                &[root],
+            )
+        };
+
+        copy(2, 1).unwrap();
+        assert_eq!(copy(3, 1), Err(SyscallError::RevokeFirst.into()));
+
+        // untyped 中的对象同样是 untyped 的子节点
+        decode_cnode_invocation(CNodeRevoke, cnode(root), &[1, 4], &[]).unwrap();
+        let ep: Cap = EndpointCap::new(0, true, true, true, true, 0x18000).into();
+        unsafe { cte_insert(ep, slot(root, 1), slot(root, 3)) };
+        assert!(is_mdb_parent_of(unsafe { &*slot(root, 1) }, unsafe {
+            &*slot(root, 3)
+        }));
+        assert_eq!(copy(2, 1), Err(SyscallError::RevokeFirst.into()));
+    }
+
+    #[test]
+    fn delete_cnode_through_zombie() {
+        let root = cnode_cap(4, 0, 0);
+        let inner = cnode_cap(2, 0, 0);
+        unsafe {
+            write_slot(slot(root, 1), inner);
+            for pos in 0..4 {
+                write_slot(slot(inner, pos), full_ep());
+            }
+        }
+
+        decode_cnode_invocation(CNodeDelete, cnode(root), &[1, 4], &[]).unwrap();
+        assert!(unsafe { (**slot(root, 1)).is_null() });
+        for pos in 0..4 {
+            assert!(unsafe { (**slot(inner, pos)).is_null() });
+        }
+    }
+
+    #[test]
+    fn delete_self_referential_cnode() {
+        // CNode 中唯一指向自身的 capability 位于第 0 个 slot
+        let inner = cnode_cap(2, 0, 0);
+        unsafe {
+            write_slot(slot(inner, 0), inner);
+            write_slot(slot(inner, 3), full_ep());
+        }
+
+        decode_cnode_invocation(CNodeDelete, cnode(inner), &[0, 2], &[]).unwrap();
+        for pos in 0..4 {
+            assert!(unsafe { (**slot(inner, pos)).is_null() });
+        }
+    }
+
+    #[test]
+    fn delete_thread_through_zombie() {
+        let root = cnode_cap(4, 0, 0);
+        let tcb = alloc_object(TCB_BITS) + bit!(TCB_BITS - 1);
+        unsafe {
+            write_slot(slot(root, 1), ThreadCap::new(tcb).into());
+            write_slot(tcb_cte_ptr(tcb, 0), full_ep());
+        }
+
+        // 同一个 TCB 的 capability 还有其他副本时只删除 slot
+        decode_cnode_invocation(CNodeCopy, cnode(root), &[2, 4, 1, 4, ALL_RIGHTS], &[root])
+            .unwrap();
+        let cte = |pos| unsafe { &*slot(root, pos) };
+        assert!(!is_final_capability(cte(1)));
+        assert!(!slot_cap_long_running_delete(cte(1)));
+        decode_cnode_invocation(CNodeDelete, cnode(root), &[2, 4], &[]).unwrap();
+        assert!(unsafe { !(**tcb_cte_ptr(tcb, 0)).is_null() });
+
+        assert!(slot_cap_long_running_delete(cte(1)));
+        decode_cnode_invocation(CNodeDelete, cnode(root), &[1, 4], &[]).unwrap();
+        assert!(cte(1).is_null());
+        assert!(unsafe { (**tcb_cte_ptr(tcb, 0)).is_null() });
+    }
+
+    #[test]
+    fn delete_bound_notification() {
+        let root = cnode_cap(4, 0, 0);
+        let tcb = alloc_tcb();
+        let ntfn = alloc_object(NOTIFICATION_BITS);
+        write(root, 1, NotificationCap::new(0, true, true, ntfn).into());
+        bind_notification(tcb, unsafe { &mut *(ntfn as *mut Notification) });
+        decode_cnode_invocation(CNodeCopy, cnode(root), &[2, 4, 1, 4, ALL_RIGHTS], &[root])
+            .unwrap();
+
+        // 还有其他 capability 时保持绑定
+        decode_cnode_invocation(CNodeDelete, cnode(root), &[2, 4], &[]).unwrap();
+        assert_eq!(tcb.bound_notification() as usize, ntfn);
+        decode_cnode_invocation(CNodeDelete, cnode(root), &[1, 4], &[]).unwrap();
+        assert!(tcb.bound_notification().is_null());
+        let ntfn = unsafe { &*(ntfn as *const Notification) };
+        assert_eq!(ntfn.get_ntfn_bound_tcb(), 0);
+    }
+
+    #[test]
+    fn save_caller_and_cancel_badged_sends() {
+        let root = cnode_cap(4, 0, 0);
+        let caller = alloc_tcb();
+        unsafe { (*node_state()).cur_thread = caller };
+        let reply: Cap = ReplyCap::new(true, false, 0x1000).into();
+        *caller.cte(TcbCnodeIndex::Caller) = Cte::new(reply);
+
+        decode_cnode_invocation(CNodeSaveCaller, cnode(root), &[2, 4], &[]).unwrap();
+        assert!(caller.cte(TcbCnodeIndex::Caller).is_null());
+        assert!(matches!(
+            unsafe { **slot(root, 2) }.view(),
+            CapView::ReplyCap(_)
+        ));
+        // 没有 caller capability 时不做处理
+        decode_cnode_invocation(CNodeSaveCaller, cnode(root), &[3, 4], &[]).unwrap();
+        assert!(unsafe { (**slot(root, 3)).is_null() });
+
+        // badge 为 0 时不做处理
+        write(root, 4, full_ep());
+        decode_cnode_invocation(CNodeCancelBadgedSends, cnode(root), &[4, 4], &[]).unwrap();
+    }
+
+    #[test]
+    fn cancel_badged_sends_restarts_matching_senders() {
+        let root = cnode_cap(4, 0, 0);
+        let ep_ptr = alloc_object(ENDPOINT_BITS);
+        let ep = unsafe { &mut *(ep_ptr as *mut Endpoint) };
+        let mut queue = TcbQueue::new();
+        let senders = [1, 2, 1].map(|badge| {
+            let tcb = alloc_tcb();
+            tcb.state_mut().set_ts_type(ThreadStateType::BlockedOnSend);
+            tcb.state_mut().set_blocking_ipc_badge(badge);
+            queue = tcb_ep_append(tcb, queue);
+            tcb
+        });
+        ep.set_queue(queue);
+        ep.set_state(EndPointState::Send as usize);
+
+        let badged = EndpointCap::new(1, true, true, true, true, ep_ptr);
+        write(root, 4, badged.into());
+        decode_cnode_invocation(CNodeCancelBadgedSends, cnode(root), &[4, 4], &[]).unwrap();
+
+        assert_eq!(senders[0].state().ts_type(), ThreadStateType::Restart);
+        assert_eq!(senders[2].state().ts_type(), ThreadStateType::Restart);
+        assert_eq!(senders[1].state().ts_type(), ThreadStateType::BlockedOnSend);
+        assert_eq!(ep.get_state(), EndPointState::Send as usize);
+        assert_eq!(ep.queue().head, senders[1].ptr() as *mut TCB);
+        assert_eq!(ep.queue().end, senders[1].ptr() as *mut TCB);
+
+        // 队列清空后 endpoint 回到 Idle
+        let badged = EndpointCap::new(2, true, true, true, true, ep_ptr);
+        write(root, 4, badged.into());
+        decode_cnode_invocation(CNodeCancelBadgedSends, cnode(root), &[4, 4], &[]).unwrap();
+        assert_eq!(senders[1].state().ts_type(), ThreadStateType::Restart);
+        assert_eq!(ep.get_state(), EndPointState::Idle as usize);
+        assert!(ep.queue().is_empty());
+    }
+}
@@ -0,0 +1,262 @@
+//! CSpace 地址解析
+//!
+//! 从根 CNode capability 开始，每一级 CNode 依次消耗 guard 和 radix 对应的位，
+//! 对应 seL4 `kernel/cspace.c` 中的 `resolveAddressBits`、`lookupSlot` 和 `lookupCap`。
+
+use super::{
+    cap::{slot_ptr, Cap, CapView, Cte},
+    fault::LookupFault,
+};
+use crate::api::failures::SyscallError;
+
+const WORD_BITS: usize = usize::BITS as usize;
+
+/// [resolve_address_bits] 的结果
+#[derive(Debug, Clone, Copy)]
+pub struct ResolveAddressBits {
+    /// 解析到的 slot
+    pub slot: *mut Cte,
+    /// 遇到非 CNode capability 时剩余未解析的位数
+    pub bits_remaining: usize,
+}
+
+/// 从 `node_cap` 开始解析 `cptr` 的低 `n_bits` 位
+///
+/// 解析完所有位，或者在还有剩余位时遇到非 CNode capability 都会返回对应的 slot，
+/// `node_cap` 不是 CNode 时返回 [LookupFault::InvalidRoot]
+pub fn resolve_address_bits(
+    node_cap: Cap,
+    cptr: usize,
+    mut n_bits: usize,
+) -> Result<ResolveAddressBits, LookupFault> {
+    let CapView::CnodeCap(mut node_cap) = node_cap.view() else {
+        return Err(LookupFault::InvalidRoot {});
+    };
+
+    loop {
+        let radix_bits = node_cap.get_cap_c_node_radix();
+        let guard_bits = node_cap.get_cap_c_node_guard_size();
+        let level_bits = radix_bits + guard_bits;
+        assert!(level_bits != 0, "all cnodes must resolve bits");
+
+        let cap_guard = node_cap.get_cap_c_node_guard();
+        // n_bits 为 64 且 guard_bits 为 0 时不能移位 64 位
+        let guard =
+            (cptr >> (n_bits.wrapping_sub(guard_bits) & (WORD_BITS - 1))) & (bit!(guard_bits) - 1);
+        if guard_bits > n_bits || guard != cap_guard {
+            return Err(LookupFault::GuardMismatch {
+                bits_left: n_bits as u8,
+                bits_found: guard_bits as u8,
+                guard_found: cap_guard as u64,
+            });
+        }
+        if level_bits > n_bits {
+            return Err(LookupFault::DepthMismatch {
+                bits_left: n_bits as u8,
+                bits_found: level_bits as u8,
+            });
+        }
+
+        let offset = (cptr >> (n_bits - level_bits)) & (bit!(radix_bits) - 1);
+        let slot = slot_ptr(node_cap.get_cap_c_node_ptr(), offset);
+        if n_bits == level_bits {
+            return Ok(ResolveAddressBits {
+                slot,
+                bits_remaining: 0,
+            });
+        }
+
+        n_bits -= level_bits;
+        match unsafe { **slot }.view() {
+            CapView::CnodeCap(cap) => node_cap = cap,
+            _ => {
+                return Ok(ResolveAddressBits {
+                    slot,
+                    bits_remaining: n_bits,
+                })
+            }
+        }
+    }
+}
+
+/// 在以 `root` 为根的 CSpace 中查找 `cptr` 对应的 slot，对应 seL4 的 `lookupSlot`
+///
+/// seL4 中的参数为线程，这里直接传入线程的 CSpace 根
+pub fn lookup_slot(root: Cap, cptr: usize) -> Result<*mut Cte, LookupFault> {
+    resolve_address_bits(root, cptr, WORD_BITS).map(|ret| ret.slot)
+}
+
+/// 在以 `root` 为根的 CSpace 中查找 `cptr` 对应的 capability，对应 seL4 的 `lookupCap`
+pub fn lookup_cap(root: Cap, cptr: usize) -> Result<Cap, LookupFault> {
+    lookup_slot(root, cptr).map(|slot| unsafe { **slot })
+}
+
+/// CNode 调用中通过 `root`、`cptr` 和 `depth` 指定 slot，对应 seL4 的 `lookupSlotForCNodeOp`
+///
+/// 必须恰好解析 `depth` 位，`is_source` 表示查找的是否为源 slot
+pub fn lookup_slot_for_cnode_op(
+    is_source: bool,
+    root: Cap,
+    cptr: usize,
+    depth: usize,
+) -> Result<*mut Cte, SyscallError> {
+    let failed_lookup = |fault| SyscallError::FailedLookup {
+        was_source: is_source,
+        fault,
+    };
+
+    if !matches!(root.view(), CapView::CnodeCap(_)) {
+        return Err(failed_lookup(LookupFault::InvalidRoot {}));
+    }
+    if !(1..=WORD_BITS).contains(&depth) {
+        return Err(SyscallError::RangeError {
+            min: 1,
+            max: WORD_BITS,
+        });
+    }
+
+    let ret = resolve_address_bits(root, cptr, depth).map_err(failed_lookup)?;
+    if ret.bits_remaining != 0 {
+        return Err(failed_lookup(LookupFault::DepthMismatch {
+            bits_left: ret.bits_remaining as u8,
+            bits_found: 0,
+        }));
+    }
+    Ok(ret.slot)
+}
+
+/// 对应 seL4 的 `lookupSourceSlot`
+pub fn lookup_source_slot(root: Cap, cptr: usize, depth: usize) -> Result<*mut Cte, SyscallError> {
+    lookup_slot_for_cnode_op(true, root, cptr, depth)
+}
+
+/// 对应 seL4 的 `lookupTargetSlot`
+pub fn lookup_target_slot(root: Cap, cptr: usize, depth: usize) -> Result<*mut Cte, SyscallError> {
+    lookup_slot_for_cnode_op(false, root, cptr, depth)
+}
+
+/// 对应 seL4 的 `lookupPivotSlot`
+pub fn lookup_pivot_slot(root: Cap, cptr: usize, depth: usize) -> Result<*mut Cte, SyscallError> {
+    lookup_slot_for_cnode_op(true, root, cptr, depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{
+        structures::EndpointCap,
+        test_utils::{cnode_cap, cnode_ptr, write},
+    };
+
+    #[test]
+    fn invalid_root() {
+        assert_eq!(
+            resolve_address_bits(Cap::null(), 0, WORD_BITS).unwrap_err(),
+            LookupFault::InvalidRoot {}
+        );
+        let ep = EndpointCap::new(0, false, false, true, true, 0x1000).into();
+        assert_eq!(lookup_cap(ep, 0).unwrap_err(), LookupFault::InvalidRoot {});
+    }
+
+    #[test]
+    fn single_level() {
+        let root = cnode_cap(4, WORD_BITS - 4, 0);
+        let ret = resolve_address_bits(root, 5, WORD_BITS).unwrap();
+        assert_eq!(ret.slot, slot_ptr(cnode_ptr(root), 5));
+        assert_eq!(ret.bits_remaining, 0);
+        assert!(lookup_cap(root, 5).unwrap().is_null());
+    }
+
+    #[test]
+    fn guard_mismatch() {
+        let root = cnode_cap(4, WORD_BITS - 4, 0x3);
+        assert_eq!(
+            lookup_slot(root, 5).unwrap_err(),
+            LookupFault::GuardMismatch {
+                bits_left: 64,
+                bits_found: 60,
+                guard_found: 0x3,
+            }
+        );
+        assert!(lookup_slot(root, 0x35).is_ok());
+
+        // guard 的位数大于剩余的位数
+        assert_eq!(
+            resolve_address_bits(root, 5, 8).unwrap_err(),
+            LookupFault::GuardMismatch {
+                bits_left: 8,
+                bits_found: 60,
+                guard_found: 0x3,
+            }
+        );
+    }
+
+    #[test]
+    fn depth_mismatch() {
+        let root = cnode_cap(8, 0, 0);
+        assert_eq!(
+            resolve_address_bits(root, 0, 4).unwrap_err(),
+            LookupFault::DepthMismatch {
+                bits_left: 4,
+                bits_found: 8,
+            }
+        );
+    }
+
+    #[test]
+    fn multi_level() {
+        // 第一级 radix 8，第二级 guard 4 + radix 4，第三级 guard 40 + radix 8
+        let root = cnode_cap(8, 0, 0);
+        let l2 = cnode_cap(4, 4, 0xa);
+        let l3 = cnode_cap(8, 40, 0);
+        write(root, 3, l2);
+        write(l2, 9, l3);
+        let ep: Cap = EndpointCap::new(0x55, false, false, true, true, 0x1000).into();
+        write(l3, 0x7f, ep);
+
+        let cptr = (3 << 56) | (0xa << 52) | (9 << 48) | 0x7f;
+        let ret = resolve_address_bits(root, cptr, WORD_BITS).unwrap();
+        assert_eq!(ret.slot, slot_ptr(cnode_ptr(l3), 0x7f));
+        assert_eq!(ret.bits_remaining, 0);
+        assert_eq!(lookup_cap(root, cptr).unwrap().raw(), ep.raw());
+
+        // 只解析到第二级，剩余的位数不足以解析第三级
+        let ret = resolve_address_bits(root, cptr >> 48, 16).unwrap();
+        assert_eq!(ret.slot, slot_ptr(cnode_ptr(l2), 9));
+        assert_eq!(ret.bits_remaining, 0);
+        assert_eq!(
+            resolve_address_bits(root, cptr >> 40, 24).unwrap_err(),
+            LookupFault::GuardMismatch {
+                bits_left: 8,
+                bits_found: 40,
+                guard_found: 0,
+            }
+        );
+
+        // 第二级的 guard 不匹配
+        assert_eq!(
+            lookup_slot(root, (3 << 56) | (0xb << 52)).unwrap_err(),
+            LookupFault::GuardMismatch {
+                bits_left: 56,
+                bits_found: 4,
+                guard_found: 0xa,
+            }
+        );
+    }
+
+    #[test]
+    fn stop_at_non_cnode() {
+        let root = cnode_cap(8, 0, 0);
+        let ep: Cap = EndpointCap::new(0, false, false, true, true, 0x1000).into();
+        write(root, 3, ep);
+
+        let ret = resolve_address_bits(root, (3 << 56) | 0x1234, WORD_BITS).unwrap();
+        assert_eq!(ret.slot, slot_ptr(cnode_ptr(root), 3));
+        assert_eq!(ret.bits_remaining, 56);
+        assert_eq!(lookup_cap(root, 3 << 56).unwrap().raw(), ep.raw());
+
+        // 空 slot 同样会结束解析
+        let ret = resolve_address_bits(root, 4 << 56, WORD_BITS).unwrap();
+        assert_eq!(ret.bits_remaining, 56);
+    }
+}
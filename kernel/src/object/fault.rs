@@ -8,6 +8,7 @@
 ///     tag guard_mismatch 3
 /// }
 /// ```
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum LookupFault {
     InvalidRoot {},
     MissingCapability {
@@ -37,7 +38,7 @@ pub enum LookupFault {
 ///     tag VMFault 5
 /// }
 /// ```
-///
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum Fault {
     NullFault,
     CapFault {
@@ -55,6 +56,7 @@ pub enum Fault {
 }
 
 #[repr(usize)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum VMFault {
     DataFault,
     InstructionFault,
@@ -93,6 +95,7 @@ pub enum VMFault {
 /// }
 /// ```
 #[repr(u8)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum ThreadStateType {
     InActive = 0,
     Running,
@@ -115,6 +118,82 @@ pub struct ThreadState {
     ts_type: ThreadStateType,
 }
 
+#[allow(clippy::new_without_default)]
+impl ThreadState {
+    /// 新创建的线程处于 Inactive 状态
+    pub const fn new() -> Self {
+        Self {
+            blocking_ipc_badge: 0,
+            blocking_ipc_can_grant: false,
+            blocking_ipc_can_grant_reply: false,
+            blocking_ipc_is_call: false,
+            tcb_queued: false,
+            blocking_object: 0,
+            ts_type: ThreadStateType::InActive,
+        }
+    }
+
+    pub const fn ts_type(&self) -> ThreadStateType {
+        self.ts_type
+    }
+
+    pub const fn set_ts_type(&mut self, ts_type: ThreadStateType) {
+        self.ts_type = ts_type;
+    }
+
+    /// 线程是否在调度队列中，对应 seL4 的 `tcbQueued`
+    pub const fn queued(&self) -> bool {
+        self.tcb_queued
+    }
+
+    pub const fn set_queued(&mut self, queued: bool) {
+        self.tcb_queued = queued;
+    }
+
+    /// 阻塞在 endpoint 或 notification 上时对应的对象地址，对应 seL4 的 `blockingObject`
+    pub const fn blocking_object(&self) -> usize {
+        self.blocking_object as usize
+    }
+
+    pub const fn set_blocking_object(&mut self, object: usize) {
+        self.blocking_object = object as u64;
+    }
+
+    /// 阻塞发送时使用的 badge，对应 seL4 的 `blockingIPCBadge`
+    pub const fn blocking_ipc_badge(&self) -> usize {
+        self.blocking_ipc_badge as usize
+    }
+
+    pub const fn set_blocking_ipc_badge(&mut self, badge: usize) {
+        self.blocking_ipc_badge = badge as u64;
+    }
+
+    pub const fn blocking_ipc_can_grant(&self) -> bool {
+        self.blocking_ipc_can_grant
+    }
+
+    pub const fn set_blocking_ipc_can_grant(&mut self, can_grant: bool) {
+        self.blocking_ipc_can_grant = can_grant;
+    }
+
+    pub const fn blocking_ipc_can_grant_reply(&self) -> bool {
+        self.blocking_ipc_can_grant_reply
+    }
+
+    pub const fn set_blocking_ipc_can_grant_reply(&mut self, can_grant_reply: bool) {
+        self.blocking_ipc_can_grant_reply = can_grant_reply;
+    }
+
+    /// 发送是否为 Call，对应 seL4 的 `blockingIPCIsCall`
+    pub const fn blocking_ipc_is_call(&self) -> bool {
+        self.blocking_ipc_is_call
+    }
+
+    pub const fn set_blocking_ipc_is_call(&mut self, is_call: bool) {
+        self.blocking_ipc_is_call = is_call;
+    }
+}
+
 /// 利用 const 静态检查断言信息
 const fn _check_type_width() {
     assert!(size_of::<LookupFault>() <= 16);
@@ -1,3 +1,18 @@
+use super::{
+    fault::{Fault, ThreadStateType},
+    structures::{Endpoint, Notification},
+    tcb::{tcb_ep_dequeue, tcb_sched_enqueue, TcbQueue, TCB},
+};
+use crate::kernel::thread::{reschedule_required, set_thread_state};
+
+/// endpoint 对象大小，对应 seL4 的 `seL4_EndpointBits`
+pub const ENDPOINT_BITS: usize = 4;
+/// notification 对象大小，对应 seL4 的 `seL4_NotificationBits`
+pub const NOTIFICATION_BITS: usize = 5;
+
+const _: () = assert!(size_of::<Endpoint>() == bit!(ENDPOINT_BITS));
+const _: () = assert!(size_of::<Notification>() == bit!(NOTIFICATION_BITS));
+
 #[repr(u8)]
 pub enum EndPointState {
     Idle = 0,
@@ -11,3 +26,75 @@ pub enum NotificationState {
     Waiting = 1,
     Active = 2,
 }
+
+impl Endpoint {
+    /// 等待在 endpoint 上的线程，对应 seL4 的 `ep_ptr_get_queue`
+    pub fn queue(&self) -> TcbQueue {
+        TcbQueue {
+            head: self.get_ep_queue_head() as *mut TCB,
+            end: self.get_ep_queue_tail() as *mut TCB,
+        }
+    }
+
+    /// 对应 seL4 的 `ep_ptr_set_queue`
+    pub fn set_queue(&mut self, queue: TcbQueue) {
+        self.set_ep_queue_head(queue.head as usize);
+        self.set_ep_queue_tail(queue.end as usize);
+    }
+}
+
+/// 取消 endpoint 上使用 `badge` 发送的线程，对应 seL4 的 `cancelBadgedSends`
+///
+/// 没有 fault 的线程处于 Restart 状态，重新执行发送；因为 fault 阻塞的线程不再运行
+pub fn cancel_badged_sends(ep: &mut Endpoint, badge: usize) {
+    if ep.get_state() != EndPointState::Send as usize {
+        return;
+    }
+    let mut queue = ep.queue();
+    ep.set_state(EndPointState::Idle as usize);
+    ep.set_queue(TcbQueue::new());
+
+    let mut thread = queue.head;
+    while let Some(tcb) = unsafe { thread.as_mut() } {
+        thread = tcb.ep_next();
+        if tcb.state().blocking_ipc_badge() != badge {
+            continue;
+        }
+        if tcb.fault() == Fault::NullFault {
+            set_thread_state(tcb, ThreadStateType::Restart);
+            tcb_sched_enqueue(tcb);
+        } else {
+            set_thread_state(tcb, ThreadStateType::InActive);
+        }
+        queue = tcb_ep_dequeue(tcb, queue);
+    }
+
+    ep.set_queue(queue);
+    if !queue.is_empty() {
+        ep.set_state(EndPointState::Send as usize);
+    }
+    reschedule_required();
+}
+
+/// 将 notification 绑定到线程，线程等待 endpoint 时也能接收 notification 的信号，
+/// 对应 seL4 的 `bindNotification`
+pub fn bind_notification(tcb: &mut TCB, ntfn: &mut Notification) {
+    ntfn.set_ntfn_bound_tcb(tcb.ptr());
+    tcb.set_bound_notification(ntfn);
+}
+
+/// 解除线程绑定的 notification，对应 seL4 的 `unbindNotification`
+pub fn unbind_notification(tcb: &mut TCB) {
+    if let Some(ntfn) = unsafe { tcb.bound_notification().as_mut() } {
+        ntfn.set_ntfn_bound_tcb(0);
+        tcb.set_bound_notification(core::ptr::null_mut());
+    }
+}
+
+/// 解除 notification 绑定的线程，对应 seL4 的 `unbindMaybeNotification`
+pub fn unbind_maybe_notification(ntfn: &mut Notification) {
+    if let Some(tcb) = unsafe { (ntfn.get_ntfn_bound_tcb() as *mut TCB).as_mut() } {
+        tcb.set_bound_notification(core::ptr::null_mut());
+        ntfn.set_ntfn_bound_tcb(0);
+    }
+}
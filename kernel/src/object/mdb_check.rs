@@ -0,0 +1,538 @@
+//! MDB 不变量检查
+//!
+//! 只在开启 `mdb-check` feature 时编译，调试版本每次返回用户态之前以及 `DebugCheckMdb` 系统调用通过
+//! [check_all] 检查内核中所有 CTE 所在的 MDB 链表，发现错误时立即 panic。
+//!
+//! CTE 只位于 CNode、TCB 和 zombie 中。检查从 root task 的 CNode 和当前线程开始，沿 slot 中的 capability
+//! 和 MDB 链表找到所有保存 CTE 的对象：不能从 root CNode 访问到的对象仍然由 untyped 派生，
+//! 指向它的 capability 位于某个已经找到的 MDB 链表中。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::Mutex;
+
+use super::{
+    cap::{Cap, CapView, Cte, ZOMBIE_TYPE_TCB},
+    cnode::{is_mdb_parent_of, CNode},
+    tcb::{tcb_cte_ptr, TCB_CNODE_ENTRIES},
+};
+use crate::{
+    arch::{PPTR_BASE, PPTR_TOP},
+    model::statedata::node_state,
+};
+
+/// 一次检查最多记录的 CNode、TCB 和 zombie 数量
+const MAX_CTE_ARRAYS: usize = 1024;
+
+/// root task 的 CNode，启动时通过 [register_root_cnode] 记录
+static ROOT_CNODE: AtomicUsize = AtomicUsize::new(0);
+static ROOT_CNODE_RADIX: AtomicUsize = AtomicUsize::new(0);
+
+/// [check_all] 找到的 CTE 数组，多个核同时检查时依次进行
+static CTE_ARRAYS: Mutex<[CteArray; MAX_CTE_ARRAYS]> =
+    Mutex::new([CteArray::EMPTY; MAX_CTE_ARRAYS]);
+
+/// MDB 中不满足的不变量，`slot` 为出错的 [Cte] 的地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MdbError {
+    /// 沿 `prev` 或 `next` 遍历时出现环
+    Cycle { slot: usize },
+    /// `slot` 的下一个节点的 `prev` 不指向 `slot`
+    BrokenLink { slot: usize },
+    /// 链表中的节点为空 capability，即 MDB 指针指向已经清空的 slot
+    DanglingNode { slot: usize },
+    /// `slot` 的 MDB 指针没有指向任何 CNode、TCB 或 zombie 中的 CTE
+    DanglingPointer { slot: usize },
+    /// 空 slot 的 MDB 指针没有清零
+    NullWithLinks { slot: usize },
+    /// `child` 由 `parent` 派生，但和 `parent` 之间隔着不属于 `parent` 的节点
+    ChildNotFollowingParent { parent: usize, child: usize },
+    /// 带 badge 的副本之前不是相同 badge 的 capability
+    BadgeMismatch { slot: usize },
+}
+
+/// CNode、TCB 或 zombie 中连续的 CTE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CteArray {
+    ptr: usize,
+    len: usize,
+}
+
+impl CteArray {
+    pub const EMPTY: Self = Self { ptr: 0, len: 0 };
+
+    pub const fn cnode(cnode: CNode) -> Self {
+        Self {
+            ptr: cnode.ptr(),
+            len: cnode.len(),
+        }
+    }
+
+    /// `tcb_ptr` 指向的 TCB 中的 CTE
+    pub fn tcb(tcb_ptr: usize) -> Self {
+        Self {
+            ptr: tcb_cte_ptr(tcb_ptr, 0) as usize,
+            len: TCB_CNODE_ENTRIES,
+        }
+    }
+
+    /// `cap` 指向的对象中的 CTE，对象中没有 CTE 时返回 `None`
+    fn from_cap(cap: &Cap) -> Option<Self> {
+        let len = match cap.view() {
+            CapView::CnodeCap(cnode) => bit!(cnode.get_cap_c_node_radix()),
+            CapView::ThreadCap(_) => TCB_CNODE_ENTRIES,
+            CapView::ZombieCap(zombie) if zombie.get_cap_zombie_type() == ZOMBIE_TYPE_TCB => {
+                TCB_CNODE_ENTRIES
+            }
+            CapView::ZombieCap(zombie) => bit!(zombie.zombie_bits()),
+            _ => return None,
+        };
+        Some(Self {
+            ptr: cap.ptr(),
+            len,
+        })
+    }
+
+    /// # Safety
+    ///
+    /// 需要指向有效的 CTE
+    unsafe fn slots(&self) -> &'static [Cte] {
+        unsafe { core::slice::from_raw_parts(self.ptr as *const Cte, self.len) }
+    }
+
+    /// `ptr` 是否为其中某个 CTE 的地址
+    fn contains(&self, ptr: usize) -> bool {
+        (self.ptr..self.ptr + self.len * size_of::<Cte>()).contains(&ptr)
+            && (ptr - self.ptr).is_multiple_of(size_of::<Cte>())
+    }
+}
+
+/// 记录 root task 的 CNode 作为检查的起点
+pub fn register_root_cnode(cnode: CNode) {
+    ROOT_CNODE_RADIX.store(cnode.radix(), Ordering::Relaxed);
+    ROOT_CNODE.store(cnode.ptr(), Ordering::Relaxed);
+}
+
+/// 检查内核中所有 CTE，出错时 panic
+pub fn check_all() {
+    let ptr = ROOT_CNODE.load(Ordering::Relaxed);
+    if ptr == 0 {
+        return;
+    }
+    let root = CteArray::cnode(CNode::new(ptr, ROOT_CNODE_RADIX.load(Ordering::Relaxed)));
+    let cur_thread = unsafe { (*node_state()).cur_thread } as usize;
+    let roots = [root, CteArray::tcb(cur_thread)];
+    let roots = if cur_thread == 0 {
+        &roots[..1]
+    } else {
+        &roots[..]
+    };
+    if let Err(err) = unsafe { check_from(roots, &mut *CTE_ARRAYS.lock()) } {
+        panic!("MDB invariant violated: {:?}", err);
+    }
+}
+
+/// 从 `roots` 开始检查所有能找到的 CTE，`arrays` 用于记录找到的 CNode、TCB 和 zombie
+///
+/// 先检查每个 slot 所在的 MDB 链表，并从链表中的 capability 找到新的 CNode 和 TCB，
+/// 最后检查所有 MDB 指针都指向找到的 CTE
+///
+/// # Safety
+///
+/// `roots` 需要指向有效的 CTE
+pub unsafe fn check_from(roots: &[CteArray], arrays: &mut [CteArray]) -> Result<(), MdbError> {
+    let mut found = Found { arrays, len: 0 };
+    for root in roots {
+        found.add(*root);
+    }
+
+    let mut index = 0;
+    while index < found.len {
+        for slot in unsafe { found.arrays[index].slots() } {
+            check_list(slot)?;
+            let mut node = if slot.is_null() {
+                None
+            } else {
+                Some(find_head(slot)?)
+            };
+            while let Some(cte) = node {
+                if let Some(array) = CteArray::from_cap(cte) {
+                    found.add(array);
+                }
+                node = next(cte)?;
+            }
+        }
+        index += 1;
+    }
+
+    for array in found.arrays[..found.len].iter() {
+        for slot in unsafe { array.slots() } {
+            let dangling = [slot.prev(), slot.next()]
+                .into_iter()
+                .any(|ptr| ptr != 0 && !found.contains(ptr));
+            if dangling {
+                return Err(MdbError::DanglingPointer { slot: addr(slot) });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 已经找到的 CTE 数组
+struct Found<'a> {
+    arrays: &'a mut [CteArray],
+    len: usize,
+}
+
+impl Found<'_> {
+    fn add(&mut self, array: CteArray) {
+        if self.arrays[..self.len]
+            .iter()
+            .any(|found| found.ptr == array.ptr)
+        {
+            return;
+        }
+        assert!(self.len < self.arrays.len(), "too many CTE arrays to check");
+        self.arrays[self.len] = array;
+        self.len += 1;
+    }
+
+    fn contains(&self, ptr: usize) -> bool {
+        self.arrays[..self.len]
+            .iter()
+            .any(|array| array.contains(ptr))
+    }
+}
+
+/// 检查 `slot` 所在的整个 MDB 链表
+pub fn check_list(slot: &Cte) -> Result<(), MdbError> {
+    if slot.is_null() {
+        return match slot.mdb() {
+            [0, 0] => Ok(()),
+            _ => Err(MdbError::NullWithLinks { slot: addr(slot) }),
+        };
+    }
+
+    let head = find_head(slot)?;
+    check_links(head)?;
+
+    let mut node = Some(head);
+    while let Some(cte) = node {
+        check_badge(cte)?;
+        check_children(cte)?;
+        node = next(cte)?;
+    }
+    Ok(())
+}
+
+/// 沿 `prev` 找到链表的第一个节点，使用快慢指针检测环
+fn find_head(slot: &Cte) -> Result<&Cte, MdbError> {
+    let (mut slow, mut fast) = (slot, slot);
+    loop {
+        let Some(prev1) = prev(fast)? else {
+            return Ok(fast);
+        };
+        let Some(prev2) = prev(prev1)? else {
+            return Ok(prev1);
+        };
+        fast = prev2;
+        slow = prev(slow)?.unwrap();
+        if core::ptr::eq(slow, fast) {
+            return Err(MdbError::Cycle { slot: addr(slow) });
+        }
+    }
+}
+
+/// 从第一个节点开始检查双向指针和空节点
+fn check_links(head: &Cte) -> Result<(), MdbError> {
+    let mut slow = head;
+    let mut node = head;
+    let mut steps = 0usize;
+    loop {
+        if node.is_null() {
+            return Err(MdbError::DanglingNode { slot: addr(node) });
+        }
+        let Some(next_node) = next(node)? else {
+            return Ok(());
+        };
+        if next_node.prev() != addr(node) {
+            return Err(MdbError::BrokenLink { slot: addr(node) });
+        }
+        node = next_node;
+
+        // 每前进两步慢指针前进一步
+        steps += 1;
+        if steps % 2 == 0 {
+            slow = next(slow)?.unwrap();
+            if core::ptr::eq(slow, node) {
+                return Err(MdbError::Cycle { slot: addr(node) });
+            }
+        }
+    }
+}
+
+/// 带 badge 且不是第一个的 endpoint 或 notification 之前需要是相同 badge 的 capability
+fn check_badge(cte: &Cte) -> Result<(), MdbError> {
+    if cte.first_badge() {
+        return Ok(());
+    }
+    let badge_of = |cap: &Cap| match cap.view() {
+        CapView::EndpointCap(ep) => Some((ep.get_cap_ep_ptr(), ep.get_cap_ep_badge())),
+        CapView::NotificationCap(ntfn) => {
+            Some((ntfn.get_cap_ntfn_ptr(), ntfn.get_cap_ntfn_badge()))
+        }
+        _ => None,
+    };
+    match badge_of(cte) {
+        Some((_, 0)) | None => Ok(()),
+        badge => match prev(cte)? {
+            Some(prev) if badge_of(prev) == badge => Ok(()),
+            _ => Err(MdbError::BadgeMismatch { slot: addr(cte) }),
+        },
+    }
+}
+
+/// `parent` 派生出的 capability 都需要位于 `parent` 之后连续的子节点中
+///
+/// 子节点之后的节点仍然满足 [is_mdb_parent_of] 时，它们之间需要有更近的父节点，
+/// 例如同一个 endpoint 上另一个带有相同 badge 的 capability
+fn check_children(parent: &Cte) -> Result<(), MdbError> {
+    let mut node = next(parent)?;
+    while let Some(cte) = node {
+        if !is_mdb_parent_of(parent, cte) {
+            break;
+        }
+        node = next(cte)?;
+    }
+    let Some(first_other) = node else {
+        return Ok(());
+    };
+    while let Some(cte) = node {
+        if is_mdb_parent_of(parent, cte) && !has_parent_between(first_other, cte)? {
+            return Err(MdbError::ChildNotFollowingParent {
+                parent: addr(parent),
+                child: addr(cte),
+            });
+        }
+        node = next(cte)?;
+    }
+    Ok(())
+}
+
+/// 从 `from` 到 `child` 之前是否有 `child` 的父节点
+fn has_parent_between(from: &Cte, child: &Cte) -> Result<bool, MdbError> {
+    let mut node = Some(from);
+    while let Some(cte) = node {
+        if core::ptr::eq(cte, child) {
+            break;
+        }
+        if is_mdb_parent_of(cte, child) {
+            return Ok(true);
+        }
+        node = next(cte)?;
+    }
+    Ok(false)
+}
+
+fn addr(cte: &Cte) -> usize {
+    cte as *const Cte as usize
+}
+
+/// 访问 MDB 指针之前检查其按照 CTE 对齐，内核中还需要位于内核窗口中
+fn follow(cte: &Cte, ptr: Option<*mut Cte>) -> Result<Option<&Cte>, MdbError> {
+    let Some(ptr) = ptr else {
+        return Ok(None);
+    };
+    let ptr_value = ptr as usize;
+    let in_window = cfg!(test) || (PPTR_BASE..PPTR_TOP).contains(&ptr_value);
+    if !ptr_value.is_multiple_of(size_of::<Cte>()) || !in_window {
+        return Err(MdbError::DanglingPointer { slot: addr(cte) });
+    }
+    Ok(Some(unsafe { &*ptr }))
+}
+
+fn next(cte: &Cte) -> Result<Option<&Cte>, MdbError> {
+    follow(cte, cte.next_node())
+}
+
+fn prev(cte: &Cte) -> Result<Option<&Cte>, MdbError> {
+    follow(cte, cte.prev_node())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{
+        cap::{write_slot, SLOT_BITS},
+        cnode::{cte_insert, cte_move},
+        structures::{EndpointCap, UntypedCap},
+        test_utils::{alloc_object, cnode_cap, cnode_ptr, slot},
+    };
+
+    fn ep(badge: usize) -> Cap {
+        EndpointCap::new(badge, true, true, true, true, 0x1000).into()
+    }
+
+    fn root() -> (Cap, CNode) {
+        let cap = cnode_cap(4, 0, 0);
+        (cap, CNode::new(cnode_ptr(cap), 4))
+    }
+
+    /// 从 `cnode` 开始检查所有能找到的 CTE
+    fn check(cnode: CNode) -> Result<(), MdbError> {
+        let mut arrays = [CteArray::EMPTY; 16];
+        unsafe { check_from(&[CteArray::cnode(cnode)], &mut arrays) }
+    }
+
+    #[test]
+    fn valid_tree() {
+        let (cap, cnode) = root();
+        unsafe {
+            write_slot(slot(cap, 0), ep(0));
+            cte_insert(ep(5), slot(cap, 0), slot(cap, 1));
+            cte_insert(ep(5), slot(cap, 1), slot(cap, 2));
+            cte_insert(ep(6), slot(cap, 0), slot(cap, 3));
+            cte_move(ep(5), slot(cap, 2), slot(cap, 4));
+            assert_eq!(check(cnode), Ok(()));
+        }
+    }
+
+    #[test]
+    fn broken_links() {
+        let (cap, cnode) = root();
+        unsafe {
+            write_slot(slot(cap, 0), ep(0));
+            cte_insert(ep(0), slot(cap, 0), slot(cap, 1));
+            cte_insert(ep(0), slot(cap, 1), slot(cap, 2));
+
+            (*slot(cap, 2)).set_prev(slot(cap, 0) as usize);
+            assert_eq!(
+                check(cnode),
+                Err(MdbError::BrokenLink {
+                    slot: slot(cap, 1) as usize
+                })
+            );
+            (*slot(cap, 2)).set_prev(slot(cap, 1) as usize);
+
+            **slot(cap, 1) = Cap::null();
+            assert_eq!(
+                check_list(&*slot(cap, 0)),
+                Err(MdbError::DanglingNode {
+                    slot: slot(cap, 1) as usize
+                })
+            );
+            assert_eq!(
+                check_list(&*slot(cap, 1)),
+                Err(MdbError::NullWithLinks {
+                    slot: slot(cap, 1) as usize
+                })
+            );
+            **slot(cap, 1) = ep(0);
+
+            (*slot(cap, 2)).set_next(slot(cap, 0) as usize);
+            (*slot(cap, 0)).set_prev(slot(cap, 2) as usize);
+            assert!(matches!(
+                check_list(&*slot(cap, 1)),
+                Err(MdbError::Cycle { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn badge_and_untyped_order() {
+        let (cap, _) = root();
+        unsafe {
+            write_slot(slot(cap, 0), ep(0));
+            cte_insert(ep(5), slot(cap, 0), slot(cap, 1));
+            cte_insert(ep(5), slot(cap, 1), slot(cap, 2));
+            (*slot(cap, 2)).set_first_badged(false);
+            **slot(cap, 1) = ep(6);
+            assert_eq!(
+                check_list(&*slot(cap, 0)),
+                Err(MdbError::BadgeMismatch {
+                    slot: slot(cap, 2) as usize
+                })
+            );
+
+            // untyped 区域中的 endpoint 被不属于该 untyped 的节点隔开
+            let ut = UntypedCap::new(0, false, 16, 0x10000).into();
+            let inside: Cap = EndpointCap::new(0, true, true, true, true, 0x18000).into();
+            let outside: Cap = EndpointCap::new(0, true, true, true, true, 0x40000).into();
+            write_slot(slot(cap, 8), ut);
+            cte_insert(inside, slot(cap, 8), slot(cap, 9));
+            assert_eq!(check_list(&*slot(cap, 8)), Ok(()));
+            cte_insert(outside, slot(cap, 8), slot(cap, 10));
+            assert_eq!(
+                check_list(&*slot(cap, 8)),
+                Err(MdbError::ChildNotFollowingParent {
+                    parent: slot(cap, 8) as usize,
+                    child: slot(cap, 9) as usize,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn unreachable_cnode_and_dangling_pointer() {
+        let (cap, cnode) = root();
+        unsafe {
+            // CNode 只保存了指向自己的 capability，只能通过 untyped 的 MDB 链表找到
+            let ut: Cap = UntypedCap::new(0, false, 16, 0x10000).into();
+            write_slot(slot(cap, 0), ut);
+            (*slot(cap, 0)).set_revocable(true);
+            let hidden = cnode_cap(2, 0, 0);
+            cte_insert(hidden, slot(cap, 0), slot(hidden, 0));
+            assert_eq!(check(cnode), Ok(()));
+            (*slot(hidden, 1)).set_prev(slot(cap, 0) as usize);
+            assert_eq!(
+                check(cnode),
+                Err(MdbError::NullWithLinks {
+                    slot: slot(hidden, 1) as usize
+                })
+            );
+            (*slot(hidden, 1)).set_prev(0);
+
+            // 链表中的节点不属于任何 CNode 或 TCB
+            let outside = alloc_object(SLOT_BITS) as *mut Cte;
+            write_slot(slot(cap, 1), ep(0));
+            write_slot(outside, ep(0));
+            (*slot(cap, 1)).set_next(outside as usize);
+            (*outside).set_prev(slot(cap, 1) as usize);
+            assert_eq!(check_list(&*slot(cap, 1)), Ok(()));
+            assert_eq!(
+                check(cnode),
+                Err(MdbError::DanglingPointer {
+                    slot: slot(cap, 1) as usize
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn endpoint_children_follow_parent() {
+        let (cap, cnode) = root();
+        unsafe {
+            write_slot(slot(cap, 0), ep(0));
+            (*slot(cap, 0)).set_revocable(true);
+            cte_insert(ep(5), slot(cap, 0), slot(cap, 1));
+            cte_insert(ep(5), slot(cap, 1), slot(cap, 2));
+            // 相同 badge 的另一个 capability 之后的副本属于更近的父节点
+            cte_insert(ep(5), slot(cap, 0), slot(cap, 3));
+            assert_eq!(check(cnode), Ok(()));
+
+            // 不相关的 capability 插在 endpoint 和它的副本之间
+            write_slot(slot(cap, 4), UntypedCap::new(0, false, 12, 0x40000).into());
+            (*slot(cap, 0)).set_next(slot(cap, 4) as usize);
+            (*slot(cap, 4)).set_prev(slot(cap, 0) as usize);
+            (*slot(cap, 4)).set_next(slot(cap, 3) as usize);
+            (*slot(cap, 3)).set_prev(slot(cap, 4) as usize);
+            assert_eq!(
+                check(cnode),
+                Err(MdbError::ChildNotFollowingParent {
+                    parent: slot(cap, 0) as usize,
+                    child: slot(cap, 3) as usize,
+                })
+            );
+        }
+    }
+}
@@ -1,7 +1,16 @@
+pub mod cap;
+pub mod cnode;
+pub mod cspace;
 pub mod fault;
 pub mod ipc;
+#[cfg(any(test, feature = "mdb-check"))]
+pub mod mdb_check;
+pub mod objecttype;
 pub mod structures;
 pub mod tcb;
+#[cfg(test)]
+pub(crate) mod test_utils;
+pub mod untyped;
 
 use core::ops::{Deref, DerefMut};
 
@@ -27,13 +36,23 @@ use core::ops::{Deref, DerefMut};
 ///         field mdbPrev 64
 /// }    
 /// ```
+///
+/// `mdbPrev` 位于低地址的 word，与 seL4 的 `cte_t` 布局一致，`next` 和 `prev` 保存的是相邻节点的地址
+#[repr(C)]
 pub struct MDBNode<T> {
     value: T,
-    next: usize,
     prev: usize,
+    next: usize,
 }
 
 impl<T> MDBNode<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            value,
+            next: 0,
+            prev: 0,
+        }
+    }
     pub const fn next(&self) -> usize {
         self.next & !0x3
     }
@@ -41,7 +60,7 @@ impl<T> MDBNode<T> {
         self.next = self.next & 0x3 | next
     }
     pub const fn set_first_badged(&mut self, first_badge: bool) {
-        self.next = self.next & !0x2 | (first_badge as usize);
+        self.next = self.next & !0x2 | ((first_badge as usize) << 1);
     }
     pub const fn first_badge(&self) -> bool {
         self.next & 0x2 != 0
@@ -58,6 +77,23 @@ impl<T> MDBNode<T> {
     pub const fn set_prev(&mut self, prev: usize) {
         self.prev = prev;
     }
+    /// MDB 部分的原始值 `[mdbPrev, mdbNext]`，用于在 slot 之间整体移动 MDB 节点
+    pub const fn mdb(&self) -> [usize; 2] {
+        [self.prev, self.next]
+    }
+    pub const fn set_mdb(&mut self, mdb: [usize; 2]) {
+        [self.prev, self.next] = mdb;
+    }
+    /// MDB 中的下一个节点，为空时返回 `None`
+    ///
+    /// 返回裸指针，相邻的节点可能同时被访问，由调用者保证解引用时没有别名
+    pub fn next_node(&self) -> Option<*mut Self> {
+        Some(self.next() as *mut Self).filter(|next| !next.is_null())
+    }
+    /// MDB 中的上一个节点，为空时返回 `None`
+    pub fn prev_node(&self) -> Option<*mut Self> {
+        Some(self.prev() as *mut Self).filter(|prev| !prev.is_null())
+    }
 }
 
 impl<T> Deref for MDBNode<T> {
@@ -0,0 +1,426 @@
+//! capability 的通用操作，对应 seL4 `object/objecttype.c`
+//!
+//! 架构相关的 capability 交给 [crate::arch] 中对应的 `arch_*` 函数处理。
+
+use sel4_types::{
+    invocation::InvocationLabel,
+    message::MSG_MAX_EXTRA_CAPS,
+    object::{self, ObjectType, OBJECT_TYPE_COUNT},
+    rights::CapRights,
+};
+
+use super::{
+    cap::{Cap, CapView, Cte, SLOT_BITS, ZOMBIE_TYPE_TCB},
+    cnode::{decode_cnode_invocation, ensure_no_children},
+    ipc::{unbind_maybe_notification, unbind_notification, ENDPOINT_BITS, NOTIFICATION_BITS},
+    structures::{
+        CnodeCap, EndpointCap, Notification, NotificationCap, ThreadCap, UntypedCap, ZombieCap,
+    },
+    tcb::{
+        decode_domain_invocation, decode_tcb_invocation, tcb_cte_ptr, TCB, TCB_CNODE_ENTRIES,
+        TCB_OFFSET,
+    },
+    untyped::decode_untyped_invocation,
+};
+use crate::{
+    api::failures::{Exception, SyscallError},
+    arch::{
+        arch_create_object, arch_derive_cap, arch_finalise_cap, arch_is_cap_revocable,
+        arch_mask_cap_rights, arch_object_size_bits, arch_same_object_as, arch_same_region_as,
+        get_current_cpu_index, MAX_UNTYPED_BITS, MIN_UNTYPED_BITS, TCB_BITS,
+    },
+    kernel::thread::suspend,
+    model::statedata::kernel_state,
+};
+
+/// 架构相关的 capability 的类型编号为奇数，对应 seL4 的 `isArchCap`
+pub const fn is_arch_cap(cap: &Cap) -> bool {
+    cap.cap_type() & 1 != 0
+}
+
+/// 复制 capability 时生成新的 capability，对应 seL4 的 `deriveCap`
+///
+/// 不能被复制的 capability 返回空 capability
+pub fn derive_cap(slot: &Cte, cap: Cap) -> Result<Cap, SyscallError> {
+    if is_arch_cap(&cap) {
+        return arch_derive_cap(cap);
+    }
+
+    match cap.view() {
+        CapView::ZombieCap(_) | CapView::IrqControlCap(_) | CapView::ReplyCap(_) => Ok(Cap::null()),
+        CapView::UntypedCap(_) => ensure_no_children(slot).map(|_| cap),
+        _ => Ok(cap),
+    }
+}
+
+/// `object_type` 类型的对象的大小，对应 seL4 的 `getObjectSize`
+///
+/// `user_size` 为 untyped 的大小或 CNode 的 radix，其他类型忽略
+pub const fn object_size_bits(object_type: ObjectType, user_size: usize) -> usize {
+    match object_type {
+        ObjectType::Untyped => user_size,
+        ObjectType::TCB => TCB_BITS,
+        ObjectType::Endpoint => ENDPOINT_BITS,
+        ObjectType::Notification => NOTIFICATION_BITS,
+        ObjectType::CapTable => SLOT_BITS + user_size,
+        _ => arch_object_size_bits(object_type),
+    }
+}
+
+/// 在 `region_base` 创建一个对象并返回指向它的 capability，对应 seL4 的 `createObject`
+///
+/// untyped 中的内存在 retype 之前已经通过 reset 清零，这里只初始化对象中不为零的部分
+///
+/// # Safety
+///
+/// `region_base` 需要指向未被使用且按照对象大小对齐的内存
+pub unsafe fn create_object(
+    object_type: ObjectType,
+    region_base: usize,
+    user_size: usize,
+    device_memory: bool,
+) -> Cap {
+    match object_type {
+        ObjectType::Untyped => UntypedCap::new(0, device_memory, user_size, region_base).into(),
+        ObjectType::TCB => {
+            let tcb = unsafe { TCB::from_ptr(region_base + TCB_OFFSET) };
+            *tcb = TCB::new();
+            tcb.set_affinity(get_current_cpu_index());
+            tcb.set_domain(unsafe { (*kernel_state()).cur_domain });
+            ThreadCap::new(region_base + TCB_OFFSET).into()
+        }
+        ObjectType::Endpoint => EndpointCap::new(0, true, true, true, true, region_base).into(),
+        ObjectType::Notification => NotificationCap::new(0, true, true, region_base).into(),
+        ObjectType::CapTable => CnodeCap::new(user_size, 0, 0, region_base).into(),
+        _ => unsafe { arch_create_object(object_type, region_base, device_memory) },
+    }
+}
+
+/// 内核中对象的大小需要与 libsel4 一致，用户态按照 libsel4 中的大小计算 untyped 的使用
+const fn _check_object_sizes() {
+    let mut i = 0;
+    while i < OBJECT_TYPE_COUNT {
+        let object_type = ObjectType::from_raw(i).unwrap();
+        assert!(object_size_bits(object_type, 1) == object_type.object_size_bits(1));
+        i += 1;
+    }
+}
+const _: () = _check_object_sizes();
+const _: () = assert!(SLOT_BITS == object::SLOT_BITS);
+const _: () = assert!(MIN_UNTYPED_BITS == object::MIN_UNTYPED_BITS);
+const _: () = assert!(MAX_UNTYPED_BITS == object::MAX_UNTYPED_BITS);
+
+/// [finalise_cap] 的结果，对应 seL4 的 `finaliseCap_ret_t`
+#[derive(Debug, Clone, Copy)]
+pub struct FinaliseCap {
+    /// 还需要继续清理的部分，为空 capability 或 zombie
+    pub remainder: Cap,
+    /// 从 slot 中删除之后还需要处理的 capability，交给 `postCapDeletion`
+    pub cleanup_info: Cap,
+}
+
+impl FinaliseCap {
+    pub const fn null() -> Self {
+        Self {
+            remainder: Cap::null(),
+            cleanup_info: Cap::null(),
+        }
+    }
+}
+
+/// 删除 capability 之前回收其引用的资源，对应 seL4 的 `finaliseCap`
+///
+/// `is_final` 表示是否为对象的最后一个 capability，CNode 和 TCB 的最后一个 capability
+/// 会变为 zombie，由调用者逐个删除其中的 slot。`exposed` 为 `true` 时要求能够立即完成
+pub fn finalise_cap(cap: Cap, is_final: bool, exposed: bool) -> FinaliseCap {
+    if is_arch_cap(&cap) {
+        return arch_finalise_cap(cap, is_final);
+    }
+
+    match cap.view() {
+        CapView::EndpointCap(_) => {
+            // TODO: is_final 时通过 cancelAllIPC 唤醒等待的线程
+            return FinaliseCap::null();
+        }
+        CapView::NotificationCap(ntfn) => {
+            if is_final {
+                let ntfn = unsafe { &mut *(ntfn.get_cap_ntfn_ptr() as *mut Notification) };
+                unbind_maybe_notification(ntfn);
+                // TODO: 通过 cancelAllSignals 唤醒等待的线程
+            }
+            return FinaliseCap::null();
+        }
+        CapView::ReplyCap(_) | CapView::NullCap(_) | CapView::DomainCap(_) => {
+            return FinaliseCap::null();
+        }
+        _ => {}
+    }
+
+    assert!(!exposed, "finaliseCap: failed to finalise immediately.");
+
+    match cap.view() {
+        CapView::CnodeCap(cnode) if is_final => {
+            let radix = cnode.get_cap_c_node_radix();
+            FinaliseCap {
+                remainder: ZombieCap::zombie_new(bit!(radix), radix, cnode.get_cap_c_node_ptr())
+                    .into(),
+                cleanup_info: Cap::null(),
+            }
+        }
+        CapView::ThreadCap(thread) if is_final => {
+            let tcb = unsafe { TCB::from_cap(&thread) };
+            unbind_notification(tcb);
+            suspend(tcb);
+            let cte_ptr = tcb_cte_ptr(thread.get_cap_tcb_ptr(), 0) as usize;
+            FinaliseCap {
+                remainder: ZombieCap::zombie_new(TCB_CNODE_ENTRIES, ZOMBIE_TYPE_TCB, cte_ptr)
+                    .into(),
+                cleanup_info: Cap::null(),
+            }
+        }
+        CapView::ZombieCap(_) => FinaliseCap {
+            remainder: cap,
+            cleanup_info: Cap::null(),
+        },
+        // TODO: 通过 deletingIRQHandler 屏蔽中断
+        CapView::IrqHandlerCap(_) if is_final => FinaliseCap {
+            remainder: Cap::null(),
+            cleanup_info: cap,
+        },
+        _ => FinaliseCap::null(),
+    }
+}
+
+/// 从 slot 中删除 capability 之后的处理，对应 seL4 的 `postCapDeletion`
+pub fn post_cap_deletion(cap: Cap) {
+    // TODO: 中断处理 capability 需要将中断状态设置为 IRQInactive
+    let _ = cap;
+}
+
+/// `cap_b` 指向的对象是否位于 `cap_a` 指向的区域内，对应 seL4 的 `sameRegionAs`
+pub fn same_region_as(cap_a: &Cap, cap_b: &Cap) -> bool {
+    match (cap_a.view(), cap_b.view()) {
+        (CapView::UntypedCap(a), _) if cap_b.is_physical() => {
+            let (a_base, b_base) = (a.get_cap_ptr(), cap_b.ptr());
+            let a_top = a_base + (bit!(a.get_cap_block_size()) - 1);
+            let b_top = b_base + (bit!(cap_b.size_bits()) - 1);
+            a_base <= b_base && b_top <= a_top && b_base <= b_top
+        }
+        (CapView::EndpointCap(a), CapView::EndpointCap(b)) => {
+            a.get_cap_ep_ptr() == b.get_cap_ep_ptr()
+        }
+        (CapView::NotificationCap(a), CapView::NotificationCap(b)) => {
+            a.get_cap_ntfn_ptr() == b.get_cap_ntfn_ptr()
+        }
+        (CapView::CnodeCap(a), CapView::CnodeCap(b)) => {
+            a.get_cap_c_node_ptr() == b.get_cap_c_node_ptr()
+                && a.get_cap_c_node_radix() == b.get_cap_c_node_radix()
+        }
+        (CapView::ThreadCap(a), CapView::ThreadCap(b)) => {
+            a.get_cap_tcb_ptr() == b.get_cap_tcb_ptr()
+        }
+        (CapView::ReplyCap(a), CapView::ReplyCap(b)) => a.get_cap_tcb_ptr() == b.get_cap_tcb_ptr(),
+        (CapView::DomainCap(_), CapView::DomainCap(_)) => true,
+        (CapView::IrqControlCap(_), CapView::IrqControlCap(_) | CapView::IrqHandlerCap(_)) => true,
+        (CapView::IrqHandlerCap(a), CapView::IrqHandlerCap(b)) => {
+            a.get_cap_irq() == b.get_cap_irq()
+        }
+        _ if is_arch_cap(cap_a) && is_arch_cap(cap_b) => arch_same_region_as(cap_a, cap_b),
+        _ => false,
+    }
+}
+
+/// 两个 capability 是否指向同一个对象，对应 seL4 的 `sameObjectAs`
+pub fn same_object_as(cap_a: &Cap, cap_b: &Cap) -> bool {
+    match (cap_a.view(), cap_b.view()) {
+        (CapView::UntypedCap(_), _) => false,
+        (CapView::IrqControlCap(_), CapView::IrqHandlerCap(_)) => false,
+        _ if is_arch_cap(cap_a) && is_arch_cap(cap_b) => arch_same_object_as(cap_a, cap_b),
+        _ => same_region_as(cap_a, cap_b),
+    }
+}
+
+/// 从 `src_cap` 派生的 `derived_cap` 是否可以撤销其子节点，对应 seL4 的 `isCapRevocable`
+///
+/// 设置了新 badge 的 endpoint 和 notification 作为同一 badge 的 capability 的父节点
+pub fn is_cap_revocable(derived_cap: &Cap, src_cap: &Cap) -> bool {
+    if is_arch_cap(derived_cap) {
+        return arch_is_cap_revocable(derived_cap, src_cap);
+    }
+
+    match (derived_cap.view(), src_cap.view()) {
+        (CapView::EndpointCap(derived), CapView::EndpointCap(src)) => {
+            derived.get_cap_ep_badge() != src.get_cap_ep_badge()
+        }
+        (CapView::NotificationCap(derived), CapView::NotificationCap(src)) => {
+            derived.get_cap_ntfn_badge() != src.get_cap_ntfn_badge()
+        }
+        (CapView::IrqHandlerCap(_), CapView::IrqControlCap(_)) => true,
+        (CapView::UntypedCap(_), _) => true,
+        _ => false,
+    }
+}
+
+/// 使用 `data` 更新 capability 中的 badge 或 guard，对应 seL4 的 `updateCapData`
+///
+/// `preserve` 为 `true` 时不能修改 badge，无法更新时返回空 capability
+pub fn update_cap_data(preserve: bool, data: usize, cap: Cap) -> Cap {
+    match cap.view() {
+        CapView::EndpointCap(mut ep) => {
+            if !preserve && ep.get_cap_ep_badge() == 0 {
+                ep.set_cap_ep_badge(data);
+                ep.into()
+            } else {
+                Cap::null()
+            }
+        }
+        CapView::NotificationCap(mut ntfn) => {
+            if !preserve && ntfn.get_cap_ntfn_badge() == 0 {
+                ntfn.set_cap_ntfn_badge(data);
+                ntfn.into()
+            } else {
+                Cap::null()
+            }
+        }
+        CapView::CnodeCap(cnode) => update_cnode_guard(data, cnode),
+        // 架构相关的 capability 没有可以更新的数据
+        _ => cap,
+    }
+}
+
+/// `data` 的低 6 位为 guard 的位数，其余为 guard
+fn update_cnode_guard(data: usize, mut cnode: CnodeCap) -> Cap {
+    const GUARD_SIZE_BITS: usize = 6;
+    let guard_size = data & (bit!(GUARD_SIZE_BITS) - 1);
+    let guard = data >> GUARD_SIZE_BITS;
+    if guard_size + cnode.get_cap_c_node_radix() > usize::BITS as usize {
+        return Cap::null();
+    }
+    let guard = if guard_size == 0 {
+        0
+    } else {
+        guard & (usize::MAX >> (usize::BITS as usize - guard_size))
+    };
+    cnode.set_cap_c_node_guard(guard);
+    cnode.set_cap_c_node_guard_size(guard_size);
+    cnode.into()
+}
+
+/// 按照 `rights` 去掉 capability 中的权限，对应 seL4 的 `maskCapRights`
+pub fn mask_cap_rights(rights: CapRights, cap: Cap) -> Cap {
+    if is_arch_cap(&cap) {
+        return arch_mask_cap_rights(rights, cap);
+    }
+
+    match cap.view() {
+        CapView::EndpointCap(mut ep) => {
+            ep.set_rights(ep.rights() & rights);
+            ep.into()
+        }
+        CapView::NotificationCap(mut ntfn) => {
+            ntfn.set_rights(ntfn.rights() & rights);
+            ntfn.into()
+        }
+        CapView::ReplyCap(mut reply) => {
+            reply.set_cap_reply_can_grant(
+                reply.get_cap_reply_can_grant() & rights.contains(CapRights::GRANT) as usize,
+            );
+            reply.into()
+        }
+        _ => cap,
+    }
+}
+
+/// 拥有全部权限的 endpoint capability 才能取消带 badge 的发送，对应 seL4 的 `hasCancelSendRights`
+pub fn has_cancel_send_rights(cap: Cap) -> bool {
+    match cap.view() {
+        CapView::EndpointCap(ep) => ep.rights() == CapRights::all(),
+        _ => false,
+    }
+}
+
+/// 解析 capability 的调用并执行，对应 seL4 的 `decodeInvocation`
+///
+/// 调用通过检查之后将当前线程设置为 `Restart` 状态再执行，被抢占时保持该状态，
+/// 重新执行系统调用时继续完成剩余的部分。`call` 表示通过 Call 发起调用
+///
+/// # Safety
+///
+/// `slot` 和 `extra_caps` 需要指向有效的 [Cte]
+pub unsafe fn decode_invocation(
+    label: InvocationLabel,
+    slot: *mut Cte,
+    args: &[usize],
+    extra_caps: &[*mut Cte],
+    call: bool,
+) -> Result<(), Exception> {
+    let mut caps = [Cap::null(); MSG_MAX_EXTRA_CAPS];
+    for (cap, &slot) in caps.iter_mut().zip(extra_caps) {
+        *cap = unsafe { **slot };
+    }
+    let caps = &caps[..extra_caps.len()];
+
+    match (unsafe { **slot }).view() {
+        CapView::NullCap(_) | CapView::ZombieCap(_) => {
+            Err(SyscallError::InvalidCapability { number: 0 }.into())
+        }
+        CapView::CnodeCap(cap) => decode_cnode_invocation(label, cap, args, caps),
+        CapView::ThreadCap(_) => unsafe {
+            decode_tcb_invocation(label, slot, args, extra_caps, call)
+        },
+        CapView::DomainCap(_) => decode_domain_invocation(label, args, caps),
+        CapView::UntypedCap(_) => unsafe { decode_untyped_invocation(label, slot, args, caps) },
+        // TODO: endpoint、notification、reply、中断和架构相关的 capability 的调用
+        _ => Err(SyscallError::IllegalOperation.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arch::{mask_vm_rights, VmRights},
+        object::structures::{EndpointCap, FrameCap, NotificationCap},
+    };
+
+    #[test]
+    fn mask_endpoint_rights() {
+        let ep = EndpointCap::new(0, true, true, true, true, 0x1000);
+        let masked = mask_cap_rights(CapRights::WRITE | CapRights::GRANT_REPLY, ep.into());
+        let CapView::EndpointCap(masked) = masked.view() else {
+            unreachable!()
+        };
+        assert_eq!(masked.rights(), CapRights::WRITE | CapRights::GRANT_REPLY);
+        assert!(!has_cancel_send_rights(masked.into()));
+        assert!(has_cancel_send_rights(ep.into()));
+
+        // 不能通过 mask 增加权限
+        let ntfn = NotificationCap::new(0, false, true, 0x1000);
+        let CapView::NotificationCap(masked) =
+            mask_cap_rights(CapRights::all(), ntfn.into()).view()
+        else {
+            unreachable!()
+        };
+        assert_eq!(masked.rights(), CapRights::WRITE);
+    }
+
+    #[test]
+    fn mask_frame_rights() {
+        use VmRights::*;
+        assert_eq!(
+            mask_vm_rights(ReadWrite, CapRights::READ | CapRights::WRITE),
+            ReadWrite
+        );
+        assert_eq!(mask_vm_rights(ReadWrite, CapRights::READ), ReadOnly);
+        assert_eq!(mask_vm_rights(ReadWrite, CapRights::WRITE), KernelOnly);
+        assert_eq!(mask_vm_rights(ReadOnly, CapRights::all()), ReadOnly);
+        assert_eq!(mask_vm_rights(KernelOnly, CapRights::all()), KernelOnly);
+
+        let frame = FrameCap::new(1, 0x2000, 0, ReadWrite as usize, false, 0x4000);
+        let CapView::FrameCap(masked) = mask_cap_rights(CapRights::READ, frame.into()).view()
+        else {
+            unreachable!()
+        };
+        assert_eq!(masked.get_cap_fvm_rights(), ReadOnly as usize);
+    }
+}
@@ -0,0 +1,1054 @@
+#[derive(Debug, Clone, Copy)]
+pub struct NullCap([usize; 2]);
+impl NullCap {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_cap_type(&self) -> usize {
+        (self.0[0] & 0xF800000000000000) >> 59
+    }
+
+    pub const fn set_cap_type(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xF800000000000000 | ((value << 59) & 0xF800000000000000)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct UntypedCap([usize; 2]);
+impl UntypedCap {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_cap_ptr(&self) -> usize {
+        let value = self.0[0] & 0xFFFFFFFFFFFF;
+        if value & 0x800000000000 != 0 {
+            value | 0xFFFF000000000000
+        } else {
+            value
+        }
+    }
+
+    pub const fn set_cap_ptr(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xFFFFFFFFFFFF | (value & 0xFFFFFFFFFFFF)
+    }
+
+    pub const fn get_cap_type(&self) -> usize {
+        (self.0[0] & 0xF800000000000000) >> 59
+    }
+
+    pub const fn set_cap_type(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xF800000000000000 | ((value << 59) & 0xF800000000000000)
+    }
+
+    pub const fn get_cap_block_size(&self) -> usize {
+        self.0[1] & 0x3F
+    }
+
+    pub const fn set_cap_block_size(&mut self, value: usize) {
+        self.0[1] = self.0[1] & !0x3F | (value & 0x3F)
+    }
+
+    pub const fn get_cap_is_device(&self) -> usize {
+        (self.0[1] & 0x40) >> 6
+    }
+
+    pub const fn set_cap_is_device(&mut self, value: usize) {
+        self.0[1] = self.0[1] & !0x40 | ((value << 6) & 0x40)
+    }
+
+    pub const fn get_cap_free_index(&self) -> usize {
+        (self.0[1] & 0xFFFFFFFFFFFF0000) >> 16
+    }
+
+    pub const fn set_cap_free_index(&mut self, value: usize) {
+        self.0[1] = self.0[1] & !0xFFFFFFFFFFFF0000 | ((value << 16) & 0xFFFFFFFFFFFF0000)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EndpointCap([usize; 2]);
+impl EndpointCap {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_cap_ep_ptr(&self) -> usize {
+        let value = self.0[0] & 0xFFFFFFFFFFFF;
+        if value & 0x800000000000 != 0 {
+            value | 0xFFFF000000000000
+        } else {
+            value
+        }
+    }
+
+    pub const fn set_cap_ep_ptr(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xFFFFFFFFFFFF | (value & 0xFFFFFFFFFFFF)
+    }
+
+    pub const fn get_cap_can_send(&self) -> usize {
+        (self.0[0] & 0x80000000000000) >> 55
+    }
+
+    pub const fn set_cap_can_send(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x80000000000000 | ((value << 55) & 0x80000000000000)
+    }
+
+    pub const fn get_cap_can_receive(&self) -> usize {
+        (self.0[0] & 0x100000000000000) >> 56
+    }
+
+    pub const fn set_cap_can_receive(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x100000000000000 | ((value << 56) & 0x100000000000000)
+    }
+
+    pub const fn get_cap_can_grant(&self) -> usize {
+        (self.0[0] & 0x200000000000000) >> 57
+    }
+
+    pub const fn set_cap_can_grant(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x200000000000000 | ((value << 57) & 0x200000000000000)
+    }
+
+    pub const fn get_cap_can_grant_reply(&self) -> usize {
+        (self.0[0] & 0x400000000000000) >> 58
+    }
+
+    pub const fn set_cap_can_grant_reply(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x400000000000000 | ((value << 58) & 0x400000000000000)
+    }
+
+    pub const fn get_cap_type(&self) -> usize {
+        (self.0[0] & 0xF800000000000000) >> 59
+    }
+
+    pub const fn set_cap_type(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xF800000000000000 | ((value << 59) & 0xF800000000000000)
+    }
+
+    pub const fn get_cap_ep_badge(&self) -> usize {
+        self.0[1]
+    }
+
+    pub const fn set_cap_ep_badge(&mut self, value: usize) {
+        self.0[1] = value
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NotificationCap([usize; 2]);
+impl NotificationCap {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_cap_ntfn_ptr(&self) -> usize {
+        let value = self.0[0] & 0xFFFFFFFFFFFF;
+        if value & 0x800000000000 != 0 {
+            value | 0xFFFF000000000000
+        } else {
+            value
+        }
+    }
+
+    pub const fn set_cap_ntfn_ptr(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xFFFFFFFFFFFF | (value & 0xFFFFFFFFFFFF)
+    }
+
+    pub const fn get_cap_ntfn_can_send(&self) -> usize {
+        (self.0[0] & 0x200000000000000) >> 57
+    }
+
+    pub const fn set_cap_ntfn_can_send(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x200000000000000 | ((value << 57) & 0x200000000000000)
+    }
+
+    pub const fn get_cap_ntfn_can_receive(&self) -> usize {
+        (self.0[0] & 0x400000000000000) >> 58
+    }
+
+    pub const fn set_cap_ntfn_can_receive(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x400000000000000 | ((value << 58) & 0x400000000000000)
+    }
+
+    pub const fn get_cap_type(&self) -> usize {
+        (self.0[0] & 0xF800000000000000) >> 59
+    }
+
+    pub const fn set_cap_type(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xF800000000000000 | ((value << 59) & 0xF800000000000000)
+    }
+
+    pub const fn get_cap_ntfn_badge(&self) -> usize {
+        self.0[1]
+    }
+
+    pub const fn set_cap_ntfn_badge(&mut self, value: usize) {
+        self.0[1] = value
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReplyCap([usize; 2]);
+impl ReplyCap {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_cap_reply_master(&self) -> usize {
+        self.0[0] & 0x1
+    }
+
+    pub const fn set_cap_reply_master(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x1 | (value & 0x1)
+    }
+
+    pub const fn get_cap_reply_can_grant(&self) -> usize {
+        (self.0[0] & 0x2) >> 1
+    }
+
+    pub const fn set_cap_reply_can_grant(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x2 | ((value << 1) & 0x2)
+    }
+
+    pub const fn get_cap_type(&self) -> usize {
+        (self.0[0] & 0xF800000000000000) >> 59
+    }
+
+    pub const fn set_cap_type(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xF800000000000000 | ((value << 59) & 0xF800000000000000)
+    }
+
+    pub const fn get_cap_tcb_ptr(&self) -> usize {
+        self.0[1]
+    }
+
+    pub const fn set_cap_tcb_ptr(&mut self, value: usize) {
+        self.0[1] = value
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CnodeCap([usize; 2]);
+impl CnodeCap {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_cap_c_node_ptr(&self) -> usize {
+        let value = (self.0[0] & 0x7FFFFFFFFFFF) << 1;
+        if value & 0x800000000000 != 0 {
+            value | 0xFFFF000000000000
+        } else {
+            value
+        }
+    }
+
+    pub const fn set_cap_c_node_ptr(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x7FFFFFFFFFFF | ((value >> 1) & 0x7FFFFFFFFFFF)
+    }
+
+    pub const fn get_cap_c_node_radix(&self) -> usize {
+        (self.0[0] & 0x1F800000000000) >> 47
+    }
+
+    pub const fn set_cap_c_node_radix(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x1F800000000000 | ((value << 47) & 0x1F800000000000)
+    }
+
+    pub const fn get_cap_c_node_guard_size(&self) -> usize {
+        (self.0[0] & 0x7E0000000000000) >> 53
+    }
+
+    pub const fn set_cap_c_node_guard_size(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x7E0000000000000 | ((value << 53) & 0x7E0000000000000)
+    }
+
+    pub const fn get_cap_type(&self) -> usize {
+        (self.0[0] & 0xF800000000000000) >> 59
+    }
+
+    pub const fn set_cap_type(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xF800000000000000 | ((value << 59) & 0xF800000000000000)
+    }
+
+    pub const fn get_cap_c_node_guard(&self) -> usize {
+        self.0[1]
+    }
+
+    pub const fn set_cap_c_node_guard(&mut self, value: usize) {
+        self.0[1] = value
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadCap([usize; 2]);
+impl ThreadCap {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_cap_tcb_ptr(&self) -> usize {
+        let value = self.0[0] & 0xFFFFFFFFFFFF;
+        if value & 0x800000000000 != 0 {
+            value | 0xFFFF000000000000
+        } else {
+            value
+        }
+    }
+
+    pub const fn set_cap_tcb_ptr(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xFFFFFFFFFFFF | (value & 0xFFFFFFFFFFFF)
+    }
+
+    pub const fn get_cap_type(&self) -> usize {
+        (self.0[0] & 0xF800000000000000) >> 59
+    }
+
+    pub const fn set_cap_type(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xF800000000000000 | ((value << 59) & 0xF800000000000000)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IrqControlCap([usize; 2]);
+impl IrqControlCap {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_cap_type(&self) -> usize {
+        (self.0[0] & 0xF800000000000000) >> 59
+    }
+
+    pub const fn set_cap_type(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xF800000000000000 | ((value << 59) & 0xF800000000000000)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IrqHandlerCap([usize; 2]);
+impl IrqHandlerCap {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_cap_type(&self) -> usize {
+        (self.0[0] & 0xF800000000000000) >> 59
+    }
+
+    pub const fn set_cap_type(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xF800000000000000 | ((value << 59) & 0xF800000000000000)
+    }
+
+    pub const fn get_cap_irq(&self) -> usize {
+        self.0[1] & 0xFFF
+    }
+
+    pub const fn set_cap_irq(&mut self, value: usize) {
+        self.0[1] = self.0[1] & !0xFFF | (value & 0xFFF)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ZombieCap([usize; 2]);
+impl ZombieCap {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_cap_zombie_type(&self) -> usize {
+        self.0[0] & 0x7F
+    }
+
+    pub const fn set_cap_zombie_type(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x7F | (value & 0x7F)
+    }
+
+    pub const fn get_cap_type(&self) -> usize {
+        (self.0[0] & 0xF800000000000000) >> 59
+    }
+
+    pub const fn set_cap_type(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xF800000000000000 | ((value << 59) & 0xF800000000000000)
+    }
+
+    pub const fn get_cap_zombie_id(&self) -> usize {
+        self.0[1]
+    }
+
+    pub const fn set_cap_zombie_id(&mut self, value: usize) {
+        self.0[1] = value
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DomainCap([usize; 2]);
+impl DomainCap {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_cap_type(&self) -> usize {
+        (self.0[0] & 0xF800000000000000) >> 59
+    }
+
+    pub const fn set_cap_type(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xF800000000000000 | ((value << 59) & 0xF800000000000000)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Endpoint([usize; 2]);
+impl Endpoint {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_state(&self) -> usize {
+        self.0[0] & 0x3
+    }
+
+    pub const fn set_state(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x3 | (value & 0x3)
+    }
+
+    pub const fn get_ep_queue_tail(&self) -> usize {
+        let value = self.0[0] & 0xFFFFFFFFFFFC;
+        if value & 0x800000000000 != 0 {
+            value | 0xFFFF000000000000
+        } else {
+            value
+        }
+    }
+
+    pub const fn set_ep_queue_tail(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xFFFFFFFFFFFC | (value & 0xFFFFFFFFFFFC)
+    }
+
+    pub const fn get_ep_queue_head(&self) -> usize {
+        self.0[1]
+    }
+
+    pub const fn set_ep_queue_head(&mut self, value: usize) {
+        self.0[1] = value
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Notification([usize; 4]);
+impl Notification {
+    pub const fn empty() -> Self {
+        Self([0; 4])
+    }
+
+    pub const fn from_raw(raw: [usize; 4]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 4] {
+        self.0
+    }
+
+    pub const fn get_state(&self) -> usize {
+        self.0[0] & 0x3
+    }
+
+    pub const fn set_state(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x3 | (value & 0x3)
+    }
+
+    pub const fn get_ntfn_queue_tail(&self) -> usize {
+        let value = (self.0[0] & 0xFFFFFFFFFFFF0000) >> 16;
+        if value & 0x800000000000 != 0 {
+            value | 0xFFFF000000000000
+        } else {
+            value
+        }
+    }
+
+    pub const fn set_ntfn_queue_tail(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xFFFFFFFFFFFF0000 | ((value << 16) & 0xFFFFFFFFFFFF0000)
+    }
+
+    pub const fn get_ntfn_queue_head(&self) -> usize {
+        let value = self.0[1] & 0xFFFFFFFFFFFF;
+        if value & 0x800000000000 != 0 {
+            value | 0xFFFF000000000000
+        } else {
+            value
+        }
+    }
+
+    pub const fn set_ntfn_queue_head(&mut self, value: usize) {
+        self.0[1] = self.0[1] & !0xFFFFFFFFFFFF | (value & 0xFFFFFFFFFFFF)
+    }
+
+    pub const fn get_ntfn_msg_identifier(&self) -> usize {
+        self.0[2]
+    }
+
+    pub const fn set_ntfn_msg_identifier(&mut self, value: usize) {
+        self.0[2] = value
+    }
+
+    pub const fn get_ntfn_bound_tcb(&self) -> usize {
+        let value = self.0[3] & 0xFFFFFFFFFFFF;
+        if value & 0x800000000000 != 0 {
+            value | 0xFFFF000000000000
+        } else {
+            value
+        }
+    }
+
+    pub const fn set_ntfn_bound_tcb(&mut self, value: usize) {
+        self.0[3] = self.0[3] & !0xFFFFFFFFFFFF | (value & 0xFFFFFFFFFFFF)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FrameCap([usize; 2]);
+impl FrameCap {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_cap_f_is_device(&self) -> usize {
+        (self.0[0] & 0x40) >> 6
+    }
+
+    pub const fn set_cap_f_is_device(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x40 | ((value << 6) & 0x40)
+    }
+
+    pub const fn get_cap_fvm_rights(&self) -> usize {
+        (self.0[0] & 0x180) >> 7
+    }
+
+    pub const fn set_cap_fvm_rights(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x180 | ((value << 7) & 0x180)
+    }
+
+    pub const fn get_cap_f_mapped_address(&self) -> usize {
+        let value = (self.0[0] & 0x1FFFFFFFFFFFE00) >> 9;
+        if value & 0x800000000000 != 0 {
+            value | 0xFFFF000000000000
+        } else {
+            value
+        }
+    }
+
+    pub const fn set_cap_f_mapped_address(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x1FFFFFFFFFFFE00 | ((value << 9) & 0x1FFFFFFFFFFFE00)
+    }
+
+    pub const fn get_cap_f_size(&self) -> usize {
+        (self.0[0] & 0x600000000000000) >> 57
+    }
+
+    pub const fn set_cap_f_size(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x600000000000000 | ((value << 57) & 0x600000000000000)
+    }
+
+    pub const fn get_cap_type(&self) -> usize {
+        (self.0[0] & 0xF800000000000000) >> 59
+    }
+
+    pub const fn set_cap_type(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xF800000000000000 | ((value << 59) & 0xF800000000000000)
+    }
+
+    pub const fn get_cap_f_base_ptr(&self) -> usize {
+        let value = self.0[1] & 0xFFFFFFFFFFFF;
+        if value & 0x800000000000 != 0 {
+            value | 0xFFFF000000000000
+        } else {
+            value
+        }
+    }
+
+    pub const fn set_cap_f_base_ptr(&mut self, value: usize) {
+        self.0[1] = self.0[1] & !0xFFFFFFFFFFFF | (value & 0xFFFFFFFFFFFF)
+    }
+
+    pub const fn get_cap_f_mapped_asid(&self) -> usize {
+        (self.0[1] & 0xFFFF000000000000) >> 48
+    }
+
+    pub const fn set_cap_f_mapped_asid(&mut self, value: usize) {
+        self.0[1] = self.0[1] & !0xFFFF000000000000 | ((value << 48) & 0xFFFF000000000000)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PageTableCap([usize; 2]);
+impl PageTableCap {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_cap_pt_mapped_address(&self) -> usize {
+        let value = self.0[0] & 0xFFFFFFF00000;
+        if value & 0x800000000000 != 0 {
+            value | 0xFFFF000000000000
+        } else {
+            value
+        }
+    }
+
+    pub const fn set_cap_pt_mapped_address(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xFFFFFFF00000 | (value & 0xFFFFFFF00000)
+    }
+
+    pub const fn get_cap_pt_is_mapped(&self) -> usize {
+        (self.0[0] & 0x1000000000000) >> 48
+    }
+
+    pub const fn set_cap_pt_is_mapped(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x1000000000000 | ((value << 48) & 0x1000000000000)
+    }
+
+    pub const fn get_cap_type(&self) -> usize {
+        (self.0[0] & 0xF800000000000000) >> 59
+    }
+
+    pub const fn set_cap_type(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xF800000000000000 | ((value << 59) & 0xF800000000000000)
+    }
+
+    pub const fn get_cap_pt_base_ptr(&self) -> usize {
+        let value = self.0[1] & 0xFFFFFFFFFFFF;
+        if value & 0x800000000000 != 0 {
+            value | 0xFFFF000000000000
+        } else {
+            value
+        }
+    }
+
+    pub const fn set_cap_pt_base_ptr(&mut self, value: usize) {
+        self.0[1] = self.0[1] & !0xFFFFFFFFFFFF | (value & 0xFFFFFFFFFFFF)
+    }
+
+    pub const fn get_cap_pt_mapped_asid(&self) -> usize {
+        (self.0[1] & 0xFFFF000000000000) >> 48
+    }
+
+    pub const fn set_cap_pt_mapped_asid(&mut self, value: usize) {
+        self.0[1] = self.0[1] & !0xFFFF000000000000 | ((value << 48) & 0xFFFF000000000000)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct VspaceCap([usize; 2]);
+impl VspaceCap {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_cap_vs_is_mapped(&self) -> usize {
+        (self.0[0] & 0x400000000000000) >> 58
+    }
+
+    pub const fn set_cap_vs_is_mapped(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x400000000000000 | ((value << 58) & 0x400000000000000)
+    }
+
+    pub const fn get_cap_type(&self) -> usize {
+        (self.0[0] & 0xF800000000000000) >> 59
+    }
+
+    pub const fn set_cap_type(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xF800000000000000 | ((value << 59) & 0xF800000000000000)
+    }
+
+    pub const fn get_cap_vs_base_ptr(&self) -> usize {
+        let value = self.0[1] & 0xFFFFFFFFFFFF;
+        if value & 0x800000000000 != 0 {
+            value | 0xFFFF000000000000
+        } else {
+            value
+        }
+    }
+
+    pub const fn set_cap_vs_base_ptr(&mut self, value: usize) {
+        self.0[1] = self.0[1] & !0xFFFFFFFFFFFF | (value & 0xFFFFFFFFFFFF)
+    }
+
+    pub const fn get_cap_vs_mapped_asid(&self) -> usize {
+        (self.0[1] & 0xFFFF000000000000) >> 48
+    }
+
+    pub const fn set_cap_vs_mapped_asid(&mut self, value: usize) {
+        self.0[1] = self.0[1] & !0xFFFF000000000000 | ((value << 48) & 0xFFFF000000000000)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AsidControlCap([usize; 2]);
+impl AsidControlCap {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_cap_type(&self) -> usize {
+        (self.0[0] & 0xF800000000000000) >> 59
+    }
+
+    pub const fn set_cap_type(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xF800000000000000 | ((value << 59) & 0xF800000000000000)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AsidPoolCap([usize; 2]);
+impl AsidPoolCap {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_cap_asid_pool(&self) -> usize {
+        let value = (self.0[0] & 0x1FFFFFFFFF) << 11;
+        if value & 0x800000000000 != 0 {
+            value | 0xFFFF000000000000
+        } else {
+            value
+        }
+    }
+
+    pub const fn set_cap_asid_pool(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x1FFFFFFFFF | ((value >> 11) & 0x1FFFFFFFFF)
+    }
+
+    pub const fn get_cap_asid_base(&self) -> usize {
+        (self.0[0] & 0x7FFF80000000000) >> 43
+    }
+
+    pub const fn set_cap_asid_base(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0x7FFF80000000000 | ((value << 43) & 0x7FFF80000000000)
+    }
+
+    pub const fn get_cap_type(&self) -> usize {
+        (self.0[0] & 0xF800000000000000) >> 59
+    }
+
+    pub const fn set_cap_type(&mut self, value: usize) {
+        self.0[0] = self.0[0] & !0xF800000000000000 | ((value << 59) & 0xF800000000000000)
+    }
+}
+
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapType {
+    NullCap = 0,
+    UntypedCap = 2,
+    EndpointCap = 4,
+    NotificationCap = 6,
+    ReplyCap = 8,
+    CnodeCap = 10,
+    ThreadCap = 12,
+    IrqControlCap = 14,
+    IrqHandlerCap = 16,
+    ZombieCap = 18,
+    DomainCap = 20,
+    FrameCap = 1,
+    PageTableCap = 3,
+    VspaceCap = 9,
+    AsidControlCap = 11,
+    AsidPoolCap = 13,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Cap([usize; 2]);
+impl Cap {
+    pub const fn empty() -> Self {
+        Self([0; 2])
+    }
+
+    pub const fn from_raw(raw: [usize; 2]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; 2] {
+        self.0
+    }
+
+    pub const fn get_cap_type(&self) -> usize {
+        (self.0[0] & 0xF800000000000000) >> 59
+    }
+
+    pub const fn view(&self) -> CapView {
+        match self.get_cap_type() {
+            0 => CapView::NullCap(NullCap::from_raw(self.0)),
+            2 => CapView::UntypedCap(UntypedCap::from_raw(self.0)),
+            4 => CapView::EndpointCap(EndpointCap::from_raw(self.0)),
+            6 => CapView::NotificationCap(NotificationCap::from_raw(self.0)),
+            8 => CapView::ReplyCap(ReplyCap::from_raw(self.0)),
+            10 => CapView::CnodeCap(CnodeCap::from_raw(self.0)),
+            12 => CapView::ThreadCap(ThreadCap::from_raw(self.0)),
+            14 => CapView::IrqControlCap(IrqControlCap::from_raw(self.0)),
+            16 => CapView::IrqHandlerCap(IrqHandlerCap::from_raw(self.0)),
+            18 => CapView::ZombieCap(ZombieCap::from_raw(self.0)),
+            20 => CapView::DomainCap(DomainCap::from_raw(self.0)),
+            1 => CapView::FrameCap(FrameCap::from_raw(self.0)),
+            3 => CapView::PageTableCap(PageTableCap::from_raw(self.0)),
+            9 => CapView::VspaceCap(VspaceCap::from_raw(self.0)),
+            11 => CapView::AsidControlCap(AsidControlCap::from_raw(self.0)),
+            13 => CapView::AsidPoolCap(AsidPoolCap::from_raw(self.0)),
+            _ => panic!("invalid cap_type"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum CapView {
+    NullCap(NullCap),
+    UntypedCap(UntypedCap),
+    EndpointCap(EndpointCap),
+    NotificationCap(NotificationCap),
+    ReplyCap(ReplyCap),
+    CnodeCap(CnodeCap),
+    ThreadCap(ThreadCap),
+    IrqControlCap(IrqControlCap),
+    IrqHandlerCap(IrqHandlerCap),
+    ZombieCap(ZombieCap),
+    DomainCap(DomainCap),
+    FrameCap(FrameCap),
+    PageTableCap(PageTableCap),
+    VspaceCap(VspaceCap),
+    AsidControlCap(AsidControlCap),
+    AsidPoolCap(AsidPoolCap),
+}
+
+impl From<NullCap> for Cap {
+    fn from(value: NullCap) -> Self {
+        Self(value.raw())
+    }
+}
+
+impl From<UntypedCap> for Cap {
+    fn from(value: UntypedCap) -> Self {
+        Self(value.raw())
+    }
+}
+
+impl From<EndpointCap> for Cap {
+    fn from(value: EndpointCap) -> Self {
+        Self(value.raw())
+    }
+}
+
+impl From<NotificationCap> for Cap {
+    fn from(value: NotificationCap) -> Self {
+        Self(value.raw())
+    }
+}
+
+impl From<ReplyCap> for Cap {
+    fn from(value: ReplyCap) -> Self {
+        Self(value.raw())
+    }
+}
+
+impl From<CnodeCap> for Cap {
+    fn from(value: CnodeCap) -> Self {
+        Self(value.raw())
+    }
+}
+
+impl From<ThreadCap> for Cap {
+    fn from(value: ThreadCap) -> Self {
+        Self(value.raw())
+    }
+}
+
+impl From<IrqControlCap> for Cap {
+    fn from(value: IrqControlCap) -> Self {
+        Self(value.raw())
+    }
+}
+
+impl From<IrqHandlerCap> for Cap {
+    fn from(value: IrqHandlerCap) -> Self {
+        Self(value.raw())
+    }
+}
+
+impl From<ZombieCap> for Cap {
+    fn from(value: ZombieCap) -> Self {
+        Self(value.raw())
+    }
+}
+
+impl From<DomainCap> for Cap {
+    fn from(value: DomainCap) -> Self {
+        Self(value.raw())
+    }
+}
+
+impl From<FrameCap> for Cap {
+    fn from(value: FrameCap) -> Self {
+        Self(value.raw())
+    }
+}
+
+impl From<PageTableCap> for Cap {
+    fn from(value: PageTableCap) -> Self {
+        Self(value.raw())
+    }
+}
+
+impl From<VspaceCap> for Cap {
+    fn from(value: VspaceCap) -> Self {
+        Self(value.raw())
+    }
+}
+
+impl From<AsidControlCap> for Cap {
+    fn from(value: AsidControlCap) -> Self {
+        Self(value.raw())
+    }
+}
+
+impl From<AsidPoolCap> for Cap {
+    fn from(value: AsidPoolCap) -> Self {
+        Self(value.raw())
+    }
+}
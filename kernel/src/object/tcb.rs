@@ -1,12 +1,67 @@
-use crate::arch::{ArchTCB, VirtAddr};
+use core::ptr::null_mut;
+
+use sel4_types::{invocation::InvocationLabel, message::MessageInfo};
+
+use crate::{
+    api::failures::{Exception, SyscallError},
+    arch::{
+        check_valid_ipc_buffer, is_valid_vtable_root, lookup_ipc_buffer, sanitise_register,
+        ArchTCB, UserContext, VirtAddr, BADGE_REGISTER, FAULT_IP, FRAME_REGISTERS, GP_REGISTERS,
+        MSG_INFO_REGISTER, MSG_REGISTERS, NEXT_IP, TCB_BITS, TLS_BASE,
+    },
+    config::{NUM_DOMAINS, NUM_PRIORITIES, TIME_SLICE},
+    kernel::thread::{
+        cur_thread, is_cur_thread, reschedule_required, restart, set_domain, set_mcp, set_priority,
+        set_thread_state, suspend,
+    },
+    model::statedata::node_state_on_core,
+};
 
 use super::{
-    fault::{Fault, LookupFault, ThreadState},
-    structures::Notification,
+    cap::{slot_ptr, Cap, CapView, Cte, SLOT_BITS, TCB_CNODE_RADIX},
+    cnode::{cte_delete, cte_insert, slot_cap_long_running_delete},
+    fault::{Fault, LookupFault, ThreadState, ThreadStateType},
+    ipc::{bind_notification, unbind_notification},
+    objecttype::{derive_cap, same_object_as, update_cap_data},
+    structures::{Notification, ThreadCap},
 };
 
+/// TCB 对象前半部分中 CTE 的数量，对应 seL4 的 `tcbCNodeEntries`
+pub const TCB_CNODE_ENTRIES: usize = 5;
+
+/// TCB 对象中 TCB 结构的偏移，前半部分为 TCB 的 CTE，对应 seL4 的 `TCB_OFFSET`
+pub const TCB_OFFSET: usize = bit!(TCB_BITS - 1);
+
+/// TCB 对象中的第 `index` 个 CTE，对应 seL4 的 `TCB_PTR_CTE_PTR`
+///
+/// `tcb_ptr` 为 thread capability 中保存的地址，CTE 位于 TCB 对象的起始处
+pub const fn tcb_cte_ptr(tcb_ptr: usize, index: usize) -> *mut Cte {
+    slot_ptr(tcb_ptr & !(bit!(TCB_BITS) - 1), index)
+}
+
+/// TCB 对象中各个 CTE 的编号，对应 seL4 的 `tcb_cnode_index`
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcbCnodeIndex {
+    /// CSpace root
+    CTable = 0,
+    /// VSpace root
+    VTable,
+    /// Reply cap slot
+    Reply,
+    /// TCB of most recent IPC sender
+    Caller,
+    /// IPC buffer cap slot
+    Buffer,
+}
+
+/// 调试用的线程名称的最大长度，名称保存在 TCB 对象前半部分 CTE 之后，对应 seL4 的 `debug_tcb_t`
+pub const TCB_NAME_LENGTH: usize = TCB_OFFSET - TCB_CNODE_ENTRIES * size_of::<Cte>();
+
 /* TCB: size >= 18 words + sizeof(arch_tcb_t) + 1 word on MCS (aligned to nearest power of 2) */
-struct TCB {
+#[repr(C)]
+#[allow(clippy::upper_case_acronyms)]
+pub struct TCB {
     /* arch specific tcb state (including context)*/
     arch: ArchTCB,
 
@@ -41,15 +96,1581 @@ struct TCB {
 
     /// userland virtual address of thread IPC buffer, 1 word
     ipc_buffer: VirtAddr,
-    // #ifdef ENABLE_SMP_SUPPORT
-    //     /* cpu ID this thread is running on, 1 word */
-    //     word_t tcbAffinity;
-    // #endif /* ENABLE_SMP_SUPPORT */
-
-    // /* Previous and next pointers for scheduler queues , 2 words */
-    // struct tcb *tcbSchedNext;
-    // struct tcb *tcbSchedPrev;
-    // /* Previous and next pointers for endpoint and notification queues, 2 words */
-    // struct tcb *tcbEPNext;
-    // struct tcb *tcbEPPrev;
+
+    /// cpu ID this thread is running on, 1 word
+    affinity: usize,
+
+    /// Previous and next pointers for scheduler queues , 2 words
+    sched_next: *mut TCB,
+    sched_prev: *mut TCB,
+
+    /// Previous and next pointers for endpoint and notification queues, 2 words
+    ep_next: *mut TCB,
+    ep_prev: *mut TCB,
+}
+
+#[allow(clippy::new_without_default)]
+impl TCB {
+    /// 新创建的线程，处于 Inactive 状态，优先级和 domain 为 0，时间片为 [TIME_SLICE]
+    pub const fn new() -> Self {
+        Self {
+            arch: ArchTCB::new(),
+            state: ThreadState::new(),
+            bound_notification: null_mut(),
+            fault: Fault::NullFault,
+            lookup_failure: LookupFault::InvalidRoot {},
+            domain: 0,
+            mcp: 0,
+            priority: 0,
+            time_slice: TIME_SLICE,
+            fault_handler: 0,
+            ipc_buffer: va!(0),
+            affinity: 0,
+            sched_next: null_mut(),
+            sched_prev: null_mut(),
+            ep_next: null_mut(),
+            ep_prev: null_mut(),
+        }
+    }
+
+    /// 获取 TCB，`ptr` 为 thread capability 中保存的地址
+    ///
+    /// # Safety
+    ///
+    /// `ptr` 需要指向一个有效的 TCB 对象。返回的引用在使用期间不能与同一个 TCB 的其他引用重叠，
+    /// 例如当前线程、就绪队列或 endpoint 队列中的指针指向同一个 TCB 时，不能同时通过它们创建引用
+    pub unsafe fn from_ptr(ptr: usize) -> &'static mut Self {
+        unsafe { &mut *(ptr as *mut Self) }
+    }
+
+    /// thread capability 指向的 TCB，对应 seL4 的 `TCB_PTR(cap_thread_cap_get_capTCBPtr(cap))`
+    ///
+    /// # Safety
+    ///
+    /// 同 [TCB::from_ptr]，`cap` 需要指向一个有效的 TCB，例如不能同时通过当前线程和
+    /// 指向当前线程的 capability 访问
+    pub unsafe fn from_cap(cap: &ThreadCap) -> &'static mut Self {
+        unsafe { Self::from_ptr(cap.get_cap_tcb_ptr()) }
+    }
+
+    /// TCB 结构的地址，与 thread capability 中保存的地址相同
+    pub fn ptr(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// 线程的第 `index` 个 CTE，对应 seL4 的 `TCB_PTR_CTE_PTR`
+    pub fn cte_ptr(&self, index: TcbCnodeIndex) -> *mut Cte {
+        tcb_cte_ptr(self.ptr(), index as usize)
+    }
+
+    /// 线程的第 `index` 个 CTE
+    pub fn cte(&mut self, index: TcbCnodeIndex) -> &mut Cte {
+        unsafe { &mut *self.cte_ptr(index) }
+    }
+
+    /// 调试用的名称缓冲区，位于 CTE 之后，以 0 结尾
+    fn name_buffer(&mut self) -> &mut [u8; TCB_NAME_LENGTH] {
+        let ptr = tcb_cte_ptr(self.ptr(), TCB_CNODE_ENTRIES) as *mut [u8; TCB_NAME_LENGTH];
+        unsafe { &mut *ptr }
+    }
+
+    /// 线程的名称，未设置时为空
+    pub fn name(&mut self) -> &str {
+        let buffer = self.name_buffer();
+        let len = buffer
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(TCB_NAME_LENGTH);
+        core::str::from_utf8(&buffer[..len]).unwrap_or_default()
+    }
+
+    /// 设置线程的名称，超过 [TCB_NAME_LENGTH] - 1 的部分按字符边界截断，对应 seL4 的 `setThreadName`
+    pub fn set_name(&mut self, name: &str) {
+        let mut len = name.len().min(TCB_NAME_LENGTH - 1);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        let buffer = self.name_buffer();
+        buffer[..len].copy_from_slice(&name.as_bytes()[..len]);
+        buffer[len] = 0;
+    }
+
+    /// 线程的用户态寄存器
+    pub fn context(&mut self) -> &mut UserContext {
+        &mut self.arch.context
+    }
+
+    pub fn state(&self) -> &ThreadState {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut ThreadState {
+        &mut self.state
+    }
+
+    pub fn bound_notification(&self) -> *mut Notification {
+        self.bound_notification
+    }
+
+    pub fn set_bound_notification(&mut self, ntfn: *mut Notification) {
+        self.bound_notification = ntfn;
+    }
+
+    pub fn fault(&self) -> Fault {
+        self.fault
+    }
+
+    pub fn set_fault(&mut self, fault: Fault) {
+        self.fault = fault;
+    }
+
+    pub fn lookup_failure(&self) -> LookupFault {
+        self.lookup_failure
+    }
+
+    pub fn set_lookup_failure(&mut self, lookup_failure: LookupFault) {
+        self.lookup_failure = lookup_failure;
+    }
+
+    pub fn domain(&self) -> usize {
+        self.domain
+    }
+
+    pub fn set_domain(&mut self, domain: usize) {
+        self.domain = domain;
+    }
+
+    /// 线程可以为其他线程设置的最大优先级
+    pub fn mcp(&self) -> usize {
+        self.mcp
+    }
+
+    pub fn set_mcp(&mut self, mcp: usize) {
+        self.mcp = mcp;
+    }
+
+    pub fn priority(&self) -> usize {
+        self.priority
+    }
+
+    pub fn set_priority(&mut self, priority: usize) {
+        self.priority = priority;
+    }
+
+    /// 剩余的时间片
+    pub fn time_slice(&self) -> usize {
+        self.time_slice
+    }
+
+    pub fn set_time_slice(&mut self, time_slice: usize) {
+        self.time_slice = time_slice;
+    }
+
+    /// 线程 CSpace 中 fault handler 的 capability 地址
+    pub fn fault_handler(&self) -> usize {
+        self.fault_handler
+    }
+
+    pub fn set_fault_handler(&mut self, fault_handler: usize) {
+        self.fault_handler = fault_handler;
+    }
+
+    pub fn ipc_buffer(&self) -> VirtAddr {
+        self.ipc_buffer
+    }
+
+    pub fn set_ipc_buffer(&mut self, ipc_buffer: VirtAddr) {
+        self.ipc_buffer = ipc_buffer;
+    }
+
+    /// 线程所在的核
+    pub fn affinity(&self) -> usize {
+        self.affinity
+    }
+
+    pub fn set_affinity(&mut self, affinity: usize) {
+        self.affinity = affinity;
+    }
+
+    pub fn sched_next(&self) -> *mut TCB {
+        self.sched_next
+    }
+
+    pub fn set_sched_next(&mut self, next: *mut TCB) {
+        self.sched_next = next;
+    }
+
+    pub fn sched_prev(&self) -> *mut TCB {
+        self.sched_prev
+    }
+
+    pub fn set_sched_prev(&mut self, prev: *mut TCB) {
+        self.sched_prev = prev;
+    }
+
+    pub fn ep_next(&self) -> *mut TCB {
+        self.ep_next
+    }
+
+    pub fn set_ep_next(&mut self, next: *mut TCB) {
+        self.ep_next = next;
+    }
+
+    pub fn ep_prev(&self) -> *mut TCB {
+        self.ep_prev
+    }
+
+    pub fn set_ep_prev(&mut self, prev: *mut TCB) {
+        self.ep_prev = prev;
+    }
+}
+
+/// 线程组成的双向链表，对应 seL4 的 `tcb_queue_t`
+#[derive(Clone, Copy)]
+pub struct TcbQueue {
+    pub head: *mut TCB,
+    pub end: *mut TCB,
+}
+
+#[allow(clippy::new_without_default)]
+impl TcbQueue {
+    pub const fn new() -> Self {
+        Self {
+            head: null_mut(),
+            end: null_mut(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end.is_null()
+    }
+}
+
+/// 一个 word 中的位数为 2^WORD_RADIX，对应 seL4 的 `wordRadix`
+const WORD_RADIX: usize = usize::BITS.trailing_zeros() as usize;
+/// 每个 domain 的 L2 位图中 word 的数量，对应 seL4 的 `L2_BITMAP_SIZE`
+const L2_BITMAP_SIZE: usize = NUM_PRIORITIES.div_ceil(usize::BITS as usize);
+
+/// 每个 domain 中每个优先级的就绪队列，通过两级位图在 O(1) 时间内找到最高的非空优先级，
+/// 对应 seL4 的 `ksReadyQueues`、`ksReadyQueuesL1Bitmap` 和 `ksReadyQueuesL2Bitmap`
+///
+/// L1 位图的第 i 位表示 L2 位图中对应的 word 不为 0。L2 位图按照高优先级在前的顺序存放，
+/// 查找最高优先级时访问的 word 位于同一缓存行
+pub struct ReadyQueues {
+    queues: [[TcbQueue; NUM_PRIORITIES]; NUM_DOMAINS],
+    l1_bitmap: [usize; NUM_DOMAINS],
+    l2_bitmap: [[usize; L2_BITMAP_SIZE]; NUM_DOMAINS],
+}
+
+/// 对应 seL4 的 `invert_l1index`
+const fn invert_l1index(l1index: usize) -> usize {
+    L2_BITMAP_SIZE - 1 - l1index
+}
+
+#[allow(clippy::new_without_default)]
+impl ReadyQueues {
+    pub const fn new() -> Self {
+        Self {
+            queues: [[TcbQueue::new(); NUM_PRIORITIES]; NUM_DOMAINS],
+            l1_bitmap: [0; NUM_DOMAINS],
+            l2_bitmap: [[0; L2_BITMAP_SIZE]; NUM_DOMAINS],
+        }
+    }
+
+    /// 对应 seL4 的 `addToBitmap`
+    fn add_to_bitmap(&mut self, dom: usize, prio: usize) {
+        let l1index = prio >> WORD_RADIX;
+        self.l1_bitmap[dom] |= bit!(l1index);
+        self.l2_bitmap[dom][invert_l1index(l1index)] |= bit!(prio & (bit!(WORD_RADIX) - 1));
+    }
+
+    /// 对应 seL4 的 `removeFromBitmap`
+    fn remove_from_bitmap(&mut self, dom: usize, prio: usize) {
+        let l1index = prio >> WORD_RADIX;
+        let l2 = &mut self.l2_bitmap[dom][invert_l1index(l1index)];
+        *l2 &= !bit!(prio & (bit!(WORD_RADIX) - 1));
+        if *l2 == 0 {
+            self.l1_bitmap[dom] &= !bit!(l1index);
+        }
+    }
+
+    /// `dom` 中没有就绪的线程
+    pub fn is_empty(&self, dom: usize) -> bool {
+        self.l1_bitmap[dom] == 0
+    }
+
+    /// `dom` 中就绪线程的最高优先级，`dom` 不能为空，对应 seL4 的 `getHighestPrio`
+    pub fn highest_prio(&self, dom: usize) -> usize {
+        let last_bit = |word: usize| usize::BITS as usize - 1 - word.leading_zeros() as usize;
+        let l1index = last_bit(self.l1_bitmap[dom]);
+        let l2index = last_bit(self.l2_bitmap[dom][invert_l1index(l1index)]);
+        (l1index << WORD_RADIX) | l2index
+    }
+
+    /// `dom` 中没有比 `prio` 更高优先级的就绪线程，对应 seL4 的 `isHighestPrio`
+    pub fn is_highest_prio(&self, dom: usize, prio: usize) -> bool {
+        self.is_empty(dom) || prio >= self.highest_prio(dom)
+    }
+
+    /// `dom` 中优先级为 `prio` 的队列的第一个线程
+    pub fn head(&self, dom: usize, prio: usize) -> *mut TCB {
+        self.queues[dom][prio].head
+    }
+
+    /// 将线程加入对应队列的头部，已经在队列中时不做处理，对应 seL4 的 `tcbSchedEnqueue`
+    pub fn enqueue(&mut self, tcb: &mut TCB) {
+        if tcb.state.queued() {
+            return;
+        }
+        let (dom, prio) = (tcb.domain, tcb.priority);
+        let ptr: *mut TCB = tcb;
+        let mut queue = self.queues[dom][prio];
+        if queue.is_empty() {
+            queue.end = ptr;
+            self.add_to_bitmap(dom, prio);
+        } else {
+            unsafe { (*queue.head).sched_prev = ptr };
+        }
+        tcb.sched_prev = null_mut();
+        tcb.sched_next = queue.head;
+        queue.head = ptr;
+        self.queues[dom][prio] = queue;
+        tcb.state.set_queued(true);
+    }
+
+    /// 将线程加入对应队列的尾部，已经在队列中时不做处理，对应 seL4 的 `tcbSchedAppend`
+    pub fn append(&mut self, tcb: &mut TCB) {
+        if tcb.state.queued() {
+            return;
+        }
+        let (dom, prio) = (tcb.domain, tcb.priority);
+        let ptr: *mut TCB = tcb;
+        let mut queue = self.queues[dom][prio];
+        if queue.is_empty() {
+            queue.head = ptr;
+            self.add_to_bitmap(dom, prio);
+        } else {
+            unsafe { (*queue.end).sched_next = ptr };
+        }
+        tcb.sched_prev = queue.end;
+        tcb.sched_next = null_mut();
+        queue.end = ptr;
+        self.queues[dom][prio] = queue;
+        tcb.state.set_queued(true);
+    }
+
+    /// 将线程移出所在的队列，不在队列中时不做处理，对应 seL4 的 `tcbSchedDequeue`
+    pub fn dequeue(&mut self, tcb: &mut TCB) {
+        if !tcb.state.queued() {
+            return;
+        }
+        let (dom, prio) = (tcb.domain, tcb.priority);
+        let mut queue = self.queues[dom][prio];
+        match unsafe { tcb.sched_prev.as_mut() } {
+            Some(prev) => prev.sched_next = tcb.sched_next,
+            None => queue.head = tcb.sched_next,
+        }
+        match unsafe { tcb.sched_next.as_mut() } {
+            Some(next) => next.sched_prev = tcb.sched_prev,
+            None => queue.end = tcb.sched_prev,
+        }
+        if queue.is_empty() {
+            self.remove_from_bitmap(dom, prio);
+        }
+        self.queues[dom][prio] = queue;
+        tcb.sched_prev = null_mut();
+        tcb.sched_next = null_mut();
+        tcb.state.set_queued(false);
+    }
+}
+
+/// 将线程加入 endpoint 队列的尾部，返回新的队列，对应 seL4 的 `tcbEPAppend`
+pub fn tcb_ep_append(tcb: &mut TCB, mut queue: TcbQueue) -> TcbQueue {
+    let ptr: *mut TCB = tcb;
+    match unsafe { queue.end.as_mut() } {
+        Some(end) => end.ep_next = ptr,
+        None => queue.head = ptr,
+    }
+    tcb.ep_prev = queue.end;
+    tcb.ep_next = null_mut();
+    queue.end = ptr;
+    queue
+}
+
+/// 将线程移出 endpoint 队列，返回新的队列，对应 seL4 的 `tcbEPDequeue`
+pub fn tcb_ep_dequeue(tcb: &mut TCB, mut queue: TcbQueue) -> TcbQueue {
+    match unsafe { tcb.ep_prev.as_mut() } {
+        Some(prev) => prev.ep_next = tcb.ep_next,
+        None => queue.head = tcb.ep_next,
+    }
+    match unsafe { tcb.ep_next.as_mut() } {
+        Some(next) => next.ep_prev = tcb.ep_prev,
+        None => queue.end = tcb.ep_prev,
+    }
+    tcb.ep_prev = null_mut();
+    tcb.ep_next = null_mut();
+    queue
+}
+
+/// 将线程加入所在核的就绪队列头部，对应 seL4 的 `tcbSchedEnqueue`
+pub fn tcb_sched_enqueue(tcb: &mut TCB) {
+    unsafe {
+        (*node_state_on_core(tcb.affinity))
+            .ready_queues
+            .enqueue(tcb)
+    };
+}
+
+/// 将线程加入所在核的就绪队列尾部，对应 seL4 的 `tcbSchedAppend`
+pub fn tcb_sched_append(tcb: &mut TCB) {
+    unsafe { (*node_state_on_core(tcb.affinity)).ready_queues.append(tcb) };
+}
+
+/// 将线程移出所在核的就绪队列，对应 seL4 的 `tcbSchedDequeue`
+pub fn tcb_sched_dequeue(tcb: &mut TCB) {
+    unsafe {
+        (*node_state_on_core(tcb.affinity))
+            .ready_queues
+            .dequeue(tcb)
+    };
+}
+
+/// 解析 domain capability 的调用，将线程移到指定的 domain，对应 seL4 的 `decodeDomainInvocation`
+pub fn decode_domain_invocation(
+    label: InvocationLabel,
+    args: &[usize],
+    extra_caps: &[Cap],
+) -> Result<(), Exception> {
+    if label != InvocationLabel::DomainSetSet {
+        return Err(SyscallError::IllegalOperation.into());
+    }
+    let Some(&domain) = args.first() else {
+        return Err(SyscallError::TruncatedMessage.into());
+    };
+    if domain >= NUM_DOMAINS {
+        return Err(SyscallError::InvalidArgument { number: 0 }.into());
+    }
+    let Some(cap) = extra_caps.first() else {
+        return Err(SyscallError::TruncatedMessage.into());
+    };
+    let CapView::ThreadCap(cap) = cap.view() else {
+        return Err(SyscallError::InvalidArgument { number: 1 }.into());
+    };
+
+    set_thread_state(unsafe { &mut *cur_thread() }, ThreadStateType::Restart);
+    set_domain(unsafe { TCB::from_cap(&cap) }, domain);
+    Ok(())
+}
+
+/// 写入第 `offset` 个消息，超出消息寄存器的部分写入 IPC buffer，返回写入之后的消息长度，
+/// 对应 seL4 的 `setMR`
+///
+/// 没有 IPC buffer 时只能写入消息寄存器
+pub fn set_mr(
+    receiver: &mut TCB,
+    receive_ipc_buffer: Option<*mut usize>,
+    offset: usize,
+    reg: usize,
+) -> usize {
+    if offset < MSG_REGISTERS.len() {
+        receiver.context().set_register(MSG_REGISTERS[offset], reg);
+        return offset + 1;
+    }
+    match receive_ipc_buffer {
+        Some(buffer) => {
+            unsafe { *buffer.add(offset + 1) = reg };
+            offset + 1
+        }
+        None => MSG_REGISTERS.len(),
+    }
+}
+
+/// `TCB_ReadRegisters` 中挂起源线程的标志位，对应 seL4 的 `ReadRegisters_suspend`
+const READ_REGISTERS_SUSPEND: usize = 0;
+/// `TCB_WriteRegisters` 中恢复目标线程的标志位，对应 seL4 的 `WriteRegisters_resume`
+const WRITE_REGISTERS_RESUME: usize = 0;
+/// `TCB_CopyRegisters` 的标志位，对应 seL4 的 `CopyRegisters_*`
+const COPY_REGISTERS_SUSPEND_SOURCE: usize = 0;
+const COPY_REGISTERS_RESUME_TARGET: usize = 1;
+const COPY_REGISTERS_TRANSFER_FRAME: usize = 2;
+const COPY_REGISTERS_TRANSFER_INTEGER: usize = 3;
+
+/// 要插入的 capability 及作为其父节点的 slot
+type CapSlot = (Cap, *mut Cte);
+
+/// 可以读写的寄存器数量
+const N_REGISTERS: usize = FRAME_REGISTERS.len() + GP_REGISTERS.len();
+
+/// 解析 thread capability 的调用，对应 seL4 的 `decodeTCBInvocation`
+///
+/// `extra_caps` 为消息中附带的 capability 所在的 slot，设置 CSpace、VSpace 和 IPC buffer 时
+/// 新的 capability 作为这些 slot 的子节点插入。`call` 表示通过 Call 发起调用，
+/// 此时 `TCB_ReadRegisters` 将读取的寄存器作为回复写入当前线程的消息中
+///
+/// # Safety
+///
+/// `slot` 和 `extra_caps` 需要指向有效的 [Cte]，且 `slot` 中为 thread capability
+pub unsafe fn decode_tcb_invocation(
+    label: InvocationLabel,
+    slot: *mut Cte,
+    args: &[usize],
+    extra_caps: &[*mut Cte],
+    call: bool,
+) -> Result<(), Exception> {
+    use InvocationLabel::*;
+
+    let CapView::ThreadCap(cap) = (unsafe { **slot }).view() else {
+        panic!("decodeTCBInvocation: expected thread cap");
+    };
+    let tcb = unsafe { TCB::from_cap(&cap) };
+
+    match label {
+        TCBReadRegisters => decode_read_registers(tcb, args, call),
+        TCBWriteRegisters => decode_write_registers(tcb, args),
+        TCBCopyRegisters => unsafe { decode_copy_registers(tcb, args, extra_caps) },
+        TCBSuspend => {
+            set_cur_thread_restart(tcb);
+            suspend(tcb);
+            Ok(())
+        }
+        TCBResume => {
+            set_cur_thread_restart(tcb);
+            restart(tcb);
+            Ok(())
+        }
+        TCBConfigure => unsafe { decode_tcb_configure(tcb, slot, args, extra_caps) },
+        TCBSetPriority => decode_set_priority(tcb, args, extra_caps),
+        TCBSetMCPriority => decode_set_mc_priority(tcb, args, extra_caps),
+        TCBSetSchedParams => decode_set_sched_params(tcb, args, extra_caps),
+        TCBSetIPCBuffer => unsafe { decode_set_ipc_buffer(tcb, slot, args, extra_caps) },
+        TCBSetSpace => unsafe { decode_set_space(tcb, slot, args, extra_caps) },
+        TCBBindNotification => decode_bind_notification(tcb, extra_caps),
+        TCBUnbindNotification => decode_unbind_notification(tcb),
+        TCBSetTLSBase => decode_set_tls_base(tcb, args),
+        _ => Err(SyscallError::IllegalOperation.into()),
+    }
+}
+
+/// 调用之前将当前线程设置为 `Restart` 状态，对应 seL4 的 `setThreadState(ksCurThread, ThreadState_Restart)`
+///
+/// 被调用的线程可能就是当前线程，此时通过 `tcb` 修改，避免与同一个 TCB 的引用重叠
+fn set_cur_thread_restart(tcb: &mut TCB) {
+    if is_cur_thread(tcb) {
+        set_thread_state(tcb, ThreadStateType::Restart);
+    } else {
+        set_thread_state(unsafe { &mut *cur_thread() }, ThreadStateType::Restart);
+    }
+}
+
+/// 对应 seL4 的 `decodeReadRegisters`
+fn decode_read_registers(tcb: &mut TCB, args: &[usize], call: bool) -> Result<(), Exception> {
+    if args.len() < 2 {
+        return Err(SyscallError::TruncatedMessage.into());
+    }
+    let (flags, n) = (args[0], args[1]);
+    if !(1..=N_REGISTERS).contains(&n) {
+        return Err(SyscallError::RangeError {
+            min: 1,
+            max: N_REGISTERS,
+        }
+        .into());
+    }
+    if is_cur_thread(tcb) {
+        return Err(SyscallError::IllegalOperation.into());
+    }
+
+    set_cur_thread_restart(tcb);
+    invoke_tcb_read_registers(tcb, flags & bit!(READ_REGISTERS_SUSPEND) != 0, n, call)
+}
+
+/// 对应 seL4 的 `decodeWriteRegisters`
+fn decode_write_registers(tcb: &mut TCB, args: &[usize]) -> Result<(), Exception> {
+    if args.len() < 2 {
+        return Err(SyscallError::TruncatedMessage.into());
+    }
+    let (flags, w) = (args[0], args[1]);
+    if args.len() - 2 < w {
+        return Err(SyscallError::TruncatedMessage.into());
+    }
+    if is_cur_thread(tcb) {
+        return Err(SyscallError::IllegalOperation.into());
+    }
+
+    set_cur_thread_restart(tcb);
+    invoke_tcb_write_registers(
+        tcb,
+        flags & bit!(WRITE_REGISTERS_RESUME) != 0,
+        &args[2..2 + w],
+    )
+}
+
+/// 对应 seL4 的 `decodeCopyRegisters`
+///
+/// # Safety
+///
+/// 与 [decode_tcb_invocation] 相同，源线程可能就是 `tcb`，调用之后不能再使用传入的引用
+unsafe fn decode_copy_registers(
+    tcb: *mut TCB,
+    args: &[usize],
+    extra_caps: &[*mut Cte],
+) -> Result<(), Exception> {
+    if args.is_empty() || extra_caps.is_empty() {
+        return Err(SyscallError::TruncatedMessage.into());
+    }
+    let flags = args[0];
+    let CapView::ThreadCap(source_cap) = (unsafe { **extra_caps[0] }).view() else {
+        return Err(SyscallError::InvalidCapability { number: 1 }.into());
+    };
+
+    set_cur_thread_restart(unsafe { &mut *tcb });
+    let flag = |bit: usize| flags & bit!(bit) != 0;
+    unsafe {
+        invoke_tcb_copy_registers(
+            tcb,
+            source_cap.get_cap_tcb_ptr() as *mut TCB,
+            flag(COPY_REGISTERS_SUSPEND_SOURCE),
+            flag(COPY_REGISTERS_RESUME_TARGET),
+            flag(COPY_REGISTERS_TRANSFER_FRAME),
+            flag(COPY_REGISTERS_TRANSFER_INTEGER),
+        )
+    }
+}
+
+/// 对应 seL4 的 `decodeTCBConfigure`，依次设置 fault handler、CSpace、VSpace 和 IPC buffer
+///
+/// # Safety
+///
+/// 与 [decode_tcb_invocation] 相同
+unsafe fn decode_tcb_configure(
+    tcb: &mut TCB,
+    slot: *mut Cte,
+    args: &[usize],
+    extra_caps: &[*mut Cte],
+) -> Result<(), Exception> {
+    if args.len() < 4 || extra_caps.len() < 3 {
+        return Err(SyscallError::TruncatedMessage.into());
+    }
+    let (fault_ep, croot_data, vroot_data, buffer_addr) = (args[0], args[1], args[2], args[3]);
+    let buffer = unsafe { decode_ipc_buffer(buffer_addr, extra_caps[2])? };
+    let (croot, vroot) =
+        unsafe { decode_space(tcb, croot_data, extra_caps[0], vroot_data, extra_caps[1])? };
+
+    set_cur_thread_restart(tcb);
+    unsafe {
+        invoke_tcb_set_space(tcb, slot, fault_ep, croot, vroot)?;
+        invoke_tcb_set_ipc_buffer(tcb, slot, buffer_addr, buffer)
+    }
+}
+
+/// 检查能否设置优先级 `prio`，不能超过作为权限的线程的最大可控优先级 `mcp`，对应 seL4 的 `checkPrio`
+fn check_prio(prio: usize, mcp: usize) -> Result<(), SyscallError> {
+    if prio > mcp {
+        return Err(SyscallError::RangeError { min: 0, max: mcp });
+    }
+    Ok(())
+}
+
+/// 设置优先级的调用中作为权限的线程的最大可控优先级，线程由第一个额外的 capability 指定
+///
+/// 作为权限的线程可能就是被设置的线程，这里只通过指针读取 MCP，不创建引用
+fn auth_tcb(extra_caps: &[*mut Cte]) -> Result<usize, SyscallError> {
+    match (unsafe { **extra_caps[0] }).view() {
+        CapView::ThreadCap(auth_cap) => {
+            let auth_tcb = auth_cap.get_cap_tcb_ptr() as *const TCB;
+            Ok(unsafe { (*auth_tcb).mcp })
+        }
+        _ => Err(SyscallError::InvalidCapability { number: 1 }),
+    }
+}
+
+/// 对应 seL4 的 `decodeSetPriority`
+fn decode_set_priority(
+    tcb: &mut TCB,
+    args: &[usize],
+    extra_caps: &[*mut Cte],
+) -> Result<(), Exception> {
+    if args.is_empty() || extra_caps.is_empty() {
+        return Err(SyscallError::TruncatedMessage.into());
+    }
+    let new_prio = args[0];
+    check_prio(new_prio, auth_tcb(extra_caps)?)?;
+
+    set_cur_thread_restart(tcb);
+    set_priority(tcb, new_prio);
+    Ok(())
+}
+
+/// 对应 seL4 的 `decodeSetMCPriority`
+fn decode_set_mc_priority(
+    tcb: &mut TCB,
+    args: &[usize],
+    extra_caps: &[*mut Cte],
+) -> Result<(), Exception> {
+    if args.is_empty() || extra_caps.is_empty() {
+        return Err(SyscallError::TruncatedMessage.into());
+    }
+    let new_mcp = args[0];
+    check_prio(new_mcp, auth_tcb(extra_caps)?)?;
+
+    set_cur_thread_restart(tcb);
+    set_mcp(tcb, new_mcp);
+    Ok(())
+}
+
+/// 对应 seL4 的 `decodeSetSchedParams`
+fn decode_set_sched_params(
+    tcb: &mut TCB,
+    args: &[usize],
+    extra_caps: &[*mut Cte],
+) -> Result<(), Exception> {
+    if args.len() < 2 || extra_caps.is_empty() {
+        return Err(SyscallError::TruncatedMessage.into());
+    }
+    let (new_mcp, new_prio) = (args[0], args[1]);
+    let mcp = auth_tcb(extra_caps)?;
+    check_prio(new_mcp, mcp)?;
+    check_prio(new_prio, mcp)?;
+
+    set_cur_thread_restart(tcb);
+    set_mcp(tcb, new_mcp);
+    set_priority(tcb, new_prio);
+    Ok(())
+}
+
+/// 对应 seL4 的 `decodeSetIPCBuffer`
+///
+/// # Safety
+///
+/// 与 [decode_tcb_invocation] 相同
+unsafe fn decode_set_ipc_buffer(
+    tcb: &mut TCB,
+    slot: *mut Cte,
+    args: &[usize],
+    extra_caps: &[*mut Cte],
+) -> Result<(), Exception> {
+    if args.is_empty() || extra_caps.is_empty() {
+        return Err(SyscallError::TruncatedMessage.into());
+    }
+    let buffer_addr = args[0];
+    let buffer = unsafe { decode_ipc_buffer(buffer_addr, extra_caps[0])? };
+
+    set_cur_thread_restart(tcb);
+    unsafe { invoke_tcb_set_ipc_buffer(tcb, slot, buffer_addr, buffer) }
+}
+
+/// 对应 seL4 的 `decodeSetSpace`
+///
+/// # Safety
+///
+/// 与 [decode_tcb_invocation] 相同
+unsafe fn decode_set_space(
+    tcb: &mut TCB,
+    slot: *mut Cte,
+    args: &[usize],
+    extra_caps: &[*mut Cte],
+) -> Result<(), Exception> {
+    if args.len() < 3 || extra_caps.len() < 2 {
+        return Err(SyscallError::TruncatedMessage.into());
+    }
+    let (fault_ep, croot_data, vroot_data) = (args[0], args[1], args[2]);
+    let (croot, vroot) =
+        unsafe { decode_space(tcb, croot_data, extra_caps[0], vroot_data, extra_caps[1])? };
+
+    set_cur_thread_restart(tcb);
+    unsafe { invoke_tcb_set_space(tcb, slot, fault_ep, croot, vroot) }
+}
+
+/// 对应 seL4 的 `decodeBindNotification`，只能绑定有接收权限且没有等待线程的 notification
+fn decode_bind_notification(tcb: &mut TCB, extra_caps: &[*mut Cte]) -> Result<(), Exception> {
+    if extra_caps.is_empty() {
+        return Err(SyscallError::TruncatedMessage.into());
+    }
+    if !tcb.bound_notification().is_null() {
+        return Err(SyscallError::IllegalOperation.into());
+    }
+    let ntfn = match (unsafe { **extra_caps[0] }).view() {
+        CapView::NotificationCap(cap) if cap.get_cap_ntfn_can_receive() != 0 => unsafe {
+            &mut *(cap.get_cap_ntfn_ptr() as *mut Notification)
+        },
+        _ => return Err(SyscallError::IllegalOperation.into()),
+    };
+    if ntfn.get_ntfn_queue_head() != 0 || ntfn.get_ntfn_bound_tcb() != 0 {
+        return Err(SyscallError::IllegalOperation.into());
+    }
+
+    set_cur_thread_restart(tcb);
+    bind_notification(tcb, ntfn);
+    Ok(())
+}
+
+/// 对应 seL4 的 `decodeUnbindNotification`
+fn decode_unbind_notification(tcb: &mut TCB) -> Result<(), Exception> {
+    if tcb.bound_notification().is_null() {
+        return Err(SyscallError::IllegalOperation.into());
+    }
+
+    set_cur_thread_restart(tcb);
+    unbind_notification(tcb);
+    Ok(())
+}
+
+/// 对应 seL4 的 `decodeSetTLSBase`
+fn decode_set_tls_base(tcb: &mut TCB, args: &[usize]) -> Result<(), Exception> {
+    let Some(&tls_base) = args.first() else {
+        return Err(SyscallError::TruncatedMessage.into());
+    };
+
+    set_cur_thread_restart(tcb);
+    tcb.context().set_register(TLS_BASE, tls_base);
+    if is_cur_thread(tcb) {
+        reschedule_required();
+    }
+    Ok(())
+}
+
+/// 解析新的 IPC buffer，地址为 0 时线程没有 IPC buffer，不使用 `buffer_slot`
+///
+/// # Safety
+///
+/// `buffer_slot` 需要指向有效的 [Cte]
+unsafe fn decode_ipc_buffer(
+    buffer_addr: usize,
+    buffer_slot: *mut Cte,
+) -> Result<Option<CapSlot>, SyscallError> {
+    if buffer_addr == 0 {
+        return Ok(None);
+    }
+    let buffer_cap = derive_cap(unsafe { &*buffer_slot }, unsafe { **buffer_slot })?;
+    check_valid_ipc_buffer(buffer_addr, &buffer_cap)?;
+    Ok(Some((buffer_cap, buffer_slot)))
+}
+
+/// 解析新的 CSpace 和 VSpace，`data` 不为 0 时先更新 capability 中的 guard 等数据
+///
+/// 线程原有的 CSpace 或 VSpace 需要长时间删除时返回 [SyscallError::IllegalOperation]
+///
+/// # Safety
+///
+/// `croot_slot` 和 `vroot_slot` 需要指向有效的 [Cte]
+unsafe fn decode_space(
+    tcb: &mut TCB,
+    croot_data: usize,
+    croot_slot: *mut Cte,
+    vroot_data: usize,
+    vroot_slot: *mut Cte,
+) -> Result<(CapSlot, CapSlot), SyscallError> {
+    if slot_cap_long_running_delete(tcb.cte(TcbCnodeIndex::CTable))
+        || slot_cap_long_running_delete(tcb.cte(TcbCnodeIndex::VTable))
+    {
+        return Err(SyscallError::IllegalOperation);
+    }
+
+    let mut croot_cap = unsafe { **croot_slot };
+    if croot_data != 0 {
+        croot_cap = update_cap_data(false, croot_data, croot_cap);
+    }
+    let croot_cap = derive_cap(unsafe { &*croot_slot }, croot_cap)?;
+    if !matches!(croot_cap.view(), CapView::CnodeCap(_)) {
+        return Err(SyscallError::IllegalOperation);
+    }
+
+    let mut vroot_cap = unsafe { **vroot_slot };
+    if vroot_data != 0 {
+        vroot_cap = update_cap_data(false, vroot_data, vroot_cap);
+    }
+    let vroot_cap = derive_cap(unsafe { &*vroot_slot }, vroot_cap)?;
+    if !is_valid_vtable_root(&vroot_cap) {
+        return Err(SyscallError::IllegalOperation);
+    }
+
+    Ok(((croot_cap, croot_slot), (vroot_cap, vroot_slot)))
+}
+
+/// 对应 seL4 的 `invokeTCB_ReadRegisters`，依次读取 `frameRegisters` 和 `gpRegisters` 中的前 `n` 个
+///
+/// `src` 不能是当前线程，否则与回复消息时使用的当前线程重叠，由 [decode_read_registers] 保证。
+/// 通过 Call 调用时已经写入回复，将当前线程设置为 `Running` 状态，不再回复空消息
+fn invoke_tcb_read_registers(
+    src: &mut TCB,
+    suspend_source: bool,
+    n: usize,
+    call: bool,
+) -> Result<(), Exception> {
+    debug_assert!(!is_cur_thread(src));
+    if suspend_source {
+        suspend(src);
+    }
+    if call {
+        let thread = unsafe { &mut *cur_thread() };
+        let ipc_buffer = lookup_ipc_buffer(true, thread);
+        thread.context().set_register(BADGE_REGISTER, 0);
+        let mut length = 0;
+        let regs = FRAME_REGISTERS.iter().chain(&GP_REGISTERS).take(n);
+        for (i, &reg) in regs.enumerate() {
+            length = set_mr(thread, ipc_buffer, i, src.context().get_register(reg));
+        }
+        let info = MessageInfo::new(0, 0, 0, length);
+        thread
+            .context()
+            .set_register(MSG_INFO_REGISTER, info.to_word());
+        set_thread_state(thread, ThreadStateType::Running);
+    }
+    Ok(())
+}
+
+/// 对应 seL4 的 `invokeTCB_WriteRegisters`，写入之后线程从新的 FaultIP 开始执行
+fn invoke_tcb_write_registers(
+    dest: &mut TCB,
+    resume_target: bool,
+    values: &[usize],
+) -> Result<(), Exception> {
+    let context = dest.context();
+    let regs = FRAME_REGISTERS.iter().chain(&GP_REGISTERS);
+    for (&reg, &value) in regs.zip(values) {
+        context.set_register(reg, sanitise_register(reg, value));
+    }
+    context.set_register(NEXT_IP, context.get_register(FAULT_IP));
+
+    if resume_target {
+        restart(dest);
+    }
+    if is_cur_thread(dest) {
+        reschedule_required();
+    }
+    Ok(())
+}
+
+/// 对应 seL4 的 `invokeTCB_CopyRegisters`
+///
+/// `src` 和 `dest` 可能是同一个线程，每次访问时只临时创建引用
+///
+/// # Safety
+///
+/// `dest` 和 `src` 需要指向有效的 TCB，且调用期间没有其他指向它们的引用
+unsafe fn invoke_tcb_copy_registers(
+    dest: *mut TCB,
+    src: *mut TCB,
+    suspend_source: bool,
+    resume_target: bool,
+    transfer_frame: bool,
+    transfer_integer: bool,
+) -> Result<(), Exception> {
+    if suspend_source {
+        suspend(unsafe { &mut *src });
+    }
+    if resume_target {
+        restart(unsafe { &mut *dest });
+    }
+    let copy = |regs: &[usize]| {
+        for &reg in regs {
+            let value = unsafe { (*src).context().get_register(reg) };
+            unsafe { (*dest).context().set_register(reg, value) };
+        }
+    };
+    if transfer_frame {
+        copy(&FRAME_REGISTERS);
+    }
+    if transfer_integer {
+        copy(&GP_REGISTERS);
+    }
+    let dest = unsafe { &mut *dest };
+    if transfer_frame {
+        let context = dest.context();
+        context.set_register(NEXT_IP, context.get_register(FAULT_IP));
+    }
+
+    if is_cur_thread(dest) {
+        reschedule_required();
+    }
+    Ok(())
+}
+
+/// 设置 fault handler，将新的 CSpace 和 VSpace 放入线程的 slot 中，
+/// 对应 seL4 `invokeTCB_ThreadControl` 中的 `thread_control_update_space`
+///
+/// 删除原有的 capability 可能会删除新的 capability 或线程本身，此时不再插入
+///
+/// # Safety
+///
+/// `slot` 和 `croot`、`vroot` 中的 slot 需要指向有效的 [Cte]
+unsafe fn invoke_tcb_set_space(
+    target: &mut TCB,
+    slot: *mut Cte,
+    fault_ep: usize,
+    croot: CapSlot,
+    vroot: CapSlot,
+) -> Result<(), Exception> {
+    let t_cap: Cap = ThreadCap::new(target.ptr()).into();
+    target.set_fault_handler(fault_ep);
+
+    for (index, (new_cap, src_slot)) in [
+        (TcbCnodeIndex::CTable, croot),
+        (TcbCnodeIndex::VTable, vroot),
+    ] {
+        let root_slot = target.cte_ptr(index);
+        unsafe { cte_delete(root_slot, true)? };
+        if same_object_as(&new_cap, unsafe { &*src_slot })
+            && same_object_as(&t_cap, unsafe { &*slot })
+        {
+            unsafe { cte_insert(new_cap, src_slot, root_slot) };
+        }
+    }
+    Ok(())
+}
+
+/// 设置 IPC buffer 的地址，将新的页放入线程的 slot 中，
+/// 对应 seL4 `invokeTCB_ThreadControl` 中的 `thread_control_update_ipc_buffer`
+///
+/// # Safety
+///
+/// `slot` 和 `buffer` 中的 slot 需要指向有效的 [Cte]
+unsafe fn invoke_tcb_set_ipc_buffer(
+    target: &mut TCB,
+    slot: *mut Cte,
+    buffer_addr: usize,
+    buffer: Option<CapSlot>,
+) -> Result<(), Exception> {
+    let t_cap: Cap = ThreadCap::new(target.ptr()).into();
+    let buffer_slot = target.cte_ptr(TcbCnodeIndex::Buffer);
+    unsafe { cte_delete(buffer_slot, true)? };
+    target.set_ipc_buffer(va!(buffer_addr));
+
+    if let Some((buffer_cap, src_slot)) = buffer {
+        if same_object_as(&buffer_cap, unsafe { &*src_slot })
+            && same_object_as(&t_cap, unsafe { &*slot })
+        {
+            unsafe { cte_insert(buffer_cap, src_slot, buffer_slot) };
+        }
+    }
+    if is_cur_thread(target) {
+        reschedule_required();
+    }
+    Ok(())
+}
+
+/// TCB 对象所需的最小大小，CTE 和 TCB 结构各占一半，按照较大的一个向上取整到 2 的幂
+const fn tcb_min_bits() -> usize {
+    let cte_size = bit!(TCB_CNODE_RADIX + SLOT_BITS);
+    let tcb_size = size_of::<TCB>().next_power_of_two();
+    let half = if tcb_size > cte_size {
+        tcb_size
+    } else {
+        cte_size
+    };
+    half.trailing_zeros() as usize + 1
+}
+
+const fn _check_type_width() {
+    assert!(tcb_min_bits() <= TCB_BITS);
+    assert!(TCB_OFFSET + size_of::<TCB>() <= bit!(TCB_BITS));
+}
+const _: () = _check_type_width();
+const _: () = assert!(TCB_CNODE_ENTRIES <= bit!(TCB_CNODE_RADIX));
+const _: () = assert!(TcbCnodeIndex::Buffer as usize + 1 == TCB_CNODE_ENTRIES);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arch::{VmRights, ARM_SMALL_PAGE, PAGE_BITS, SPSR_EL1, SP_EL0, TPIDRRO_EL0, VSPACE_BITS},
+        config::MAX_PRIO,
+        model::statedata::node_state,
+        object::{
+            ipc::NOTIFICATION_BITS,
+            structures::{FrameCap, NotificationCap, VspaceCap},
+            test_utils::{alloc_object, alloc_tcb, cnode_cap, cnode_ptr, slot, write},
+        },
+    };
+
+    #[test]
+    fn ctes_before_tcb() {
+        let tcb = alloc_tcb();
+        let base = tcb.ptr() - TCB_OFFSET;
+        assert_eq!(base & (bit!(TCB_BITS) - 1), 0);
+        assert_eq!(tcb.cte_ptr(TcbCnodeIndex::CTable) as usize, base);
+        assert_eq!(
+            tcb.cte_ptr(TcbCnodeIndex::Buffer) as usize,
+            base + bit!(SLOT_BITS) * TcbCnodeIndex::Buffer as usize
+        );
+        assert!(tcb.cte(TcbCnodeIndex::Caller).is_null());
+
+        let cap: Cap = ThreadCap::new(tcb.ptr()).into();
+        assert_eq!(cap.ptr(), base);
+        assert_eq!(
+            unsafe { TCB::from_cap(&ThreadCap::new(tcb.ptr())) }.ptr(),
+            tcb.ptr()
+        );
+    }
+
+    #[test]
+    fn new_thread() {
+        let tcb = alloc_tcb();
+        assert_eq!(tcb.time_slice(), TIME_SLICE);
+        assert!(tcb.sched_next().is_null() && tcb.ep_prev().is_null());
+        assert!(!tcb.state().queued());
+        assert_eq!(tcb.fault(), Fault::NullFault);
+    }
+
+    #[test]
+    fn name() {
+        let tcb = alloc_tcb();
+        assert_eq!(tcb.name(), "");
+        tcb.set_name("rootserver");
+        assert_eq!(tcb.name(), "rootserver");
+        assert!(tcb.cte(TcbCnodeIndex::Buffer).is_null());
+
+        let long = "线".repeat(TCB_NAME_LENGTH);
+        tcb.set_name(&long);
+        assert!(tcb.name().len() < TCB_NAME_LENGTH);
+        assert!(long.starts_with(tcb.name()));
+    }
+
+    #[test]
+    fn domain_set() {
+        use InvocationLabel::*;
+
+        let tcb = alloc_tcb();
+        let thread: Cap = ThreadCap::new(tcb.ptr()).into();
+        let err = |e: SyscallError| Err(Exception::SyscallError(e));
+        assert_eq!(
+            decode_domain_invocation(CNodeCopy, &[0], &[thread]),
+            err(SyscallError::IllegalOperation)
+        );
+        assert_eq!(
+            decode_domain_invocation(DomainSetSet, &[], &[thread]),
+            err(SyscallError::TruncatedMessage)
+        );
+        assert_eq!(
+            decode_domain_invocation(DomainSetSet, &[NUM_DOMAINS], &[thread]),
+            err(SyscallError::InvalidArgument { number: 0 })
+        );
+        assert_eq!(
+            decode_domain_invocation(DomainSetSet, &[0], &[]),
+            err(SyscallError::TruncatedMessage)
+        );
+        assert_eq!(
+            decode_domain_invocation(DomainSetSet, &[0], &[Cap::null()]),
+            err(SyscallError::InvalidArgument { number: 1 })
+        );
+
+        let dom = NUM_DOMAINS - 1;
+        tcb.state_mut().set_ts_type(ThreadStateType::Running);
+        assert_eq!(
+            decode_domain_invocation(DomainSetSet, &[dom], &[thread]),
+            Ok(())
+        );
+        assert_eq!(tcb.domain(), dom);
+        assert_eq!(
+            unsafe { (*cur_thread()).state().ts_type() },
+            ThreadStateType::Restart
+        );
+        assert!(tcb.state().queued());
+        assert_eq!(
+            unsafe { &(*node_state_on_core(0)).ready_queues }.head(dom, 0),
+            tcb as *mut TCB
+        );
+    }
+
+    #[test]
+    fn ready_queue_order() {
+        let mut queues = ReadyQueues::new();
+        let [a, b, c] = [alloc_tcb(), alloc_tcb(), alloc_tcb()];
+        queues.enqueue(a);
+        queues.append(b);
+        queues.enqueue(c);
+        queues.enqueue(a);
+        assert_eq!(queues.head(0, 0), c as *mut TCB);
+        assert_eq!(c.sched_next(), a as *mut TCB);
+        assert_eq!(b.sched_prev(), a as *mut TCB);
+
+        queues.dequeue(a);
+        assert!(!a.state().queued());
+        assert_eq!(c.sched_next(), b as *mut TCB);
+        assert_eq!(b.sched_prev(), c as *mut TCB);
+        queues.dequeue(c);
+        queues.dequeue(c);
+        assert_eq!(queues.head(0, 0), b as *mut TCB);
+        queues.dequeue(b);
+        assert!(queues.head(0, 0).is_null());
+        assert!(queues.is_empty(0));
+    }
+
+    #[test]
+    fn ready_queue_bitmap() {
+        let mut queues = ReadyQueues::new();
+        let tcbs = [3, 64, 63, MAX_PRIO].map(|prio| {
+            let tcb = alloc_tcb();
+            tcb.set_priority(prio);
+            queues.enqueue(tcb);
+            tcb
+        });
+        assert_eq!(queues.highest_prio(0), MAX_PRIO);
+        assert!(queues.is_highest_prio(0, MAX_PRIO));
+        assert!(!queues.is_highest_prio(0, 100));
+
+        queues.dequeue(tcbs[3]);
+        assert_eq!(queues.highest_prio(0), 64);
+        queues.dequeue(tcbs[1]);
+        assert_eq!(queues.highest_prio(0), 63);
+        assert!(queues.is_highest_prio(0, 63));
+        queues.dequeue(tcbs[2]);
+        assert_eq!(queues.highest_prio(0), 3);
+        queues.dequeue(tcbs[0]);
+        assert!(queues.is_empty(0));
+        assert!(queues.is_highest_prio(0, 0));
+    }
+
+    /// 将 `tcb` 的 thread capability 写入 `root` 的第 `pos` 个 slot
+    fn thread_slot(root: Cap, pos: usize, tcb: &TCB) -> *mut Cte {
+        write(root, pos, ThreadCap::new(tcb.ptr()).into());
+        slot(root, pos)
+    }
+
+    fn invoke(
+        label: InvocationLabel,
+        target: *mut Cte,
+        args: &[usize],
+        extra_caps: &[*mut Cte],
+    ) -> Result<(), Exception> {
+        unsafe { decode_tcb_invocation(label, target, args, extra_caps, true) }
+    }
+
+    fn err(e: SyscallError) -> Result<(), Exception> {
+        Err(Exception::SyscallError(e))
+    }
+
+    #[test]
+    fn write_and_read_registers() {
+        use InvocationLabel::*;
+
+        let root = cnode_cap(4, 0, 0);
+        let target = alloc_tcb();
+        let target_slot = thread_slot(root, 1, target);
+        assert_eq!(
+            invoke(TCBWriteRegisters, target_slot, &[0, 3, 0x1000, 0x2000], &[]),
+            err(SyscallError::TruncatedMessage)
+        );
+        // pc、sp、spsr、x0、x1，EL1h 的 spsr 会被替换
+        let values = [0x1000, 0x2000, 0x5, 42, 43];
+        let args = [&[0, values.len()], &values[..]].concat();
+        assert_eq!(invoke(TCBWriteRegisters, target_slot, &args, &[]), Ok(()));
+        let context = target.context();
+        assert_eq!(context.get_register(NEXT_IP), 0x1000);
+        assert_eq!(context.get_register(SP_EL0), 0x2000);
+        let pstate_user = UserContext::new().get_register(SPSR_EL1);
+        assert_eq!(context.get_register(SPSR_EL1), pstate_user);
+        assert_eq!(context.get_register(1), 43);
+        context.set_register(TPIDRRO_EL0, 0x77);
+        assert_eq!(target.state().ts_type(), ThreadStateType::InActive);
+        // EL0t 的 spsr 也只保留条件标志，用户态不能修改 DAIF 屏蔽中断
+        let values = [0x1000, 0x2000, 0x6000_03c0];
+        let args = [&[0, values.len()], &values[..]].concat();
+        assert_eq!(invoke(TCBWriteRegisters, target_slot, &args, &[]), Ok(()));
+        assert_eq!(
+            target.context().get_register(SPSR_EL1),
+            0x6000_0000 | pstate_user
+        );
+
+        let caller = alloc_tcb();
+        unsafe { (*node_state()).cur_thread = caller };
+        assert_eq!(
+            invoke(TCBReadRegisters, target_slot, &[0, N_REGISTERS + 1], &[]),
+            err(SyscallError::RangeError {
+                min: 1,
+                max: N_REGISTERS
+            })
+        );
+        // 没有 IPC buffer 时只返回消息寄存器中的部分
+        assert_eq!(invoke(TCBReadRegisters, target_slot, &[0, 5], &[]), Ok(()));
+        let context = caller.context();
+        assert_eq!(context.get_register(MSG_INFO_REGISTER), MSG_REGISTERS.len());
+        assert_eq!(context.get_register(MSG_REGISTERS[0]), 0x1000);
+        assert_eq!(context.get_register(MSG_REGISTERS[3]), 42);
+        // 已经写入回复，当前线程不再处于 Restart 状态
+        assert_eq!(caller.state().ts_type(), ThreadStateType::Running);
+
+        let page = alloc_object(PAGE_BITS);
+        let frame = FrameCap::new(
+            0,
+            page,
+            ARM_SMALL_PAGE,
+            VmRights::ReadWrite as usize,
+            false,
+            0,
+        );
+        *caller.cte(TcbCnodeIndex::Buffer) = Cte::new(frame.into());
+        caller.set_ipc_buffer(va!(0x1000_0400));
+        let n = N_REGISTERS;
+        assert_eq!(invoke(TCBReadRegisters, target_slot, &[0, n], &[]), Ok(()));
+        assert_eq!(caller.context().get_register(MSG_INFO_REGISTER), n);
+        let buffer = (page + 0x400) as *const usize;
+        assert_eq!(unsafe { *buffer.add(5) }, 43);
+        assert_eq!(unsafe { *buffer.add(n) }, 0x77);
+
+        unsafe { (*node_state()).cur_thread = target };
+        assert_eq!(
+            invoke(TCBReadRegisters, target_slot, &[0, 1], &[]),
+            err(SyscallError::IllegalOperation)
+        );
+        unsafe { (*node_state()).cur_thread = caller };
+        assert_eq!(invoke(TCBWriteRegisters, target_slot, &[1, 0], &[]), Ok(()));
+        assert_eq!(target.state().ts_type(), ThreadStateType::Restart);
+        assert!(matches!(
+            target.cte(TcbCnodeIndex::Reply).view(),
+            CapView::ReplyCap(reply) if reply.get_cap_reply_master() != 0
+        ));
+    }
+
+    #[test]
+    fn copy_registers_and_tls_base() {
+        use InvocationLabel::*;
+
+        let root = cnode_cap(4, 0, 0);
+        let (dest, src) = (alloc_tcb(), alloc_tcb());
+        let dest_slot = thread_slot(root, 1, dest);
+        let src_slot = thread_slot(root, 2, src);
+        src.state_mut().set_ts_type(ThreadStateType::Running);
+        let context = src.context();
+        context.set_register(NEXT_IP, 0x3000);
+        context.set_register(0, 1);
+        context.set_register(9, 9);
+
+        assert_eq!(
+            invoke(TCBCopyRegisters, dest_slot, &[0], &[]),
+            err(SyscallError::TruncatedMessage)
+        );
+        assert_eq!(
+            invoke(TCBCopyRegisters, dest_slot, &[0], &[slot(root, 3)]),
+            err(SyscallError::InvalidCapability { number: 1 })
+        );
+        let flags = bit!(COPY_REGISTERS_TRANSFER_FRAME) | bit!(COPY_REGISTERS_SUSPEND_SOURCE);
+        assert_eq!(
+            invoke(TCBCopyRegisters, dest_slot, &[flags], &[src_slot]),
+            Ok(())
+        );
+        // 挂起正在运行的线程时将下一条指令作为恢复执行的位置
+        assert_eq!(src.state().ts_type(), ThreadStateType::InActive);
+        let context = dest.context();
+        assert_eq!(context.get_register(FAULT_IP), 0x3000);
+        assert_eq!(context.get_register(NEXT_IP), 0x3000);
+        assert_eq!((context.get_register(0), context.get_register(9)), (1, 0));
+        let flags = bit!(COPY_REGISTERS_TRANSFER_INTEGER);
+        assert_eq!(
+            invoke(TCBCopyRegisters, dest_slot, &[flags], &[src_slot]),
+            Ok(())
+        );
+        assert_eq!(dest.context().get_register(9), 9);
+        // 源线程和目标线程可以相同
+        assert_eq!(
+            invoke(TCBCopyRegisters, dest_slot, &[flags], &[dest_slot]),
+            Ok(())
+        );
+        assert_eq!(dest.context().get_register(9), 9);
+
+        assert_eq!(
+            invoke(TCBSetTLSBase, dest_slot, &[], &[]),
+            err(SyscallError::TruncatedMessage)
+        );
+        assert_eq!(invoke(TCBSetTLSBase, dest_slot, &[0x8000], &[]), Ok(()));
+        assert_eq!(dest.context().get_register(TLS_BASE), 0x8000);
+        assert_eq!(
+            invoke(CNodeCopy, dest_slot, &[], &[]),
+            err(SyscallError::IllegalOperation)
+        );
+    }
+
+    #[test]
+    fn priority_limited_by_mcp() {
+        use InvocationLabel::*;
+
+        let root = cnode_cap(4, 0, 0);
+        let (target, auth) = (alloc_tcb(), alloc_tcb());
+        let target_slot = thread_slot(root, 1, target);
+        let auth_slot = thread_slot(root, 2, auth);
+        auth.set_mcp(100);
+        let range = err(SyscallError::RangeError { min: 0, max: 100 });
+
+        assert_eq!(
+            invoke(TCBSetPriority, target_slot, &[1], &[]),
+            err(SyscallError::TruncatedMessage)
+        );
+        assert_eq!(
+            invoke(TCBSetPriority, target_slot, &[1], &[slot(root, 3)]),
+            err(SyscallError::InvalidCapability { number: 1 })
+        );
+        assert_eq!(
+            invoke(TCBSetPriority, target_slot, &[101], &[auth_slot]),
+            range
+        );
+        assert_eq!(
+            invoke(TCBSetPriority, target_slot, &[100], &[auth_slot]),
+            Ok(())
+        );
+        assert_eq!(target.priority(), 100);
+
+        assert_eq!(
+            invoke(TCBSetMCPriority, target_slot, &[200], &[auth_slot]),
+            range
+        );
+        assert_eq!(
+            invoke(TCBSetMCPriority, target_slot, &[50], &[auth_slot]),
+            Ok(())
+        );
+        assert_eq!(target.mcp(), 50);
+
+        let set_sched_params =
+            |args: &[usize]| invoke(TCBSetSchedParams, target_slot, args, &[auth_slot]);
+        assert_eq!(set_sched_params(&[80]), err(SyscallError::TruncatedMessage));
+        assert_eq!(set_sched_params(&[101, 0]), range);
+        assert_eq!(set_sched_params(&[80, 101]), range);
+        assert_eq!(set_sched_params(&[80, 90]), Ok(()));
+        assert_eq!((target.mcp(), target.priority()), (80, 90));
+
+        // 线程可以用自己作为权限，但不能超过自己的最大可控优先级
+        assert_eq!(
+            invoke(TCBSetPriority, target_slot, &[81], &[target_slot]),
+            err(SyscallError::RangeError { min: 0, max: 80 })
+        );
+    }
+
+    #[test]
+    fn suspend_and_resume() {
+        use InvocationLabel::*;
+
+        let root = cnode_cap(4, 0, 0);
+        let target = alloc_tcb();
+        let target_slot = thread_slot(root, 1, target);
+
+        assert_eq!(invoke(TCBResume, target_slot, &[], &[]), Ok(()));
+        assert_eq!(target.state().ts_type(), ThreadStateType::Restart);
+        assert!(target.state().queued());
+        assert!(!target.cte(TcbCnodeIndex::Reply).is_null());
+
+        set_thread_state(target, ThreadStateType::Running);
+        target.context().set_register(NEXT_IP, 0x4000);
+        assert_eq!(invoke(TCBSuspend, target_slot, &[], &[]), Ok(()));
+        assert_eq!(target.state().ts_type(), ThreadStateType::InActive);
+        assert!(!target.state().queued());
+        assert_eq!(target.context().get_register(FAULT_IP), 0x4000);
+    }
+
+    #[test]
+    fn configure_space_and_ipc_buffer() {
+        use InvocationLabel::*;
+
+        let root = cnode_cap(4, 0, 0);
+        let target = alloc_tcb();
+        let target_slot = thread_slot(root, 1, target);
+        let cspace = cnode_cap(2, 0, 0);
+        write(root, 2, cspace);
+        let vspace = alloc_object(VSPACE_BITS);
+        write(root, 3, VspaceCap::new(1, vspace, true).into());
+        let page = alloc_object(PAGE_BITS);
+        let rw = VmRights::ReadWrite as usize;
+        write(
+            root,
+            4,
+            FrameCap::new(0, page, ARM_SMALL_PAGE, rw, false, 0).into(),
+        );
+        write(root, 5, VspaceCap::new(0, vspace, false).into());
+        let [cslot, vslot, bslot] = [2, 3, 4].map(|pos| slot(root, pos));
+
+        let configure = |args: &[usize], extra_caps: &[*mut Cte]| {
+            invoke(TCBConfigure, target_slot, args, extra_caps)
+        };
+        assert_eq!(
+            configure(&[7, 0, 0, 0x1000_0400], &[cslot, vslot]),
+            err(SyscallError::TruncatedMessage)
+        );
+        assert_eq!(
+            configure(&[7, 0, 0, 0x1000_0100], &[cslot, vslot, bslot]),
+            err(SyscallError::AlignmentError)
+        );
+        assert_eq!(
+            configure(&[7, 0, 0, 0x1000_0400], &[cslot, vslot, cslot]),
+            err(SyscallError::IllegalOperation)
+        );
+        assert_eq!(
+            configure(&[7, 0, 0, 0x1000_0400], &[bslot, vslot, bslot]),
+            err(SyscallError::IllegalOperation)
+        );
+        assert_eq!(
+            configure(&[7, 0, 0, 0x1000_0400], &[cslot, slot(root, 5), bslot]),
+            err(SyscallError::IllegalOperation)
+        );
+        assert_eq!(
+            configure(&[7, 0, 0, 0x1000_0400], &[cslot, vslot, bslot]),
+            Ok(())
+        );
+        assert_eq!(target.fault_handler(), 7);
+        assert_eq!(target.ipc_buffer().raw(), 0x1000_0400);
+        assert_eq!(target.cte(TcbCnodeIndex::CTable).ptr(), cnode_ptr(cspace));
+        assert_eq!(target.cte(TcbCnodeIndex::VTable).ptr(), vspace);
+        assert_eq!(target.cte(TcbCnodeIndex::Buffer).ptr(), page);
+        let cte = unsafe { &*cslot };
+        assert_eq!(cte.next(), target.cte_ptr(TcbCnodeIndex::CTable) as usize);
+
+        assert_eq!(invoke(TCBSetIPCBuffer, target_slot, &[0], &[cslot]), Ok(()));
+        assert!(target.cte(TcbCnodeIndex::Buffer).is_null());
+        assert_eq!(target.ipc_buffer().raw(), 0);
+
+        assert_eq!(
+            invoke(TCBSetSpace, target_slot, &[0, 0, 0], &[cslot]),
+            err(SyscallError::TruncatedMessage)
+        );
+        // guard 为 1，大小为 1 位的 CSpace
+        let data = (1 << 6) | 1;
+        assert_eq!(
+            invoke(TCBSetSpace, target_slot, &[0, data, 0], &[cslot, vslot]),
+            Ok(())
+        );
+        assert_eq!(target.fault_handler(), 0);
+        match target.cte(TcbCnodeIndex::CTable).view() {
+            CapView::CnodeCap(cap) => assert_eq!(cap.get_cap_c_node_guard_size(), 1),
+            _ => panic!("expected cnode cap"),
+        }
+    }
+
+    #[test]
+    fn bind_and_unbind_notification() {
+        use InvocationLabel::*;
+
+        let root = cnode_cap(4, 0, 0);
+        let (tcb, other) = (alloc_tcb(), alloc_tcb());
+        let tcb_slot = thread_slot(root, 1, tcb);
+        let other_slot = thread_slot(root, 2, other);
+        let ntfn = alloc_object(NOTIFICATION_BITS);
+        write(root, 3, NotificationCap::new(0, true, true, ntfn).into());
+        write(root, 4, NotificationCap::new(0, false, true, ntfn).into());
+        let illegal = err(SyscallError::IllegalOperation);
+
+        assert_eq!(invoke(TCBUnbindNotification, tcb_slot, &[], &[]), illegal);
+        assert_eq!(
+            invoke(TCBBindNotification, tcb_slot, &[], &[]),
+            err(SyscallError::TruncatedMessage)
+        );
+        assert_eq!(
+            invoke(TCBBindNotification, tcb_slot, &[], &[slot(root, 4)]),
+            illegal
+        );
+        assert_eq!(
+            invoke(TCBBindNotification, tcb_slot, &[], &[slot(root, 3)]),
+            Ok(())
+        );
+        let ntfn = unsafe { &*(ntfn as *const Notification) };
+        assert_eq!(ntfn.get_ntfn_bound_tcb(), tcb.ptr());
+        assert_eq!(tcb.bound_notification() as usize, ntfn as *const _ as usize);
+        assert_eq!(
+            invoke(TCBBindNotification, tcb_slot, &[], &[slot(root, 3)]),
+            illegal
+        );
+        assert_eq!(
+            invoke(TCBBindNotification, other_slot, &[], &[slot(root, 3)]),
+            illegal
+        );
+
+        assert_eq!(invoke(TCBUnbindNotification, tcb_slot, &[], &[]), Ok(()));
+        assert!(tcb.bound_notification().is_null());
+        assert_eq!(ntfn.get_ntfn_bound_tcb(), 0);
+    }
 }
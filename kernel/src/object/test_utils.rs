@@ -0,0 +1,49 @@
+//! 主机上单元测试使用的辅助函数
+
+use std::alloc::{alloc_zeroed, Layout};
+
+use super::{
+    cap::{slot_ptr, Cap, CapView, Cte, SLOT_BITS},
+    structures::CnodeCap,
+    tcb::{TCB, TCB_OFFSET},
+};
+use crate::arch::TCB_BITS;
+
+/// 分配一个按照大小对齐并清零的内核对象，不会释放
+pub fn alloc_object(size_bits: usize) -> usize {
+    let layout = Layout::from_size_align(bit!(size_bits), bit!(size_bits)).unwrap();
+    let ptr = unsafe { alloc_zeroed(layout) };
+    assert!(!ptr.is_null());
+    ptr as usize
+}
+
+/// 分配一个新创建的 TCB
+pub fn alloc_tcb() -> &'static mut TCB {
+    let tcb = unsafe { TCB::from_ptr(alloc_object(TCB_BITS) + TCB_OFFSET) };
+    *tcb = TCB::new();
+    tcb
+}
+
+/// 分配一个全部为空 slot 的 CNode
+pub fn alloc_cnode(radix: usize) -> usize {
+    alloc_object(radix + SLOT_BITS)
+}
+
+pub fn cnode_cap(radix: usize, guard_size: usize, guard: usize) -> Cap {
+    CnodeCap::new(radix, guard_size, guard, alloc_cnode(radix)).into()
+}
+
+pub fn cnode_ptr(cap: Cap) -> usize {
+    match cap.view() {
+        CapView::CnodeCap(cap) => cap.get_cap_c_node_ptr(),
+        _ => unreachable!(),
+    }
+}
+
+pub fn slot(cnode: Cap, pos: usize) -> *mut Cte {
+    slot_ptr(cnode_ptr(cnode), pos)
+}
+
+pub fn write(cnode: Cap, pos: usize, cap: Cap) {
+    unsafe { **slot(cnode, pos) = cap };
+}
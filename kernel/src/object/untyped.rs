@@ -0,0 +1,521 @@
+//! Untyped
+//!
+//! untyped capability 中的 free index 记录区域中已经使用的部分，以 2^[MIN_UNTYPED_BITS] 字节为单位。
+//! 所有内核对象都通过 `Untyped_Retype` 从 untyped 中创建，对应 seL4 `object/untyped.c`。
+
+use sel4_types::{invocation::InvocationLabel, object::ObjectType};
+
+use super::{
+    cap::{Cap, CapView, Cte},
+    cnode::{ensure_no_children, insert_new_cap, CNode},
+    cspace::lookup_target_slot,
+    fault::{LookupFault, ThreadStateType},
+    objecttype::{create_object, object_size_bits},
+};
+use crate::{
+    api::failures::{Exception, SyscallError},
+    arch::{clean_dcache_poc, MAX_UNTYPED_BITS, MIN_UNTYPED_BITS},
+    config::{RESET_CHUNK_BITS, RETYPE_FAN_OUT_LIMIT},
+    kernel::thread::{cur_thread, set_thread_state},
+    model::preemption::preemption_point,
+};
+
+const WORD_BITS: usize = usize::BITS as usize;
+
+/// free index 对应的字节偏移，对应 seL4 的 `FREE_INDEX_TO_OFFSET`
+pub const fn free_index_to_offset(free_index: usize) -> usize {
+    free_index << MIN_UNTYPED_BITS
+}
+
+/// 字节偏移对应的 free index，对应 seL4 的 `OFFSET_TO_FREE_INDEX`
+pub const fn offset_to_free_index(offset: usize) -> usize {
+    offset >> MIN_UNTYPED_BITS
+}
+
+/// 整个区域都已经使用时的 free index，对应 seL4 的 `MAX_FREE_INDEX`
+pub const fn max_free_index(size_bits: usize) -> usize {
+    bit!(size_bits - MIN_UNTYPED_BITS)
+}
+
+/// 目标 CNode 中连续的 slot，对应 seL4 的 `slot_range_t`
+#[derive(Debug, Clone, Copy)]
+struct SlotRange {
+    cnode: CNode,
+    offset: usize,
+    length: usize,
+}
+
+/// 解析 untyped 的调用，对应 seL4 的 `decodeUntypedInvocation`
+///
+/// 参数依次为对象类型、对象大小、目标 CNode 的 index 和 depth、目标 slot 的起始位置和数量，
+/// depth 为 0 时直接使用 `extra_caps` 中的 CNode
+///
+/// # Safety
+///
+/// slot 需要指向有效的 [Cte]，且其中为 untyped capability
+pub unsafe fn decode_untyped_invocation(
+    label: InvocationLabel,
+    slot: *mut Cte,
+    args: &[usize],
+    extra_caps: &[Cap],
+) -> Result<(), Exception> {
+    if label != InvocationLabel::UntypedRetype {
+        return Err(SyscallError::IllegalOperation.into());
+    }
+    if args.len() < 6 || extra_caps.is_empty() {
+        return Err(SyscallError::TruncatedMessage.into());
+    }
+    let (new_type, user_obj_size, node_index) = (args[0], args[1], args[2]);
+    let (node_depth, node_offset, node_window) = (args[3], args[4], args[5]);
+    let root = extra_caps[0];
+
+    let Some(new_type) = ObjectType::from_raw(new_type) else {
+        return Err(SyscallError::InvalidArgument { number: 0 }.into());
+    };
+    let size_error = SyscallError::RangeError {
+        min: 0,
+        max: MAX_UNTYPED_BITS,
+    };
+    if user_obj_size >= WORD_BITS - 1 {
+        return Err(size_error.into());
+    }
+    let object_size = object_size_bits(new_type, user_obj_size);
+    if object_size > MAX_UNTYPED_BITS {
+        return Err(size_error.into());
+    }
+    if new_type == ObjectType::CapTable && user_obj_size == 0 {
+        return Err(SyscallError::InvalidArgument { number: 1 }.into());
+    }
+    if new_type == ObjectType::Untyped && user_obj_size < MIN_UNTYPED_BITS {
+        return Err(SyscallError::InvalidArgument { number: 1 }.into());
+    }
+
+    let node_cap = if node_depth == 0 {
+        root
+    } else {
+        unsafe { **lookup_target_slot(root, node_index, node_depth)? }
+    };
+    let CapView::CnodeCap(node_cap) = node_cap.view() else {
+        return Err(SyscallError::FailedLookup {
+            was_source: false,
+            fault: LookupFault::MissingCapability {
+                bits_left: node_depth as u8,
+            },
+        }
+        .into());
+    };
+    let dest_cnode = CNode::from_cap(&node_cap);
+
+    let node_size = dest_cnode.len();
+    if node_offset > node_size - 1 {
+        return Err(SyscallError::RangeError {
+            min: 0,
+            max: node_size - 1,
+        }
+        .into());
+    }
+    if !(1..=RETYPE_FAN_OUT_LIMIT).contains(&node_window) {
+        return Err(SyscallError::RangeError {
+            min: 1,
+            max: RETYPE_FAN_OUT_LIMIT,
+        }
+        .into());
+    }
+    if node_window > node_size - node_offset {
+        return Err(SyscallError::RangeError {
+            min: 1,
+            max: node_size - node_offset,
+        }
+        .into());
+    }
+    let dest_slots = unsafe { &dest_cnode.slots()[node_offset..node_offset + node_window] };
+    if dest_slots.iter().any(|slot| !slot.is_null()) {
+        return Err(SyscallError::DeleteFirst.into());
+    }
+
+    let CapView::UntypedCap(cap) = (unsafe { **slot }).view() else {
+        panic!("decodeUntypedInvocation: expected untyped cap");
+    };
+    // 没有子节点时 untyped 中的对象都已经删除，reset 之后从头开始使用
+    let reset = ensure_no_children(unsafe { &*slot }).is_ok();
+    let free_index = if reset { 0 } else { cap.get_cap_free_index() };
+    let free_ref = cap.get_cap_ptr() + free_index_to_offset(free_index);
+    let untyped_free_bytes = bit!(cap.get_cap_block_size()) - free_index_to_offset(free_index);
+    if object_size >= WORD_BITS || node_window > untyped_free_bytes >> object_size {
+        return Err(SyscallError::NotEnoughMemory {
+            memory_left: untyped_free_bytes,
+        }
+        .into());
+    }
+
+    let device_memory = cap.get_cap_is_device() != 0;
+    if device_memory && !new_type.is_frame() && new_type != ObjectType::Untyped {
+        return Err(SyscallError::InvalidArgument { number: 1 }.into());
+    }
+
+    // 区域的结尾按照 untyped 的大小对齐，按照对象大小向上对齐之后剩余的空间仍然足够
+    let aligned_free_ref = free_ref.next_multiple_of(bit!(object_size));
+
+    // reset 被抢占时线程保持 Restart 状态，重新执行时从 free index 记录的位置继续清零
+    set_thread_state(unsafe { &mut *cur_thread() }, ThreadStateType::Restart);
+    let dest = SlotRange {
+        cnode: dest_cnode,
+        offset: node_offset,
+        length: node_window,
+    };
+    unsafe {
+        invoke_untyped_retype(
+            slot,
+            reset,
+            aligned_free_ref,
+            new_type,
+            user_obj_size,
+            dest,
+            device_memory,
+        )
+    }
+}
+
+/// 对应 seL4 的 `invokeUntyped_Retype`
+unsafe fn invoke_untyped_retype(
+    src_slot: *mut Cte,
+    reset: bool,
+    retype_base: usize,
+    new_type: ObjectType,
+    user_size: usize,
+    dest: SlotRange,
+    device_memory: bool,
+) -> Result<(), Exception> {
+    if reset {
+        unsafe { reset_untyped_cap(src_slot)? };
+    }
+
+    let cte = unsafe { &mut *src_slot };
+    let CapView::UntypedCap(mut cap) = cte.view() else {
+        panic!("invokeUntyped_Retype: expected untyped cap");
+    };
+    let region_base = cap.get_cap_ptr();
+    let total_object_size = dest.length << object_size_bits(new_type, user_size);
+    let free_ref = retype_base + total_object_size;
+    debug_assert!(free_ref <= region_base + bit!(cap.get_cap_block_size()));
+    cap.set_cap_free_index(offset_to_free_index(free_ref - region_base));
+    **cte = cap.into();
+
+    unsafe {
+        create_new_objects(
+            new_type,
+            src_slot,
+            dest,
+            retype_base,
+            user_size,
+            device_memory,
+        )
+    };
+    Ok(())
+}
+
+/// 清零 untyped 中已经使用的部分并将 free index 重置为 0，对应 seL4 的 `resetUntypedCap`
+///
+/// 从高地址开始每次清零 2^[RESET_CHUNK_BITS] 字节并检查抢占，被抢占时 free index
+/// 记录已经清零的位置，重新执行时从该位置继续。设备内存不会被清零
+unsafe fn reset_untyped_cap(src_slot: *mut Cte) -> Result<(), Exception> {
+    let cte = unsafe { &mut *src_slot };
+    let CapView::UntypedCap(mut cap) = cte.view() else {
+        panic!("resetUntypedCap: expected untyped cap");
+    };
+    let block_size = cap.get_cap_block_size();
+    let region_base = cap.get_cap_ptr();
+    let offset = free_index_to_offset(cap.get_cap_free_index());
+    let device_memory = cap.get_cap_is_device() != 0;
+
+    if offset == 0 {
+        return Ok(());
+    }
+
+    if device_memory || block_size < RESET_CHUNK_BITS {
+        if !device_memory {
+            unsafe { clear_memory(region_base, block_size) };
+        }
+        cap.set_cap_free_index(0);
+        **cte = cap.into();
+        return Ok(());
+    }
+
+    for offset in (0..offset).step_by(bit!(RESET_CHUNK_BITS)).rev() {
+        unsafe { clear_memory(region_base + offset, RESET_CHUNK_BITS) };
+        cap.set_cap_free_index(offset_to_free_index(offset));
+        **cte = cap.into();
+        preemption_point()?;
+    }
+    Ok(())
+}
+
+/// 在 `region_base` 开始连续创建 `dest.length` 个对象，并作为 `parent` 的子节点插入到目标 slot 中，
+/// 对应 seL4 的 `createNewObjects`
+unsafe fn create_new_objects(
+    new_type: ObjectType,
+    parent: *mut Cte,
+    dest: SlotRange,
+    region_base: usize,
+    user_size: usize,
+    device_memory: bool,
+) {
+    let object_size = object_size_bits(new_type, user_size);
+    for i in 0..dest.length {
+        let cap = unsafe {
+            create_object(
+                new_type,
+                region_base + (i << object_size),
+                user_size,
+                device_memory,
+            )
+        };
+        let slot = dest.cnode.slot_ptr(dest.offset + i).unwrap();
+        unsafe { insert_new_cap(parent, slot, cap) };
+    }
+}
+
+/// 清零 `ptr` 开始的 2^`bits` 字节并写回到 PoC，对应 seL4 的 `clearMemory`
+///
+/// # Safety
+///
+/// `ptr` 需要指向未被使用的内存
+pub unsafe fn clear_memory(ptr: usize, bits: usize) {
+    unsafe { core::ptr::write_bytes(ptr as *mut u8, 0, bit!(bits)) };
+    clean_dcache_poc(va!(ptr), va!(ptr + bit!(bits)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arch::{set_irq_pending, TCB_BITS},
+        config::MAX_NUM_WORK_UNITS_PER_PREEMPTION,
+        model::preemption::reset_work_units,
+        object::{
+            cap::write_slot,
+            cnode::decode_cnode_invocation,
+            ipc::ENDPOINT_BITS,
+            mdb_check::check_list,
+            structures::{CnodeCap, UntypedCap},
+            tcb::TCB_OFFSET,
+            test_utils::{alloc_object, cnode_cap, slot},
+        },
+    };
+    use InvocationLabel::*;
+
+    const UNTYPED_OBJECT: usize = ObjectType::Untyped as usize;
+    const TCB_OBJECT: usize = ObjectType::TCB as usize;
+    const ENDPOINT_OBJECT: usize = ObjectType::Endpoint as usize;
+    const CAP_TABLE_OBJECT: usize = ObjectType::CapTable as usize;
+    const SMALL_PAGE_OBJECT: usize = ObjectType::SmallPage as usize;
+    const UT_BITS: usize = 12;
+
+    /// root CNode 的第 1 个 slot 中为启动时创建的 untyped，初始时已经用完
+    fn setup(is_device: bool) -> (Cap, usize) {
+        let root = cnode_cap(4, 0, 0);
+        let base = alloc_object(UT_BITS);
+        let ut = UntypedCap::new(max_free_index(UT_BITS), is_device, UT_BITS, base);
+        unsafe { write_slot(slot(root, 1), ut.into()) };
+        (root, base)
+    }
+
+    fn retype(root: Cap, args: &[usize]) -> Result<(), Exception> {
+        unsafe { decode_untyped_invocation(UntypedRetype, slot(root, 1), args, &[root]) }
+    }
+
+    fn untyped(root: Cap) -> UntypedCap {
+        match unsafe { **slot(root, 1) }.view() {
+            CapView::UntypedCap(cap) => cap,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn retype_and_reset() {
+        let (root, base) = setup(false);
+        unsafe { core::ptr::write_bytes(base as *mut u8, 0xaa, bit!(UT_BITS)) };
+
+        retype(root, &[ENDPOINT_OBJECT, 0, 0, 0, 4, 3]).unwrap();
+        assert_eq!(
+            untyped(root).get_cap_free_index(),
+            offset_to_free_index(3 << ENDPOINT_BITS)
+        );
+        for i in 0..3 {
+            let cap = unsafe { **slot(root, 4 + i) };
+            assert!(matches!(cap.view(), CapView::EndpointCap(_)));
+            assert_eq!(cap.ptr(), base + (i << ENDPOINT_BITS));
+            let cte = unsafe { &*slot(root, 4 + i) };
+            assert!(cte.revocable() && cte.first_badge());
+        }
+        // reset 清零了之前使用过的整个区域
+        assert!((0..bit!(UT_BITS)).all(|i| unsafe { *((base + i) as *const u8) } == 0));
+        assert_eq!(check_list(unsafe { &*slot(root, 1) }), Ok(()));
+
+        // 还有子节点时从 free index 继续，按照对象大小对齐，free index 之后的内存在 reset 时已经清零
+        let tcb_base = base + bit!(TCB_BITS);
+        retype(root, &[TCB_OBJECT, 0, 0, 0, 7, 1]).unwrap();
+        assert!((0..TCB_OFFSET).all(|i| unsafe { *((tcb_base + i) as *const u8) } == 0));
+        match unsafe { **slot(root, 7) }.view() {
+            CapView::ThreadCap(tcb) => {
+                assert_eq!(tcb.get_cap_tcb_ptr(), tcb_base + TCB_OFFSET)
+            }
+            _ => panic!("expected thread cap"),
+        }
+        assert_eq!(untyped(root).get_cap_free_index(), max_free_index(UT_BITS));
+        assert_eq!(
+            retype(root, &[ENDPOINT_OBJECT, 0, 0, 0, 8, 1]),
+            Err(SyscallError::NotEnoughMemory { memory_left: 0 }.into())
+        );
+
+        // 删除所有子节点之后重新从头开始
+        decode_cnode_invocation(CNodeRevoke, cnode(root), &[1, 4], &[]).unwrap();
+        retype(root, &[ENDPOINT_OBJECT, 0, 0, 0, 8, 1]).unwrap();
+        assert_eq!(unsafe { **slot(root, 8) }.ptr(), base);
+    }
+
+    #[test]
+    fn preempted_reset_resumes() {
+        let root = cnode_cap(4, 0, 0);
+        let ut_bits = 16;
+        let base = alloc_object(ut_bits);
+        let ut = UntypedCap::new(max_free_index(ut_bits), false, ut_bits, base);
+        unsafe { write_slot(slot(root, 1), ut.into()) };
+        unsafe { core::ptr::write_bytes(base as *mut u8, 0xaa, bit!(ut_bits)) };
+
+        reset_work_units();
+        set_irq_pending(true);
+        assert_eq!(
+            retype(root, &[ENDPOINT_OBJECT, 0, 0, 0, 4, 1]),
+            Err(Exception::Preempted)
+        );
+        assert_eq!(
+            unsafe { (*cur_thread()).state().ts_type() },
+            ThreadStateType::Restart
+        );
+        // 从高地址开始清零，free index 记录已经清零的位置
+        let chunks = bit!(ut_bits - RESET_CHUNK_BITS) - MAX_NUM_WORK_UNITS_PER_PREEMPTION;
+        let cleared = chunks << RESET_CHUNK_BITS;
+        assert_eq!(
+            untyped(root).get_cap_free_index(),
+            offset_to_free_index(cleared)
+        );
+        assert!(unsafe { (*slot(root, 4)).is_null() });
+        assert_eq!(unsafe { *((base + cleared - 1) as *const u8) }, 0xaa);
+
+        set_irq_pending(false);
+        retype(root, &[ENDPOINT_OBJECT, 0, 0, 0, 4, 1]).unwrap();
+        assert_eq!(unsafe { **slot(root, 4) }.ptr(), base);
+        assert!((0..bit!(ut_bits)).all(|i| unsafe { *((base + i) as *const u8) } == 0));
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let (root, _) = setup(false);
+        assert_eq!(
+            unsafe { decode_untyped_invocation(CNodeCopy, slot(root, 1), &[], &[]) },
+            Err(SyscallError::IllegalOperation.into())
+        );
+        assert_eq!(
+            retype(root, &[ENDPOINT_OBJECT, 0, 0, 0, 4]),
+            Err(SyscallError::TruncatedMessage.into())
+        );
+        assert_eq!(
+            retype(
+                root,
+                &[sel4_types::object::OBJECT_TYPE_COUNT, 0, 0, 0, 4, 1]
+            ),
+            Err(SyscallError::InvalidArgument { number: 0 }.into())
+        );
+        assert_eq!(
+            retype(root, &[UNTYPED_OBJECT, MAX_UNTYPED_BITS + 1, 0, 0, 4, 1]),
+            Err(SyscallError::RangeError {
+                min: 0,
+                max: MAX_UNTYPED_BITS
+            }
+            .into())
+        );
+        assert_eq!(
+            retype(root, &[CAP_TABLE_OBJECT, WORD_BITS - 1, 0, 0, 4, 1]),
+            Err(SyscallError::RangeError {
+                min: 0,
+                max: MAX_UNTYPED_BITS
+            }
+            .into())
+        );
+        assert_eq!(
+            retype(root, &[CAP_TABLE_OBJECT, 0, 0, 0, 4, 1]),
+            Err(SyscallError::InvalidArgument { number: 1 }.into())
+        );
+        assert_eq!(
+            retype(root, &[UNTYPED_OBJECT, 3, 0, 0, 4, 1]),
+            Err(SyscallError::InvalidArgument { number: 1 }.into())
+        );
+        assert_eq!(
+            retype(root, &[ENDPOINT_OBJECT, 0, 0, 0, 16, 1]),
+            Err(SyscallError::RangeError { min: 0, max: 15 }.into())
+        );
+        assert_eq!(
+            retype(root, &[ENDPOINT_OBJECT, 0, 0, 0, 14, 3]),
+            Err(SyscallError::RangeError { min: 1, max: 2 }.into())
+        );
+        assert_eq!(
+            retype(root, &[ENDPOINT_OBJECT, 0, 0, 0, 0, 2]),
+            Err(SyscallError::DeleteFirst.into())
+        );
+        assert_eq!(
+            retype(root, &[ENDPOINT_OBJECT, 0, 1, 4, 4, 1]),
+            Err(SyscallError::FailedLookup {
+                was_source: false,
+                fault: LookupFault::MissingCapability { bits_left: 4 }
+            }
+            .into())
+        );
+        assert_eq!(
+            retype(root, &[UNTYPED_OBJECT, UT_BITS + 1, 0, 0, 4, 1]),
+            Err(SyscallError::NotEnoughMemory {
+                memory_left: bit!(UT_BITS)
+            }
+            .into())
+        );
+    }
+
+    #[test]
+    fn device_untyped_only_frames() {
+        let (root, base) = setup(true);
+        assert_eq!(
+            retype(root, &[ENDPOINT_OBJECT, 0, 0, 0, 4, 1]),
+            Err(SyscallError::InvalidArgument { number: 1 }.into())
+        );
+        retype(root, &[SMALL_PAGE_OBJECT, 0, 0, 0, 4, 1]).unwrap();
+        match unsafe { **slot(root, 4) }.view() {
+            CapView::FrameCap(frame) => {
+                assert_eq!(frame.get_cap_f_base_ptr(), base);
+                assert_eq!(frame.get_cap_f_is_device(), 1);
+            }
+            _ => panic!("expected frame cap"),
+        }
+    }
+
+    #[test]
+    fn copy_marks_untyped_full() {
+        let (root, _) = setup(false);
+        retype(root, &[ENDPOINT_OBJECT, 0, 0, 0, 4, 1]).unwrap();
+        decode_cnode_invocation(CNodeRevoke, cnode(root), &[1, 4], &[]).unwrap();
+
+        // 复制之后只能通过副本创建对象
+        decode_cnode_invocation(CNodeCopy, cnode(root), &[2, 4, 1, 4, 0xf], &[root]).unwrap();
+        assert_eq!(untyped(root).get_cap_free_index(), max_free_index(UT_BITS));
+        assert_eq!(
+            retype(root, &[ENDPOINT_OBJECT, 0, 0, 0, 4, 1]),
+            Err(SyscallError::NotEnoughMemory { memory_left: 0 }.into())
+        );
+    }
+
+    fn cnode(cap: Cap) -> CnodeCap {
+        match cap.view() {
+            CapView::CnodeCap(cap) => cap,
+            _ => unreachable!(),
+        }
+    }
+}
@@ -1,6 +1,28 @@
-use crate::arch::{PPTR_BASE, PPTR_TOP};
+use crate::arch::{PhysAddr, PhysAddrRange, VirtAddr, KDEV_BASE, PPTR_BASE, PPTR_TOP};
 
 /// 平台物理内存起始地址
 pub const PADDR_BASE: usize = 0;
 /// 物理内存 TOP 地址
 pub const PADDR_TOP: usize = PPTR_TOP - PPTR_BASE;
+/// 交给用户的设备内存的最高地址
+pub const PADDR_USER_DEVICE_TOP: usize = bit!(40);
+
+// 没有 DTB 时使用的默认值，与 `qemu-system-aarch64 -machine virt -m 1G` 一致
+
+/// 默认物理内存
+pub const DEFAULT_MEMORY: PhysAddrRange = PhysAddrRange::new(pa!(0x4000_0000), pa!(0x8000_0000));
+/// 默认 PL011 UART 地址
+pub const DEFAULT_UART_PADDR: PhysAddr = pa!(0x0900_0000);
+/// 默认 GIC Distributor 地址
+pub const DEFAULT_GIC_DIST_PADDR: PhysAddr = pa!(0x0800_0000);
+/// 默认 GIC CPU Interface 地址
+pub const DEFAULT_GIC_CPU_PADDR: PhysAddr = pa!(0x0801_0000);
+/// 默认 Generic Timer 中断号，依次为 secure、non-secure、virtual 和 hypervisor 物理计时器
+pub const DEFAULT_TIMER_IRQS: [usize; 4] = [29, 30, 27, 26];
+
+/// PL011 UART 在内核中的虚拟地址，对应 seL4 的 `UART_PPTR`
+pub const UART_PPTR: VirtAddr = va!(KDEV_BASE);
+/// GIC Distributor 在内核中的虚拟地址，对应 seL4 的 `GIC_V2_DISTRIBUTOR_PPTR`
+pub const GIC_DIST_PPTR: VirtAddr = va!(KDEV_BASE + 0x1000);
+/// GIC CPU Interface 在内核中的虚拟地址，占用两个页，对应 seL4 的 `GIC_V2_CONTROLLER_PPTR`
+pub const GIC_CPU_PPTR: VirtAddr = va!(KDEV_BASE + 0x2000);
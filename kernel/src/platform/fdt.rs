@@ -0,0 +1,671 @@
+//! Flattened Device Tree 解析
+//!
+//! 只实现启动阶段需要的只读解析，格式参考 Devicetree Specification v0.4 第五章，
+//! QEMU `-machine virt,dumpdtb` 导出的文件即为该格式。
+
+use core::{ffi::CStr, slice};
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+/// 支持的最低版本，`last_comp_version` 需要不大于该值
+const FDT_LAST_COMP_VERSION: u32 = 16;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+/// 节点嵌套的最大深度
+const FDT_MAX_DEPTH: usize = 16;
+
+/// 未声明 `#address-cells` 时的默认值
+const DEFAULT_ADDRESS_CELLS: u32 = 2;
+/// 未声明 `#size-cells` 时的默认值
+const DEFAULT_SIZE_CELLS: u32 = 1;
+
+/// FDT 解析错误
+#[derive(PartialEq, Debug)]
+pub enum FdtError {
+    /// 头部的 magic 不正确
+    BadMagic,
+    /// 不兼容的版本
+    BadVersion,
+    /// 头部中的偏移超出了 DTB 的大小
+    Truncated,
+    /// 结构块中出现了未知的 token，或者节点的开始和结束不匹配
+    BadToken,
+    /// 节点嵌套超过了 [FDT_MAX_DEPTH]
+    TooDeep,
+}
+
+/// 读取大端序的 u32
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+/// 读取大端序的 u64
+fn be64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + 8)?;
+    Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+/// 读取 `cells` 个 u32 组成的数字，超过两个 cell 时只保留低 64 位
+fn read_cells(data: &[u8], offset: usize, cells: u32) -> Option<u64> {
+    (0..cells as usize).try_fold(0u64, |value, i| {
+        Some(value.checked_shl(32).unwrap_or(0) | be32(data, offset + i * 4)? as u64)
+    })
+}
+
+/// 读取以 `\0` 结尾的字符串
+fn read_str(data: &[u8], offset: usize) -> Option<&str> {
+    CStr::from_bytes_until_nul(data.get(offset..)?)
+        .ok()?
+        .to_str()
+        .ok()
+}
+
+/// 扁平设备树
+///
+/// ```plain
+/// struct fdt_header {
+///     uint32_t magic;
+///     uint32_t totalsize;
+///     uint32_t off_dt_struct;
+///     uint32_t off_dt_strings;
+///     uint32_t off_mem_rsvmap;
+///     uint32_t version;
+///     uint32_t last_comp_version;
+///     uint32_t boot_cpuid_phys;
+///     uint32_t size_dt_strings;
+///     uint32_t size_dt_struct;
+/// };
+/// ```
+#[derive(Clone, Copy)]
+pub struct Fdt<'a> {
+    mem_rsvmap: &'a [u8],
+    structs: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> Fdt<'a> {
+    /// 从 `data` 中解析 FDT 头部
+    pub fn new(data: &'a [u8]) -> Result<Self, FdtError> {
+        let header = |idx: usize| be32(data, idx * 4).ok_or(FdtError::Truncated);
+        if header(0)? != FDT_MAGIC {
+            return Err(FdtError::BadMagic);
+        }
+        if header(6)? > FDT_LAST_COMP_VERSION {
+            return Err(FdtError::BadVersion);
+        }
+        let total_size = header(1)? as usize;
+        let off_struct = header(2)? as usize;
+        let off_strings = header(3)? as usize;
+        let off_rsvmap = header(4)? as usize;
+        let size_strings = header(8)? as usize;
+        let size_struct = header(9)? as usize;
+
+        let data = data.get(..total_size).ok_or(FdtError::Truncated)?;
+        let fdt = Self {
+            mem_rsvmap: data.get(off_rsvmap..).ok_or(FdtError::Truncated)?,
+            structs: data
+                .get(off_struct..off_struct + size_struct)
+                .ok_or(FdtError::Truncated)?,
+            strings: data
+                .get(off_strings..off_strings + size_strings)
+                .ok_or(FdtError::Truncated)?,
+        };
+        fdt.check_structs()?;
+        Ok(fdt)
+    }
+
+    /// 检查结构块中的 token，之后遍历节点时不会再遇到错误的 token
+    fn check_structs(&self) -> Result<(), FdtError> {
+        if self.token(0) != Some(FDT_BEGIN_NODE) {
+            return Err(FdtError::BadToken);
+        }
+        let mut offset = 0;
+        let mut depth = 0;
+        loop {
+            match self.token(offset).ok_or(FdtError::Truncated)? {
+                FDT_BEGIN_NODE => {
+                    if depth == FDT_MAX_DEPTH {
+                        return Err(FdtError::TooDeep);
+                    }
+                    let name = read_str(self.structs, offset + 4).ok_or(FdtError::Truncated)?;
+                    offset = (offset + 4 + name.len() + 1).next_multiple_of(4);
+                    depth += 1;
+                }
+                FDT_PROP => offset = self.property(offset).ok_or(FdtError::Truncated)?.2,
+                FDT_END_NODE => {
+                    depth = depth.checked_sub(1).ok_or(FdtError::BadToken)?;
+                    offset += 4;
+                }
+                FDT_NOP => offset += 4,
+                FDT_END if depth == 0 => return Ok(()),
+                _ => return Err(FdtError::BadToken),
+            }
+        }
+    }
+
+    /// 从指针处解析 FDT
+    ///
+    /// # Safety
+    ///
+    /// `ptr` 开始的 `size` 字节需要可读，并且在 [Fdt] 使用期间保持不变
+    pub unsafe fn from_ptr(ptr: *const u8, size: usize) -> Result<Self, FdtError> {
+        Self::new(unsafe { slice::from_raw_parts(ptr, size) })
+    }
+
+    /// `/memreserve/` 中的保留内存，返回 `(address, size)`
+    pub fn memory_reservations(&self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        let data = self.mem_rsvmap;
+        (0..)
+            .map(move |i| Some((be64(data, i * 16)?, be64(data, i * 16 + 8)?)))
+            .take_while(|entry| !matches!(entry, None | Some((0, 0))))
+            .flatten()
+    }
+
+    /// 深度优先遍历所有节点
+    pub fn nodes(&self) -> NodeIter<'a> {
+        NodeIter {
+            fdt: *self,
+            offset: 0,
+            depth: 0,
+            scopes: [NodeScope {
+                cells: (DEFAULT_ADDRESS_CELLS, DEFAULT_SIZE_CELLS),
+                interrupt_parent: None,
+                props: 0,
+            }; FDT_MAX_DEPTH],
+        }
+    }
+
+    /// 查找第一个 `compatible` 包含 `compatibles` 中任意一项的节点
+    pub fn find_compatible(&self, compatibles: &[&str]) -> Option<FdtNode<'a>> {
+        self.nodes()
+            .find(|node| compatibles.iter().any(|c| node.is_compatible(c)))
+    }
+
+    /// 查找 `phandle` 对应的节点
+    pub fn find_phandle(&self, phandle: u32) -> Option<FdtNode<'a>> {
+        self.nodes()
+            .find(|node| node.property_u32("phandle") == Some(phandle))
+    }
+
+    /// 读取结构块中 `offset` 处的 token
+    fn token(&self, offset: usize) -> Option<u32> {
+        be32(self.structs, offset)
+    }
+
+    /// 读取 `offset` 处的属性，返回属性名，属性值以及下一个 token 的偏移
+    fn property(&self, offset: usize) -> Option<(&'a str, &'a [u8], usize)> {
+        let len = be32(self.structs, offset + 4)? as usize;
+        let name_off = be32(self.structs, offset + 8)? as usize;
+        let value = self.structs.get(offset + 12..offset + 12 + len)?;
+        let name = read_str(self.strings, name_off)?;
+        Some((name, value, (offset + 12 + len).next_multiple_of(4)))
+    }
+
+    /// 从 `offset` 开始的属性，遇到子节点或者节点结束时停止
+    fn properties(&self, mut offset: usize) -> impl Iterator<Item = (&'a str, &'a [u8])> + 'a {
+        let fdt = *self;
+        core::iter::from_fn(move || loop {
+            match fdt.token(offset)? {
+                FDT_PROP => {
+                    let (name, value, next) = fdt.property(offset)?;
+                    offset = next;
+                    return Some((name, value));
+                }
+                FDT_NOP => offset += 4,
+                _ => return None,
+            }
+        })
+    }
+}
+
+/// 设备树节点
+#[derive(Clone, Copy)]
+pub struct FdtNode<'a> {
+    fdt: Fdt<'a>,
+    /// 节点名称，包含 `@` 后的单元地址
+    pub name: &'a str,
+    /// 节点深度，根节点为 0
+    pub depth: usize,
+    /// 第一个属性的偏移
+    props: usize,
+    /// 父节点的 `#address-cells` 和 `#size-cells`
+    parent_cells: (u32, u32),
+    /// 节点自身或者最近的祖先节点声明的 `interrupt-parent`
+    interrupt_parent: Option<u32>,
+    /// 父节点第一个属性的偏移，根节点没有父节点
+    parent_props: Option<usize>,
+}
+
+impl<'a> FdtNode<'a> {
+    /// 节点的所有属性
+    pub fn properties(&self) -> impl Iterator<Item = (&'a str, &'a [u8])> + 'a {
+        self.fdt.properties(self.props)
+    }
+
+    /// 获取名称为 `name` 的属性值
+    pub fn property(&self, name: &str) -> Option<&'a [u8]> {
+        self.properties().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    /// 获取字符串类型的属性
+    pub fn property_str(&self, name: &str) -> Option<&'a str> {
+        read_str(self.property(name)?, 0)
+    }
+
+    /// 获取 u32 类型的属性
+    pub fn property_u32(&self, name: &str) -> Option<u32> {
+        be32(self.property(name)?, 0)
+    }
+
+    /// 去掉单元地址后的节点名称
+    pub fn base_name(&self) -> &'a str {
+        self.name.split('@').next().unwrap_or(self.name)
+    }
+
+    /// `compatible` 属性是否包含 `compatible`
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.property("compatible").is_some_and(|value| {
+            value
+                .split(|&c| c == 0)
+                .any(|item| item == compatible.as_bytes())
+        })
+    }
+
+    /// 解析 `reg` 属性，返回 `(address, size)`
+    pub fn reg(&self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        let (address_cells, size_cells) = self.parent_cells;
+        let value = self.property("reg").unwrap_or(&[]);
+        let entry_size = (address_cells + size_cells) as usize * 4;
+        value
+            .chunks_exact(entry_size.max(4))
+            .filter_map(move |entry| {
+                Some((
+                    read_cells(entry, 0, address_cells)?,
+                    read_cells(entry, address_cells as usize * 4, size_cells)?,
+                ))
+            })
+    }
+
+    /// 中断父节点的 `#interrupt-cells`
+    ///
+    /// 中断父节点由 `interrupt-parent` 指定，节点和祖先节点都没有声明时为设备树中的父节点
+    pub fn interrupt_cells(&self) -> Option<u32> {
+        let props = match self.interrupt_parent {
+            Some(phandle) => self.fdt.find_phandle(phandle)?.props,
+            None => self.parent_props?,
+        };
+        let (_, value) = self
+            .fdt
+            .properties(props)
+            .find(|(name, _)| *name == "#interrupt-cells")?;
+        be32(value, 0)
+    }
+
+    /// 获取 `interrupts` 属性中的第 `index` 项，每项的长度为 [FdtNode::interrupt_cells]
+    pub fn interrupt(&self, index: usize) -> Option<&'a [u8]> {
+        let cells = self.interrupt_cells()? as usize;
+        if cells == 0 {
+            return None;
+        }
+        self.property("interrupts")?
+            .chunks_exact(cells * 4)
+            .nth(index)
+    }
+
+    /// 节点自身声明的 `#address-cells` 和 `#size-cells`
+    fn cells(&self) -> (u32, u32) {
+        (
+            self.property_u32("#address-cells")
+                .unwrap_or(DEFAULT_ADDRESS_CELLS),
+            self.property_u32("#size-cells")
+                .unwrap_or(DEFAULT_SIZE_CELLS),
+        )
+    }
+}
+
+/// 遍历时记录的每一层节点的信息，供子节点使用
+#[derive(Clone, Copy)]
+struct NodeScope {
+    /// 节点声明的 `#address-cells` 和 `#size-cells`
+    cells: (u32, u32),
+    /// 节点自身或者最近的祖先节点声明的 `interrupt-parent`
+    interrupt_parent: Option<u32>,
+    /// 节点第一个属性的偏移
+    props: usize,
+}
+
+/// 节点遍历器，按照结构块中的顺序返回每个节点
+pub struct NodeIter<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+    depth: usize,
+    /// 当前节点的所有祖先节点
+    scopes: [NodeScope; FDT_MAX_DEPTH],
+}
+
+impl<'a> Iterator for NodeIter<'a> {
+    type Item = FdtNode<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.fdt.token(self.offset)? {
+                FDT_BEGIN_NODE => {
+                    let name = read_str(self.fdt.structs, self.offset + 4)?;
+                    let props = (self.offset + 4 + name.len() + 1).next_multiple_of(4);
+                    let parent = self.depth.checked_sub(1).map(|depth| self.scopes[depth]);
+                    let mut node = FdtNode {
+                        fdt: self.fdt,
+                        name,
+                        depth: self.depth,
+                        props,
+                        parent_cells: parent
+                            .map_or((DEFAULT_ADDRESS_CELLS, DEFAULT_SIZE_CELLS), |scope| {
+                                scope.cells
+                            }),
+                        interrupt_parent: parent.and_then(|scope| scope.interrupt_parent),
+                        parent_props: parent.map(|scope| scope.props),
+                    };
+                    if let Some(phandle) = node.property_u32("interrupt-parent") {
+                        node.interrupt_parent = Some(phandle);
+                    }
+                    self.scopes[self.depth] = NodeScope {
+                        cells: node.cells(),
+                        interrupt_parent: node.interrupt_parent,
+                        props,
+                    };
+                    self.depth += 1;
+                    self.offset = props;
+                    return Some(node);
+                }
+                FDT_PROP => self.offset = self.fdt.property(self.offset)?.2,
+                FDT_END_NODE => {
+                    self.depth = self.depth.checked_sub(1)?;
+                    self.offset += 4;
+                }
+                FDT_NOP => self.offset += 4,
+                // 结构块结束，其他 token 已经在 [Fdt::new] 中检查过
+                _ => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按照 DTB 格式构造测试数据
+    #[derive(Default)]
+    struct DtbBuilder {
+        reservations: Vec<(u64, u64)>,
+        structs: Vec<u8>,
+        strings: Vec<u8>,
+    }
+
+    impl DtbBuilder {
+        fn token(&mut self, token: u32) -> &mut Self {
+            self.structs.extend(token.to_be_bytes());
+            self
+        }
+
+        fn begin(&mut self, name: &str) -> &mut Self {
+            self.token(FDT_BEGIN_NODE);
+            self.structs.extend(name.as_bytes());
+            self.structs.push(0);
+            self.structs
+                .resize(self.structs.len().next_multiple_of(4), 0);
+            self
+        }
+
+        fn end(&mut self) -> &mut Self {
+            self.token(FDT_END_NODE)
+        }
+
+        fn prop(&mut self, name: &str, value: &[u8]) -> &mut Self {
+            let name_off = self.strings.len() as u32;
+            self.strings.extend(name.as_bytes());
+            self.strings.push(0);
+            self.token(FDT_PROP);
+            self.structs.extend((value.len() as u32).to_be_bytes());
+            self.structs.extend(name_off.to_be_bytes());
+            self.structs.extend(value);
+            self.structs
+                .resize(self.structs.len().next_multiple_of(4), 0);
+            self
+        }
+
+        fn prop_cells(&mut self, name: &str, cells: &[u32]) -> &mut Self {
+            let value: Vec<u8> = cells.iter().flat_map(|c| c.to_be_bytes()).collect();
+            self.prop(name, &value)
+        }
+
+        fn build(&mut self) -> Vec<u8> {
+            let off_rsvmap = 40;
+            let off_struct = off_rsvmap + (self.reservations.len() + 1) * 16;
+            let off_strings = off_struct + self.structs.len() + 4;
+            let total_size = off_strings + self.strings.len();
+            let header = [
+                FDT_MAGIC,
+                total_size as u32,
+                off_struct as u32,
+                off_strings as u32,
+                off_rsvmap as u32,
+                17,
+                16,
+                0,
+                self.strings.len() as u32,
+                self.structs.len() as u32 + 4,
+            ];
+            let mut data: Vec<u8> = header.iter().flat_map(|h| h.to_be_bytes()).collect();
+            for &(addr, size) in self.reservations.iter().chain(&[(0, 0)]) {
+                data.extend(addr.to_be_bytes());
+                data.extend(size.to_be_bytes());
+            }
+            data.extend(&self.structs);
+            data.extend(FDT_END.to_be_bytes());
+            data.extend(&self.strings);
+            data
+        }
+    }
+
+    fn set_header(data: &mut [u8], idx: usize, value: u32) {
+        data[idx * 4..idx * 4 + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    fn simple_dtb() -> Vec<u8> {
+        DtbBuilder::default()
+            .begin("")
+            .prop("model", b"test\0")
+            .end()
+            .build()
+    }
+
+    #[test]
+    fn bad_header() {
+        assert!(Fdt::new(&simple_dtb()).is_ok());
+
+        let mut data = simple_dtb();
+        set_header(&mut data, 0, 0xdead_beef);
+        assert_eq!(Fdt::new(&data).err(), Some(FdtError::BadMagic));
+
+        let mut data = simple_dtb();
+        set_header(&mut data, 6, FDT_LAST_COMP_VERSION + 1);
+        assert_eq!(Fdt::new(&data).err(), Some(FdtError::BadVersion));
+    }
+
+    #[test]
+    fn truncated() {
+        let data = simple_dtb();
+        assert_eq!(Fdt::new(&data[..20]).err(), Some(FdtError::Truncated));
+        assert_eq!(
+            Fdt::new(&data[..data.len() - 1]).err(),
+            Some(FdtError::Truncated)
+        );
+
+        let mut data = simple_dtb();
+        let total_size = data.len() as u32;
+        set_header(&mut data, 9, total_size);
+        assert_eq!(Fdt::new(&data).err(), Some(FdtError::Truncated));
+
+        // 属性的长度超出了结构块
+        let mut data = DtbBuilder::default()
+            .begin("")
+            .prop_cells("reg", &[1])
+            .end()
+            .build();
+        let len_offset = 40 + 16 + 8 + 4;
+        data[len_offset..len_offset + 4].copy_from_slice(&64u32.to_be_bytes());
+        assert_eq!(Fdt::new(&data).err(), Some(FdtError::Truncated));
+    }
+
+    #[test]
+    fn bad_token() {
+        let data = DtbBuilder::default().begin("").token(0x7).end().build();
+        assert_eq!(Fdt::new(&data).err(), Some(FdtError::BadToken));
+
+        let data = DtbBuilder::default().begin("").build();
+        assert_eq!(Fdt::new(&data).err(), Some(FdtError::BadToken));
+
+        let data = DtbBuilder::default().begin("").end().end().build();
+        assert_eq!(Fdt::new(&data).err(), Some(FdtError::BadToken));
+
+        let mut builder = DtbBuilder::default();
+        for _ in 0..=FDT_MAX_DEPTH {
+            builder.begin("node");
+        }
+        for _ in 0..=FDT_MAX_DEPTH {
+            builder.end();
+        }
+        assert_eq!(Fdt::new(&builder.build()).err(), Some(FdtError::TooDeep));
+    }
+
+    #[test]
+    fn nested_cells() {
+        let data = DtbBuilder::default()
+            .begin("")
+            .prop_cells("#address-cells", &[2])
+            .prop_cells("#size-cells", &[2])
+            .begin("memory@40000000")
+            .prop("device_type", b"memory\0")
+            .prop_cells("reg", &[0, 0x4000_0000, 0, 0x4000_0000])
+            .end()
+            .begin("soc")
+            .prop_cells("#address-cells", &[1])
+            .prop_cells("#size-cells", &[1])
+            .token(FDT_NOP)
+            .begin("uart@9000000")
+            .prop("compatible", b"ns16550a\0arm,pl011\0")
+            .prop_cells("reg", &[0x900_0000, 0x1000, 0x900_1000, 0x1000])
+            .end()
+            .end()
+            .begin("cpus")
+            .prop_cells("#size-cells", &[0])
+            .begin("cpu@0")
+            .prop_cells("reg", &[0, 0x100])
+            .end()
+            .end()
+            .end()
+            .build();
+        let fdt = Fdt::new(&data).unwrap();
+
+        let names: Vec<_> = fdt.nodes().map(|node| (node.name, node.depth)).collect();
+        assert_eq!(
+            names,
+            [
+                ("", 0),
+                ("memory@40000000", 1),
+                ("soc", 1),
+                ("uart@9000000", 2),
+                ("cpus", 1),
+                ("cpu@0", 2),
+            ]
+        );
+
+        let memory = fdt.nodes().nth(1).unwrap();
+        assert_eq!(memory.base_name(), "memory");
+        assert_eq!(memory.property_str("device_type"), Some("memory"));
+        assert!(memory.reg().eq([(0x4000_0000, 0x4000_0000)]));
+
+        let uart = fdt.find_compatible(&["arm,pl011"]).unwrap();
+        assert_eq!(uart.name, "uart@9000000");
+        assert!(uart.reg().eq([(0x900_0000, 0x1000), (0x900_1000, 0x1000)]));
+
+        // `cpus` 只声明了 `#size-cells`，`#address-cells` 使用默认值
+        let cpu = fdt.nodes().last().unwrap();
+        assert!(cpu.reg().eq([(0x100, 0)]));
+    }
+
+    #[test]
+    fn memory_reservations() {
+        let data = DtbBuilder {
+            reservations: vec![(0x4000_0000, 0x1000), (0x8000_0000, 0x20_0000)],
+            ..Default::default()
+        }
+        .begin("")
+        .end()
+        .build();
+        let fdt = Fdt::new(&data).unwrap();
+        assert!(fdt
+            .memory_reservations()
+            .eq([(0x4000_0000, 0x1000), (0x8000_0000, 0x20_0000)]));
+
+        let data = simple_dtb();
+        assert_eq!(Fdt::new(&data).unwrap().memory_reservations().count(), 0);
+    }
+
+    #[test]
+    fn interrupt_cells_from_parent() {
+        let data = DtbBuilder::default()
+            .begin("")
+            .prop_cells("interrupt-parent", &[1])
+            .prop_cells("#interrupt-cells", &[1])
+            .begin("intc@8000000")
+            .prop_cells("phandle", &[1])
+            .prop_cells("#interrupt-cells", &[3])
+            .end()
+            .begin("timer")
+            .prop("compatible", b"arm,armv8-timer\0")
+            .prop_cells("interrupts", &[1, 13, 4, 1, 14, 4])
+            .end()
+            .begin("legacy")
+            .prop_cells("interrupt-parent", &[2])
+            .begin("intc")
+            .prop_cells("phandle", &[2])
+            .prop_cells("#interrupt-cells", &[2])
+            .prop_cells("interrupts", &[5, 6])
+            .end()
+            .end()
+            .end()
+            .build();
+        let fdt = Fdt::new(&data).unwrap();
+
+        let timer = fdt.find_compatible(&["arm,armv8-timer"]).unwrap();
+        assert_eq!(timer.interrupt_cells(), Some(3));
+        let cells: Vec<u8> = [1u32, 14, 4].iter().flat_map(|c| c.to_be_bytes()).collect();
+        assert_eq!(timer.interrupt(1), Some(&cells[..]));
+        assert_eq!(timer.interrupt(2), None);
+
+        let legacy = fdt.nodes().find(|node| node.name == "intc").unwrap();
+        assert_eq!(legacy.interrupt_cells(), Some(2));
+        assert_eq!(legacy.interrupt(0).map(<[u8]>::len), Some(8));
+
+        // 没有 `interrupt-parent` 时使用设备树中的父节点
+        let data = DtbBuilder::default()
+            .begin("")
+            .prop_cells("#interrupt-cells", &[2])
+            .begin("device")
+            .prop_cells("interrupts", &[7, 8])
+            .end()
+            .end()
+            .build();
+        let fdt = Fdt::new(&data).unwrap();
+        let device = fdt.nodes().nth(1).unwrap();
+        assert_eq!(device.interrupt_cells(), Some(2));
+        assert_eq!(fdt.nodes().next().unwrap().interrupt_cells(), None);
+    }
+}
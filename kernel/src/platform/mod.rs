@@ -1,3 +1,195 @@
 mod aarch64_qemu;
+pub mod fdt;
 
 pub use aarch64_qemu::*;
+
+use fdt::{Fdt, FdtError};
+use macros::boot_code;
+use spin::Mutex;
+
+use crate::{
+    arch::{PhysAddr, PhysAddrRange, RegionList},
+    config::MAX_NUM_NODES,
+};
+
+/// 最多记录的物理内存区域数量
+pub const MAX_NUM_MEM_REGIONS: usize = 16;
+/// 最多记录的保留内存区域数量
+pub const MAX_NUM_RESV_REGIONS: usize = 16;
+
+/// PSCI 调用方式，对应 DTB 中 psci 节点的 `method` 属性
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PsciMethod {
+    Smc,
+    Hvc,
+}
+
+/// 平台信息，启动时从 DTB 中获取，没有 DTB 时使用 QEMU virt 的默认值
+pub struct PlatformInfo {
+    /// 物理内存
+    pub memory: RegionList<MAX_NUM_MEM_REGIONS>,
+    /// `/memreserve/` 和 `/reserved-memory` 中的保留内存
+    pub reserved: RegionList<MAX_NUM_RESV_REGIONS>,
+    /// PL011 UART 地址
+    pub uart: PhysAddr,
+    /// GIC Distributor 地址
+    pub gic_dist: PhysAddr,
+    /// GIC CPU Interface 地址
+    pub gic_cpu: PhysAddr,
+    /// Generic Timer 中断号，参考 [DEFAULT_TIMER_IRQS]
+    pub timer_irqs: [usize; 4],
+    /// PSCI 调用方式
+    pub psci_method: PsciMethod,
+    /// 每个核 MPIDR 中的亲和性字段，按照 DTB `/cpus` 中的顺序排列，超过 [MAX_NUM_NODES] 的核会被忽略
+    pub cpus: [usize; MAX_NUM_NODES],
+    /// [PlatformInfo::cpus] 中有效的数量
+    pub num_cpus: usize,
+}
+
+impl PlatformInfo {
+    const fn new() -> Self {
+        let mut memory = RegionList::new();
+        memory.push(DEFAULT_MEMORY);
+        Self {
+            memory,
+            reserved: RegionList::new(),
+            uart: DEFAULT_UART_PADDR,
+            gic_dist: DEFAULT_GIC_DIST_PADDR,
+            gic_cpu: DEFAULT_GIC_CPU_PADDR,
+            timer_irqs: DEFAULT_TIMER_IRQS,
+            psci_method: PsciMethod::Smc,
+            cpus: default_cpus(),
+            num_cpus: MAX_NUM_NODES,
+        }
+    }
+
+    /// 内核使用的 non-secure 物理计时器中断
+    pub const fn kernel_timer_irq(&self) -> usize {
+        self.timer_irqs[1]
+    }
+
+    /// 从 DTB 中读取平台信息，DTB 中没有描述的设备保持默认值
+    #[boot_code]
+    fn parse_fdt(&mut self, fdt: &Fdt) {
+        let range = |(addr, size): (u64, u64)| {
+            PhysAddrRange::new(pa!(addr), pa!(addr.saturating_add(size)))
+        };
+
+        let mut memory = RegionList::new();
+        for node in fdt.nodes().filter(|node| node.depth == 1) {
+            if node.property_str("device_type") == Some("memory") {
+                node.reg().for_each(|reg| {
+                    if !memory.push(range(reg)) {
+                        log::warn!("too many memory regions, ignore {:#x?}", reg);
+                    }
+                });
+            }
+        }
+        if !memory.is_empty() {
+            self.memory = memory;
+        }
+
+        let mut in_reserved_memory = false;
+        let reserved_nodes = fdt.nodes().filter(|node| {
+            if node.depth == 1 {
+                in_reserved_memory = node.base_name() == "reserved-memory";
+            }
+            in_reserved_memory && node.depth == 2
+        });
+        fdt.memory_reservations()
+            .chain(reserved_nodes.flat_map(|node| node.reg()))
+            .for_each(|reg| {
+                if !self.reserved.push(range(reg)) {
+                    log::warn!("too many reserved regions, ignore {:#x?}", reg);
+                }
+            });
+
+        if let Some((addr, _)) = fdt
+            .find_compatible(&["arm,pl011"])
+            .and_then(|node| node.reg().next())
+        {
+            self.uart = pa!(addr);
+        }
+
+        if let Some(node) =
+            fdt.find_compatible(&["arm,cortex-a15-gic", "arm,gic-400", "arm,cortex-a9-gic"])
+        {
+            let mut reg = node.reg();
+            if let (Some((dist, _)), Some((cpu, _))) = (reg.next(), reg.next()) {
+                self.gic_dist = pa!(dist);
+                self.gic_cpu = pa!(cpu);
+            }
+        }
+
+        if let Some(node) = fdt.find_compatible(&["arm,armv8-timer", "arm,armv7-timer"]) {
+            for (idx, irq) in self.timer_irqs.iter_mut().enumerate() {
+                if let Some(cells) = node.interrupt(idx).filter(|cells| cells.len() == 12) {
+                    let kind = u32::from_be_bytes(cells[0..4].try_into().unwrap());
+                    let num = u32::from_be_bytes(cells[4..8].try_into().unwrap()) as usize;
+                    // GIC 中断说明符：0 为 SPI，从 32 开始；1 为 PPI，从 16 开始
+                    *irq = if kind == 0 { num + 32 } else { num + 16 };
+                }
+            }
+        }
+
+        let mut num_cpus = 0;
+        for node in fdt.nodes().filter(|node| node.depth == 2) {
+            if node.property_str("device_type") != Some("cpu") {
+                continue;
+            }
+            if let Some((mpidr, _)) = node.reg().next() {
+                if num_cpus == MAX_NUM_NODES {
+                    log::warn!(
+                        "MAX_NUM_NODES is {}, ignore cpu {:#x}",
+                        MAX_NUM_NODES,
+                        mpidr
+                    );
+                    continue;
+                }
+                self.cpus[num_cpus] = mpidr as usize;
+                num_cpus += 1;
+            }
+        }
+        if num_cpus != 0 {
+            self.num_cpus = num_cpus;
+        }
+
+        if let Some(method) = fdt
+            .find_compatible(&["arm,psci-1.0", "arm,psci-0.2", "arm,psci"])
+            .and_then(|node| node.property_str("method"))
+        {
+            self.psci_method = match method {
+                "hvc" => PsciMethod::Hvc,
+                _ => PsciMethod::Smc,
+            };
+        }
+    }
+}
+
+/// QEMU virt 中第 i 个核的 MPIDR 亲和性字段为 i
+const fn default_cpus() -> [usize; MAX_NUM_NODES] {
+    let mut cpus = [0; MAX_NUM_NODES];
+    let mut i = 0;
+    while i < MAX_NUM_NODES {
+        cpus[i] = i;
+        i += 1;
+    }
+    cpus
+}
+
+/// 当前平台信息
+pub static PLATFORM: Mutex<PlatformInfo> = Mutex::new(PlatformInfo::new());
+
+/// 根据 kernel loader 传入的 DTB 初始化 [PLATFORM]
+///
+/// 需要在映射内核窗口之后调用，DTB 通过内核窗口访问
+#[boot_code]
+pub fn init(dtb_p_reg: Option<PhysAddrRange>) -> Result<(), FdtError> {
+    let mut platform = PLATFORM.lock();
+    if let Some(dtb) = dtb_p_reg {
+        let fdt = unsafe { Fdt::from_ptr(dtb.start.vaddr().raw() as *const u8, dtb.size())? };
+        platform.parse_fdt(&fdt);
+    }
+    crate::driver::set_psci_method(platform.psci_method);
+    Ok(())
+}
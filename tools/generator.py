@@ -19,27 +19,78 @@ def derive_str(derives) -> str:
 
 
 USIZE_WIDTH = 64
+# 规范地址的有效位数，与 getStructure 中的 BF_CANONICAL_RANGE 一致
+CANONICAL_RANGE = 48
 
+# 参数顺序 (usize 数量，usize 数量，usize 数量)
 NEW_FUNC_TEMPLATE = """
     pub const fn empty() -> Self {
         Self([0; %d])
     }
+
+    pub const fn from_raw(raw: [usize; %d]) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(&self) -> [usize; %d] {
+        self.0
+    }
 """
 
-# 参数顺序 (field名称，返回类型, idx 顺序，MASK, RightShiftSize)
+# 参数顺序 (field名称，返回类型, 取值表达式)
 GET_FUNC_TEMPLATE = """
     pub const fn get_%s(&self) -> %s {
-        ((self.0[%d] & 0x%X) >> %d) as _
+        %s
     }
 """
 
-# 参数顺序 (field名称，参数类型, idx 顺序，idx 顺序，MASK，LeftShiftSize)
+# 参数顺序 (field名称，参数类型, idx 顺序，idx 顺序，MASK，写入表达式)
 SET_FUNC_TEMPLATE = """
     pub const fn set_%s(&mut self, value: %s) {
-        self.0[%d] = self.0[%d] & !0x%X | ((value as usize) << %d)
+        self.0[%d] = self.0[%d] & !0x%X | (%s & 0x%X)
+    }
+"""
+
+# 参数顺序 (field名称，参数类型, idx 顺序)
+SET_WORD_FUNC_TEMPLATE = """
+    pub const fn set_%s(&mut self, value: %s) {
+        self.0[%d] = value
     }
 """
 
+# tagged_union 中根据 tag 转换为具体类型的分支
+# 参数顺序 (tag 值，union 名称，block 名称，block 名称)
+VIEW_ARM_TEMPLATE = """
+            %d => %sView::%s(%s::from_raw(self.0)),"""
+
+# 参数顺序 (union 名称，block 名称，union 名称，block 名称)
+FROM_TEMPLATE = """
+impl From<%s> for %s {
+    fn from(value: %s) -> Self {
+        Self(value.raw())
+    }
+}
+"""
+
+# field_high 保存的是地址的高位，读取时需要按照规范地址进行符号扩展
+# 参数顺序 (取值表达式，符号位，扩展位)
+SIGN_EXTEND_TEMPLATE = """let value = %s;
+        if value & 0x%X != 0 {
+            value | 0x%X
+        } else {
+            value
+        }"""
+
+
+def shift_expr(expr, op, shift) -> str:
+    if shift == 0:
+        return expr
+    return "(%s %s %d)" % (expr, op, shift)
+
+
+def rustfmt(dest_file):
+    subprocess.run(["rustfmt", "--edition", "2021", dest_file])
+
 
 def getStructure(source_file):
     result = subprocess.run(
@@ -47,7 +98,7 @@ def getStructure(source_file):
             "gcc",
             "-E",
             "-P",
-            "-DBF_CANONICAL_RANGE=48",
+            "-DBF_CANONICAL_RANGE=%d" % CANONICAL_RANGE,
             "-x",
             "c",
             source_file,
@@ -101,6 +152,8 @@ def trans_data(source_file):
     tree = parser.parse(getStructure(source_file))
     tree = BFTransformer().transform(tree)
     tagged = {}
+    # block 名称 -> (usize 数量，{field 名称: (idx 顺序，shift，MASK)})
+    blocks = {}
     for x in tree.children:
         if x['type'] != 'tagged_union':
             continue
@@ -115,70 +168,142 @@ def trans_data(source_file):
             declare = derive_str(["Debug", "Clone", "Copy"])
             declare += "pub struct %s([usize; %d]); \n" % (top_name, width)
             declare += "impl %s { " % (top_name)
-            declare += NEW_FUNC_TEMPLATE % (width)
+            declare += NEW_FUNC_TEMPLATE % (width, width, width)
+
+            fields = {}
+            blocks[i["name"]] = (width, fields)
 
             idx = 0
             for field in reversed(i["fields"]):
                 field_type = field["type"]
                 arg_type = "usize"
 
-                bit_mask = 0
+                # padding 不需要产生任何代码，简单跳过就行了
+                if field_type == "padding":
+                    idx += field["bits"]
+                    continue
+
+                word = idx // USIZE_WIDTH
                 shift = idx % USIZE_WIDTH
+                bit_mask = ((1 << field["bits"]) - 1) << shift
+                field_name = underscore(field["name"])
+                fields[field["name"]] = (word, shift, bit_mask)
+                idx += field["bits"]
+
+                # 占满整个 usize 的 field 直接读写即可
+                if field["bits"] == USIZE_WIDTH:
+                    declare += GET_FUNC_TEMPLATE % (field_name, arg_type, "self.0[%d]" % word)
+                    declare += SET_WORD_FUNC_TEMPLATE % (field_name, arg_type, word)
+                    continue
 
                 # field 正常处理，获取整个值，然后 MASK 特定位就行了
                 # TODO: 将仅有一个位的数据更换为 bool
                 # TODO: 如果 field 的名称是当前列表中定义的名称需要特殊处理 也就是处理 tagged
                 if field_type == "field":
-                    bit_mask = (1 << field["bits"]) - 1
+                    get_expr = "self.0[%d] & 0x%X" % (word, bit_mask)
+                    if shift != 0:
+                        get_expr = "(%s) >> %d" % (get_expr, shift)
+                    set_expr = shift_expr("value", "<<", shift)
                 # 处理 field_high 的情况：
                 #     padding 16
                 #     field_high mdbNext 46
                 #     field mdbRevocable 1
                 #     field mdbFirstBadged 1
-                # 这种情况下， field_high 会其实包含 48 位，但是不携带低两位的信息
-                # 所以使用计算后的 MASK (1 << (46 + 2)) - (1 << 2) = 0xFFFF_FFFF_FFFC
-                # 真正的 mdbNext 从整个 usize 中获取，然后 &MASK 得到
+                # field_high 只保存值的高位，值的最高位与规范地址的最高位 (CANONICAL_RANGE - 1) 对齐，
+                # 上面的 mdbNext 恰好对齐，不需要移位，MASK 为 (1 << (46 + 2)) - (1 << 2) = 0xFFFF_FFFF_FFFC
+                # 其他情况需要根据对齐的差值移位，例如 field_high capCNodePtr 47 位于 [0, 47)，读取时需要左移 1 位
+                # 读取后按照规范地址进行符号扩展
                 elif field_type == "field_high":
-                    high = field["bits"] + shift
-                    assert high <= 64
-                    bit_mask = (1 << high) - (1 << shift)
-                    shift = 0
-                # padding 不需要产生任何代码，简单跳过就行了
-                elif field_type == "padding":
-                    idx += field["bits"]
-                    continue
+                    delta = shift + field["bits"] - CANONICAL_RANGE
+                    raw_expr = "self.0[%d] & 0x%X" % (word, bit_mask)
+                    if delta > 0:
+                        raw_expr = "(%s) >> %d" % (raw_expr, delta)
+                        set_expr = shift_expr("value", "<<", delta)
+                    elif delta < 0:
+                        raw_expr = "(%s) << %d" % (raw_expr, -delta)
+                        set_expr = shift_expr("value", ">>", -delta)
+                    else:
+                        set_expr = "value"
+                    get_expr = SIGN_EXTEND_TEMPLATE % (
+                        raw_expr,
+                        1 << (CANONICAL_RANGE - 1),
+                        ((1 << USIZE_WIDTH) - 1) ^ ((1 << CANONICAL_RANGE) - 1),
+                    )
                 else:
                     raise "无法处理类型 %s" % (field["type"])
 
-                field_name = underscore(field["name"])
-                declare += GET_FUNC_TEMPLATE % (
-                    field_name,
-                    arg_type,
-                    idx / USIZE_WIDTH,
-                    bit_mask,
-                    shift,
-                )
+                declare += GET_FUNC_TEMPLATE % (field_name, arg_type, get_expr)
                 declare += SET_FUNC_TEMPLATE % (
                     field_name,
                     arg_type,
-                    idx / USIZE_WIDTH,
-                    idx / USIZE_WIDTH,
+                    word,
+                    word,
+                    bit_mask,
+                    set_expr,
                     bit_mask,
-                    shift,
                 )
-                idx += field["bits"]
         else:
-            # TODO: 为 tagged_union 实现类型
+            # tagged_union 生成 tag 对应的枚举，名称取自 tag 字段，例如 capType -> CapType
+            # 同时生成 union 类型本身，以及可以 match 的 View 枚举，例如 cap -> Cap, CapView
+            tag_type = camelize(i["tag_field"])
+            tag_field = underscore(i["tag_field"])
             top_name = camelize(i["name"])
 
             declare = "#[repr(usize)]\n"
-            declare += derive_str(["Debug", "Clone", "Copy"])
-            declare += "pub enum %s { \n" % (top_name)
+            declare += derive_str(["Debug", "Clone", "Copy", "PartialEq", "Eq"])
+            declare += "pub enum %s { \n" % (tag_type)
             for tag in i["tags"]:
                 declare += "    %s = %d, \n" % (
                     camelize(tag["name"]),
                     tag["value"],
                 )
+            declare += "}\n\n"
+
+            # 所有成员的大小和 tag 的位置都必须相同
+            width, fields = blocks[i["tags"][0]["name"]]
+            word, shift, bit_mask = fields[i["tag_field"]]
+            for tag in i["tags"]:
+                assert blocks[tag["name"]][0] == width, "%s 的大小不一致" % tag["name"]
+                assert blocks[tag["name"]][1][i["tag_field"]] == (word, shift, bit_mask), (
+                    "%s 的 tag 位置不一致" % tag["name"]
+                )
+
+            get_expr = "self.0[%d] & 0x%X" % (word, bit_mask)
+            if shift != 0:
+                get_expr = "(%s) >> %d" % (get_expr, shift)
+
+            declare += "#[repr(C)]\n"
+            declare += derive_str(["Debug", "Clone", "Copy"])
+            declare += "pub struct %s([usize; %d]); \n" % (top_name, width)
+            declare += "impl %s { " % (top_name)
+            declare += NEW_FUNC_TEMPLATE % (width, width, width)
+            declare += GET_FUNC_TEMPLATE % (tag_field, "usize", get_expr)
+            declare += """
+    pub const fn view(&self) -> %sView {
+        match self.get_%s() {""" % (top_name, tag_field)
+            for tag in i["tags"]:
+                name = camelize(tag["name"])
+                declare += VIEW_ARM_TEMPLATE % (tag["value"], top_name, name, name)
+            declare += """
+            _ => panic!("invalid %s"),
+        }
+    }
+}
+
+""" % (tag_field)
+
+            declare += derive_str(["Debug", "Clone", "Copy"])
+            declare += "pub enum %sView { \n" % (top_name)
+            for tag in i["tags"]:
+                name = camelize(tag["name"])
+                declare += "    %s(%s), \n" % (name, name)
+            declare += "}\n"
+
+            for tag in i["tags"]:
+                name = camelize(tag["name"])
+                declare += FROM_TEMPLATE % (name, top_name, name)
+            # 与 block 共用末尾的 "}"，这里去掉最后一个 From 的 "}"
+            declare = declare.rstrip()[:-1]
 
         declare += "}"
         all_data += declare + "\n\n"
@@ -193,3 +318,4 @@ if __name__ == "__main__":
     dest_file = sys.argv[2]
     data = trans_data(source_file)
     open(dest_file, "w+").write(data)
+    rustfmt(dest_file)